@@ -1,13 +1,201 @@
-use std::alloc::{GlobalAlloc, Layout, System};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub mod mark;
+pub mod mass;
+
+/// Number of live allocations.
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of live bytes.
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Largest number of live bytes ever observed.
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Maximum number of live bytes, or 0 if unbounded.
+static CAP: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of live bytes above which the loom is under pressure, or 0 if unbounded.
+static HIGH_WATER: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of live bytes when pressure was last relieved, or 0 if it hasn't been.
+static RELIEVED: AtomicUsize = AtomicUsize::new(0);
+
+/// Once pressure is relieved as far as it can be, the loom isn't under pressure again until it
+/// grows by this fraction of the high-water mark, so that freeing what little can be freed isn't
+/// retried on every allocation.
+const HYSTERESIS: usize = 16;
 
 pub struct Loom;
 
+/// A snapshot of the loom's allocation counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub allocs: usize,
+    pub bytes: usize,
+    pub peak: usize,
+}
+
+impl Stats {
+    /// Get the allocations made since `base` was taken.
+    pub fn since(&self, base: &Self) -> Self {
+        Self {
+            allocs: self.allocs.saturating_sub(base.allocs),
+            bytes: self.bytes.saturating_sub(base.bytes),
+            peak: self.peak,
+        }
+    }
+}
+
+impl Loom {
+    /// Get the current allocation counters.
+    pub fn stats() -> Stats {
+        Stats {
+            allocs: ALLOCS.load(Ordering::Relaxed),
+            bytes: BYTES.load(Ordering::Relaxed),
+            peak: PEAK.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Get the maximum number of live bytes, if any.
+    pub fn cap() -> Option<usize> {
+        match CAP.load(Ordering::Relaxed) {
+            0 => None,
+            cap => Some(cap),
+        }
+    }
+
+    /// Limit the number of live bytes. Allocations that would exceed the cap fail.
+    pub fn set_cap(cap: Option<usize>) {
+        CAP.store(cap.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Set the number of live bytes above which the loom is under pressure.
+    pub fn set_high_water(high_water: Option<usize>) {
+        HIGH_WATER.store(high_water.unwrap_or(0), Ordering::Relaxed);
+        RELIEVED.store(0, Ordering::Relaxed);
+    }
+
+    /// Determine if the number of live bytes is above the high-water mark, in which case the
+    /// runtime should free what it can before the cap is reached. If the loom was still above the
+    /// mark when pressure was last relieved, it's under pressure only once it has grown by a
+    /// sixteenth of the mark since.
+    pub fn pressure() -> bool {
+        let high_water = match HIGH_WATER.load(Ordering::Relaxed) {
+            0 => return false,
+            high_water => high_water,
+        };
+        let floor = match RELIEVED.load(Ordering::Relaxed) {
+            relieved if relieved >= high_water => relieved + high_water / HYSTERESIS,
+            _ => high_water,
+        };
+        BYTES.load(Ordering::Relaxed) >= floor
+    }
+
+    /// Note that the runtime has freed what it can to relieve pressure.
+    pub fn relieved() {
+        RELIEVED.store(BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// Determine if `size` more bytes fit under the cap.
+    fn fits(size: usize) -> bool {
+        match CAP.load(Ordering::Relaxed) {
+            0 => true,
+            cap => BYTES.load(Ordering::Relaxed).saturating_add(size) <= cap,
+        }
+    }
+
+    fn grow(size: usize) {
+        let bytes = BYTES.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    fn shrink(size: usize) {
+        BYTES.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
 unsafe impl GlobalAlloc for Loom {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        System.alloc(layout)
+        if !Self::fits(layout.size()) {
+            return std::ptr::null_mut();
+        }
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+            Self::grow(layout.size());
+        }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        System.dealloc(ptr, layout)
+        System.dealloc(ptr, layout);
+        ALLOCS.fetch_sub(1, Ordering::Relaxed);
+        Self::shrink(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() && !Self::fits(new_size - layout.size()) {
+            return std::ptr::null_mut();
+        }
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            Self::shrink(layout.size());
+            Self::grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cap() {
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        let base = Loom::stats().bytes;
+
+        // An allocation that fits under the cap succeeds and one that doesn't fails.
+        unsafe {
+            Loom::set_cap(Some(base + 1536));
+            let ptr = Loom.alloc(layout);
+            assert!(!ptr.is_null());
+            assert!(Loom.alloc(layout).is_null());
+            assert!(Loom.realloc(ptr, layout, 2048).is_null());
+            Loom.dealloc(ptr, layout);
+            Loom::set_cap(None);
+        }
+
+        // The loom is under pressure only while above the high-water mark.
+        unsafe {
+            Loom::set_high_water(Some(base + 512));
+            assert!(!Loom::pressure());
+            let ptr = Loom.alloc(layout);
+            assert!(Loom::pressure());
+            Loom.dealloc(ptr, layout);
+            assert!(!Loom::pressure());
+            Loom::set_high_water(None);
+        }
+
+        // Once relieved above the high-water mark, the loom is under pressure again only after
+        // growing by a sixteenth of the mark.
+        unsafe {
+            Loom::set_high_water(Some(base + 512));
+            let ptr = Loom.alloc(layout);
+            Loom::relieved();
+            assert!(!Loom::pressure());
+            let small = Loom.alloc(Layout::from_size_align(8, 8).unwrap());
+            assert!(!Loom::pressure());
+            let big = Loom.alloc(layout);
+            assert!(Loom::pressure());
+            Loom.dealloc(big, layout);
+            Loom.dealloc(small, Layout::from_size_align(8, 8).unwrap());
+            Loom.dealloc(ptr, layout);
+            Loom::set_high_water(None);
+        }
     }
 }
@@ -0,0 +1,170 @@
+use crate::Stats;
+use std::collections::HashSet;
+
+/// An owner of loom allocations that can enumerate them during the mark phase of a mark-and-sweep
+/// pass.
+///
+/// Implementors call [`Marker::mark`] once per reference they hold to a loom allocation and only
+/// descend into the allocation when `mark` returns `true`. Allocations are owned rather than
+/// reference counted, so there are no counts to check, only what's reachable.
+pub trait Mark {
+    fn mark(&self, marker: &mut Marker);
+}
+
+/// Loom usage attributed to a single root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Root {
+    pub name: String,
+    pub allocs: usize,
+    pub bytes: usize,
+}
+
+/// The result of a mark-and-sweep pass.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// Usage attributed to each root, in the order the roots were marked.
+    pub roots: Vec<Root>,
+    /// Allocations reachable from the roots.
+    pub reachable: Stats,
+    /// Allocations live in the loom when the pass began.
+    pub live: Stats,
+    /// Allocations that are live but unreachable from any root: the difference between `live`
+    /// and `reachable`, which is leaked or held by something that wasn't marked as a root.
+    pub leaked: Stats,
+}
+
+impl Report {
+    /// Determine if everything live was reachable from the roots.
+    pub fn is_clean(&self) -> bool {
+        0 == self.leaked.allocs
+    }
+}
+
+/// The state of a mark phase.
+#[derive(Default)]
+pub struct Marker {
+    /// Addresses of the allocations marked.
+    marked: HashSet<usize>,
+    reachable: Stats,
+    roots: Vec<Root>,
+}
+
+impl Marker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a reference to the `size`-byte allocation at `ptr`.
+    ///
+    /// Returns `true` if this is the first reference to the allocation, in which case the caller
+    /// should mark the allocation's own references.
+    pub fn mark<T>(&mut self, ptr: *const T, size: usize) -> bool {
+        if !self.marked.insert(ptr as usize) {
+            return false;
+        }
+        self.reachable.allocs += 1;
+        self.reachable.bytes += size;
+        if let Some(root) = self.roots.last_mut() {
+            root.allocs += 1;
+            root.bytes += size;
+        }
+        true
+    }
+
+    /// Mark everything reachable from a named root.
+    pub fn root(&mut self, name: &str, root: &dyn Mark) {
+        self.roots.push(Root {
+            name: name.to_string(),
+            allocs: 0,
+            bytes: 0,
+        });
+        root.mark(self);
+    }
+
+    /// Compare the marked allocations against the loom's live allocations.
+    ///
+    /// `live` must be taken before the marker is created so that the marker's own allocations
+    /// aren't counted.
+    pub fn sweep(self, live: Stats) -> Report {
+        let leaked = live.since(&self.reachable);
+        Report {
+            roots: self.roots,
+            reachable: self.reachable,
+            live,
+            leaked,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uniquely owned allocations.
+    struct Owned(Vec<String>);
+
+    impl Mark for Owned {
+        fn mark(&self, marker: &mut Marker) {
+            for s in &self.0 {
+                marker.mark(s.as_ptr(), s.capacity());
+            }
+        }
+    }
+
+    fn owned(strs: &[&str]) -> Owned {
+        Owned(strs.iter().map(|s| String::from(*s)).collect())
+    }
+
+    #[test]
+    fn sweep() {
+        // Everything live is reachable.
+        {
+            let owned = owned(&["01234567", "89abcdef", "ghijklmn"]);
+            let live = Stats {
+                allocs: 3,
+                bytes: 24,
+                peak: 24,
+            };
+            let mut marker = Marker::new();
+            marker.root("owned", &owned);
+            marker.root("again", &owned);
+            let report = marker.sweep(live);
+            assert!(report.is_clean());
+            assert_eq!(3, report.reachable.allocs);
+            assert_eq!(24, report.reachable.bytes);
+
+            // Allocations already reached from an earlier root aren't counted again.
+            assert_eq!(
+                vec![
+                    Root {
+                        name: "owned".to_string(),
+                        allocs: 3,
+                        bytes: 24,
+                    },
+                    Root {
+                        name: "again".to_string(),
+                        allocs: 0,
+                        bytes: 0,
+                    }
+                ],
+                report.roots
+            );
+        }
+
+        // One live allocation is unreachable.
+        {
+            let owned = owned(&["01234567", "89abcdef"]);
+            let live = Stats {
+                allocs: 3,
+                bytes: 24,
+                peak: 24,
+            };
+            let mut marker = Marker::new();
+            marker.root("owned", &owned);
+            let report = marker.sweep(live);
+            assert!(!report.is_clean());
+            assert_eq!(1, report.leaked.allocs);
+            assert_eq!(8, report.leaked.bytes);
+        }
+    }
+}
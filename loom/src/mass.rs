@@ -0,0 +1,106 @@
+use crate::{
+    mark::{Mark, Marker, Root},
+    Loom, Stats,
+};
+use std::fmt;
+
+/// Loom usage attributed to named roots, in the spirit of Arvo's `|mass`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mass {
+    /// Usage attributed to each root, in the order given.
+    pub roots: Vec<Root>,
+    /// Live usage not attributable to any root, such as I/O buffers.
+    pub other: Stats,
+    /// Total live usage.
+    pub live: Stats,
+    /// Maximum number of live bytes, if any.
+    pub cap: Option<usize>,
+}
+
+impl Mass {
+    /// Measure the loom, attributing usage to each of `roots`.
+    ///
+    /// An allocation reachable from several roots is attributed to the first.
+    pub fn measure(roots: &[(&str, &dyn Mark)]) -> Self {
+        Self::measure_with(Loom::stats(), roots)
+    }
+
+    /// Measure the loom as of `live`, attributing usage to each of `roots`.
+    pub fn measure_with(live: Stats, roots: &[(&str, &dyn Mark)]) -> Self {
+        let mut marker = Marker::new();
+        for (name, root) in roots {
+            marker.root(name, *root);
+        }
+        let report = marker.sweep(live);
+        Self {
+            roots: report.roots,
+            other: report.leaked,
+            live,
+            cap: Loom::cap(),
+        }
+    }
+}
+
+impl fmt::Display for Mass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for root in &self.roots {
+            writeln!(f, "{}: {}", root.name, Size(root.bytes))?;
+        }
+        writeln!(f, "other: {}", Size(self.other.bytes))?;
+        write!(f, "total: {}", Size(self.live.bytes))?;
+        if let Some(cap) = self.cap {
+            write!(f, " of {}", Size(cap))?;
+        }
+        Ok(())
+    }
+}
+
+/// A byte count formatted in the largest binary unit it fills.
+struct Size(usize);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 >= 1 << (10 * (unit + 1)) {
+            unit += 1;
+        }
+        if 0 == unit {
+            write!(f, "{} B", self.0)
+        } else {
+            let val = self.0 as f64 / (1u64 << (10 * unit)) as f64;
+            write!(f, "{:.1} {}", val, UNITS[unit])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Strings(Vec<String>);
+
+    impl Mark for Strings {
+        fn mark(&self, marker: &mut Marker) {
+            for s in &self.0 {
+                marker.mark(s.as_ptr(), s.capacity());
+            }
+        }
+    }
+
+    #[test]
+    fn measure() {
+        let kernel = Strings(vec!["a".repeat(2048), "b".repeat(1024)]);
+        let cache = Strings(vec!["c".repeat(512)]);
+        let live = Stats {
+            allocs: 4,
+            bytes: 2048 + 1024 + 512 + 100,
+            peak: 0,
+        };
+        let mass = Mass::measure_with(live, &[("kernel", &kernel), ("cache", &cache)]);
+        assert_eq!(3072, mass.roots[0].bytes);
+        assert_eq!(512, mass.roots[1].bytes);
+        assert_eq!(100, mass.other.bytes);
+        assert!(format!("{}", mass).starts_with("kernel: 3.0 KiB\ncache: 512 B\nother: 100 B"));
+    }
+}
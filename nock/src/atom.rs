@@ -5,6 +5,82 @@ pub enum Atom {
     Indirect(Vec<u64>),
 }
 
+impl Atom {
+    /// Create an atom from little-endian 64-bit limbs.
+    pub fn from_limbs(mut limbs: Vec<u64>) -> Self {
+        while limbs.len() > 1 && 0 == limbs[limbs.len() - 1] {
+            limbs.pop();
+        }
+        match limbs.len() {
+            0 => Atom::Direct(0),
+            1 => Atom::Direct(limbs[0]),
+            _ => Atom::Indirect(limbs),
+        }
+    }
+
+    /// Create an atom from a little-endian byte buffer.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::from_limbs(
+            bytes
+                .chunks(8)
+                .map(|chunk| {
+                    let mut limb = [0; 8];
+                    limb[..chunk.len()].copy_from_slice(chunk);
+                    u64::from_le_bytes(limb)
+                })
+                .collect(),
+        )
+    }
+
+    /// Get an atom's little-endian 64-bit limbs.
+    pub fn limbs(&self) -> &[u64] {
+        match self {
+            Atom::Direct(v) => std::slice::from_ref(v),
+            Atom::Indirect(v) => v,
+        }
+    }
+
+    /// Convert an atom into a little-endian byte buffer with no trailing zeros.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.limbs().iter().flat_map(|l| l.to_le_bytes()).collect();
+        while let Some(0) = bytes.last() {
+            bytes.pop();
+        }
+        bytes
+    }
+
+    /// Get the number of significant bits in an atom.
+    pub fn bits(&self) -> u64 {
+        let limbs = self.limbs();
+        match limbs.iter().rposition(|l| 0 != *l) {
+            Some(i) => 64 * i as u64 + u64::from(64 - limbs[i].leading_zeros()),
+            None => 0,
+        }
+    }
+
+    /// Get the bit of an atom at a given index, where index 0 is the least significant bit.
+    pub fn bit(&self, idx: u64) -> bool {
+        match self.limbs().get((idx / 64) as usize) {
+            Some(limb) => 0 != (limb >> (idx % 64)) & 1,
+            None => false,
+        }
+    }
+}
+
+/// Atom from u64.
+impl From<u64> for Atom {
+    fn from(val: u64) -> Self {
+        Atom::Direct(val)
+    }
+}
+
+/// Atom from a string, i.e. a cord.
+impl From<&str> for Atom {
+    fn from(val: &str) -> Self {
+        Self::from_bytes(val.as_bytes())
+    }
+}
+
 /// Create an atom.
 #[macro_export]
 macro_rules! a {
@@ -25,6 +101,8 @@ macro_rules! av {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn clone() {
         // Clone 777.
@@ -33,4 +111,42 @@ mod tests {
             assert_eq!(a, a.clone());
         }
     }
+
+    #[test]
+    fn bytes() {
+        // 0 <-> []
+        {
+            assert_eq!(Atom::Direct(0), Atom::from_bytes(&[]));
+            assert!(Atom::Direct(0).to_bytes().is_empty());
+        }
+
+        // 0x1_0000 <-> [0 0 1]
+        {
+            assert_eq!(Atom::Direct(0x1_0000), Atom::from_bytes(&[0, 0, 1]));
+            assert_eq!(vec![0, 0, 1], Atom::Direct(0x1_0000).to_bytes());
+        }
+
+        // 2^64 <-> [0 0 0 0 0 0 0 0 1]
+        {
+            let a = Atom::from_bytes(&[0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0]);
+            assert_eq!(Atom::Indirect(vec![0, 1]), a);
+            assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 0, 1], a.to_bytes());
+        }
+
+        // %pill
+        {
+            assert_eq!(Atom::Direct(0x6c6c_6970), Atom::from("pill"));
+        }
+    }
+
+    #[test]
+    fn bits() {
+        assert_eq!(0, Atom::Direct(0).bits());
+        assert_eq!(1, Atom::Direct(1).bits());
+        assert_eq!(8, Atom::Direct(0xff).bits());
+        assert_eq!(65, Atom::Indirect(vec![0, 1]).bits());
+        assert!(Atom::Indirect(vec![0, 1]).bit(64));
+        assert!(!Atom::Indirect(vec![0, 1]).bit(63));
+        assert!(!Atom::Direct(1).bit(1000));
+    }
 }
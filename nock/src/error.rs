@@ -7,6 +7,7 @@ pub enum Error {
     UnexpectedAtom(String, u64),
     UnexpectedIndirectAtom(String, u64),
     UnexpectedCell(String, u64),
+    MalformedJam(String, u64),
 }
 
 /// Create instance of Error::BadLiteral.
@@ -41,6 +42,14 @@ macro_rules! unexpected_cell {
     };
 }
 
+/// Create instance of Error::MalformedJam.
+#[macro_export]
+macro_rules! malformed_jam {
+    ($msg:expr, $bit:expr) => {
+        crate::error::Error::MalformedJam($msg.to_string(), $bit)
+    };
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -68,6 +77,9 @@ impl fmt::Display for Error {
                     axis, expr
                 )
             }
+            Error::MalformedJam(msg, bit) => {
+                write!(f, "encountered malformed jam at bit {}: {}", bit, msg)
+            }
         }
     }
 }
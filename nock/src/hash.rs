@@ -0,0 +1,151 @@
+use crate::{atom::Atom, cell::Cell, noun::Noun};
+use std::collections::HashMap;
+
+/// Murmur3 hash.
+///
+/// This is Hoon's `+mug`: atoms are hashed over their bytes with seed `0xcafebabe` and cells over
+/// the concatenation of their head and tail mugs with seed `0xdeadbeef`.
+pub trait Mug {
+    fn mug(&self) -> u32;
+}
+
+impl Mug for Atom {
+    fn mug(&self) -> u32 {
+        mum(0xcafe_babe, 0x7fff, &self.to_bytes())
+    }
+}
+
+impl Mug for Cell {
+    fn mug(&self) -> u32 {
+        let head = mugs(&self.head)[&(&*self.head as *const Noun)];
+        let tail = mugs(&self.tail)[&(&*self.tail as *const Noun)];
+        mug_both(head, tail)
+    }
+}
+
+impl Mug for Noun {
+    fn mug(&self) -> u32 {
+        mugs(self)[&(self as *const Noun)]
+    }
+}
+
+/// Compute the mug of every subtree of a noun, keyed by the subtree's address.
+pub(crate) fn mugs(noun: &Noun) -> HashMap<*const Noun, u32> {
+    let mut memo = HashMap::new();
+    let mut stack = vec![(noun, false)];
+    while let Some((noun, expanded)) = stack.pop() {
+        match noun {
+            Noun::Atom(a) => {
+                memo.insert(noun as *const Noun, a.mug());
+            }
+            Noun::Cell(c) if expanded => {
+                let head = memo[&(&*c.head as *const Noun)];
+                let tail = memo[&(&*c.tail as *const Noun)];
+                memo.insert(noun as *const Noun, mug_both(head, tail));
+            }
+            Noun::Cell(c) => {
+                stack.push((noun, true));
+                stack.push((&c.tail, false));
+                stack.push((&c.head, false));
+            }
+        }
+    }
+    memo
+}
+
+/// Mug a cell given the mugs of its head and tail.
+fn mug_both(head: u32, tail: u32) -> u32 {
+    let both = u64::from(head) | (u64::from(tail) << 32);
+    let bytes = both.to_le_bytes();
+    let len = 8 - (both.leading_zeros() / 8) as usize;
+    mum(0xdead_beef, 0xfffe, &bytes[..len])
+}
+
+/// Fold a murmur3 hash into 31 bits, retrying with a new seed up to eight times if the result is
+/// zero.
+fn mum(seed: u32, fallback: u32, key: &[u8]) -> u32 {
+    for i in 0..8 {
+        let haz = murmur3(key, seed.wrapping_add(i));
+        let ham = (haz >> 31) ^ (haz & 0x7fff_ffff);
+        if 0 != ham {
+            return ham;
+        }
+    }
+    fallback
+}
+
+/// MurmurHash3_x86_32.
+fn murmur3(key: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h = seed;
+    let mut chunks = key.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h ^= k;
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let rem = chunks.remainder();
+    if !rem.is_empty() {
+        let mut k = 0u32;
+        for (i, byte) in rem.iter().enumerate() {
+            k |= u32::from(*byte) << (8 * i);
+        }
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+    h ^= key.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{b, na, nc};
+
+    #[test]
+    fn mug_atom() {
+        // (mug 0) -> 0x79ff.04e8
+        {
+            assert_eq!(0x79ff_04e8, a!(0).mug());
+        }
+
+        // (mug 1) -> 0x715c.2a60
+        {
+            assert_eq!(0x715c_2a60, a!(1).mug());
+        }
+
+        // (mug 2) -> 0x718b.9468
+        {
+            assert_eq!(0x718b_9468, a!(2).mug());
+        }
+    }
+
+    #[test]
+    fn mug_cell() {
+        // (mug [0 0]) -> 0x192f.5588
+        {
+            assert_eq!(0x192f_5588, nc!(b!(na!(0)), b!(na!(0))).mug());
+        }
+
+        // (mug [1 1]) -> 0x6b32.ec46
+        {
+            assert_eq!(0x6b32_ec46, nc!(b!(na!(1)), b!(na!(1))).mug());
+        }
+
+        // Equal cells have equal mugs and unequal cells don't.
+        {
+            let lh = nc!(b!(na!(1)), b!(nc!(b!(na!(2)), b!(na!(3)))));
+            let rh = nc!(b!(na!(1)), b!(nc!(b!(na!(2)), b!(na!(3)))));
+            assert_eq!(lh.mug(), rh.mug());
+            let rh = nc!(b!(nc!(b!(na!(1)), b!(na!(2)))), b!(na!(3)));
+            assert_ne!(lh.mug(), rh.mug());
+        }
+    }
+}
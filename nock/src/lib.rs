@@ -3,9 +3,12 @@ pub mod atom;
 #[macro_use]
 pub mod cell;
 pub mod error;
+pub mod hash;
 pub mod interpreters;
 pub mod loobean;
+pub mod mark;
 pub mod noun;
+pub mod serdes;
 
 #[global_allocator]
 static GLOBAL: loom::Loom = loom::Loom;
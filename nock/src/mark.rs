@@ -0,0 +1,208 @@
+use crate::{atom::Atom, cell::Cell, hash::mugs, noun::Noun};
+use loom::mark::{Mark, Marker};
+use std::{collections::HashMap, mem};
+
+impl Mark for Noun {
+    fn mark(&self, marker: &mut Marker) {
+        let mut stack = vec![self];
+        while let Some(noun) = stack.pop() {
+            match noun {
+                Noun::Atom(Atom::Indirect(v)) if 0 < v.capacity() => {
+                    marker.mark(v.as_ptr(), mem::size_of::<u64>() * v.capacity());
+                }
+                Noun::Atom(_) => {}
+                Noun::Cell(c) => {
+                    for child in [&c.head, &c.tail] {
+                        if marker.mark(&**child as *const Noun, mem::size_of::<Noun>()) {
+                            stack.push(child);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Mark for Cell {
+    fn mark(&self, marker: &mut Marker) {
+        for child in [&self.head, &self.tail] {
+            if marker.mark(&**child as *const Noun, mem::size_of::<Noun>()) {
+                child.mark(marker);
+            }
+        }
+    }
+}
+
+/// Duplicated subtrees of a noun, as found by [`duplicates`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Duplicates {
+    /// Number of distinct subtrees.
+    pub unique: usize,
+    /// Number of subtrees equal to an earlier distinct subtree.
+    pub duplicates: usize,
+    /// Loom bytes occupied by the duplicated subtrees.
+    pub bytes: usize,
+}
+
+impl Noun {
+    /// Copy a noun into fresh, tightly sized allocations, freeing the original as it's copied.
+    ///
+    /// Cells are reallocated in depth-first order and indirect atoms are normalized and shrunk to
+    /// fit, which compacts a noun that has been built up piecemeal.
+    pub fn pack(self) -> Self {
+        enum Frame {
+            Tail(Box<Noun>),
+            Head(Noun),
+        }
+
+        let mut stack = Vec::new();
+        let mut noun = self;
+        loop {
+            let mut packed = match noun {
+                Noun::Cell(c) => {
+                    stack.push(Frame::Tail(c.tail));
+                    noun = *c.head;
+                    continue;
+                }
+                Noun::Atom(Atom::Indirect(v)) => Noun::Atom(match Atom::from_limbs(v) {
+                    Atom::Indirect(mut v) => {
+                        v.shrink_to_fit();
+                        Atom::Indirect(v)
+                    }
+                    direct => direct,
+                }),
+                atom => atom,
+            };
+            loop {
+                match stack.pop() {
+                    Some(Frame::Tail(tail)) => {
+                        stack.push(Frame::Head(packed));
+                        noun = *tail;
+                        break;
+                    }
+                    Some(Frame::Head(head)) => {
+                        packed = Noun::Cell(Cell {
+                            head: Box::new(head),
+                            tail: Box::new(packed),
+                        });
+                    }
+                    None => return packed,
+                }
+            }
+        }
+    }
+}
+
+/// Find the subtrees of a noun that are equal to another subtree, which is the work a meld would
+/// save.
+///
+/// Subtrees of a duplicate aren't counted separately.
+pub fn duplicates(noun: &Noun) -> Duplicates {
+    let mugs = mugs(noun);
+    let mut seen: HashMap<u32, Vec<&Noun>> = HashMap::new();
+    let mut dups = Duplicates::default();
+    let mut stack = vec![noun];
+    while let Some(noun) = stack.pop() {
+        let bucket = seen.entry(mugs[&(noun as *const Noun)]).or_default();
+        if bucket.contains(&noun) {
+            dups.duplicates += 1;
+            dups.bytes += size(noun);
+            continue;
+        }
+        bucket.push(noun);
+        dups.unique += 1;
+        if let Noun::Cell(c) = noun {
+            stack.push(&c.tail);
+            stack.push(&c.head);
+        }
+    }
+    dups
+}
+
+/// Get the loom bytes owned by a noun.
+fn size(noun: &Noun) -> usize {
+    let mut bytes = 0;
+    let mut stack = vec![noun];
+    while let Some(noun) = stack.pop() {
+        match noun {
+            Noun::Atom(Atom::Indirect(v)) => bytes += mem::size_of::<u64>() * v.capacity(),
+            Noun::Atom(_) => {}
+            Noun::Cell(c) => {
+                bytes += 2 * mem::size_of::<Noun>();
+                stack.push(&c.tail);
+                stack.push(&c.head);
+            }
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{b, na, nc};
+    use loom::Stats;
+
+    #[test]
+    fn mark() {
+        // [1 [2 3]] owns four boxes.
+        {
+            let noun = nc!(b!(na!(1)), b!(nc!(b!(na!(2)), b!(na!(3)))));
+            let mut marker = Marker::new();
+            marker.root("noun", &noun);
+            let report = marker.sweep(Stats {
+                allocs: 4,
+                bytes: 4 * mem::size_of::<Noun>(),
+                peak: 0,
+            });
+            assert!(report.is_clean());
+            assert_eq!(4, report.roots[0].allocs);
+        }
+
+        // [2^64 0] owns two boxes and a limb buffer.
+        {
+            let noun = nc!(b!(Noun::Atom(Atom::Indirect(vec![0, 1]))), b!(na!(0)));
+            let mut marker = Marker::new();
+            marker.root("noun", &noun);
+            let report = marker.sweep(Stats::default());
+            assert_eq!(3, report.reachable.allocs);
+        }
+    }
+
+    #[test]
+    fn pack() {
+        // [[1 2] 2^64 0]
+        {
+            let mut limbs = Vec::with_capacity(16);
+            limbs.extend([0, 1, 0]);
+            let noun = nc!(
+                b!(nc!(b!(na!(1)), b!(na!(2)))),
+                b!(nc!(b!(Noun::Atom(Atom::Indirect(limbs))), b!(na!(0))))
+            );
+            let packed = noun.clone().pack();
+            assert_eq!(
+                nc!(
+                    b!(nc!(b!(na!(1)), b!(na!(2)))),
+                    b!(nc!(b!(Noun::Atom(Atom::Indirect(vec![0, 1]))), b!(na!(0))))
+                ),
+                packed
+            );
+            assert!(size(&packed) < size(&noun));
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        // [[1 2] [1 2] 3]
+        {
+            let noun = nc!(
+                b!(nc!(b!(na!(1)), b!(na!(2)))),
+                b!(nc!(b!(nc!(b!(na!(1)), b!(na!(2)))), b!(na!(3))))
+            );
+            let dups = super::duplicates(&noun);
+            assert_eq!(1, dups.duplicates);
+            assert_eq!(2 * mem::size_of::<Noun>(), dups.bytes);
+            assert_eq!(6, dups.unique);
+        }
+    }
+}
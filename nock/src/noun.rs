@@ -14,7 +14,7 @@ pub enum Noun {
 impl Clone for Noun {
     fn clone(&self) -> Self {
         match self {
-            Noun::Atom(a) => na!(av!(*a)),
+            Noun::Atom(a) => Noun::Atom(a.clone()),
             Noun::Cell(c) => nc!(ch!(c).clone(), ct!(c).clone()),
         }
     }
@@ -35,8 +35,15 @@ impl PartialEq for Noun {
 impl fmt::Display for Noun {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Noun::Atom(a) => {
-                write!(f, "{}", av!(a))
+            Noun::Atom(Atom::Direct(v)) => {
+                write!(f, "{}", v)
+            }
+            Noun::Atom(Atom::Indirect(v)) => {
+                write!(f, "0x")?;
+                for limb in v.iter().rev() {
+                    write!(f, "{:016x}", limb)?;
+                }
+                Ok(())
             }
             Noun::Cell(ref c) => {
                 write!(f, "[{} {}]", ch!(c), ct!(c))
@@ -45,6 +52,37 @@ impl fmt::Display for Noun {
     }
 }
 
+/// Noun from u64.
+impl From<u64> for Noun {
+    fn from(val: u64) -> Self {
+        Noun::Atom(Atom::Direct(val))
+    }
+}
+
+/// Noun from Atom.
+impl From<Atom> for Noun {
+    fn from(atom: Atom) -> Self {
+        Noun::Atom(atom)
+    }
+}
+
+/// Noun from Cell.
+impl From<Cell> for Noun {
+    fn from(cell: Cell) -> Self {
+        Noun::Cell(cell)
+    }
+}
+
+/// Noun from (Noun, Noun).
+impl From<(Noun, Noun)> for Noun {
+    fn from((head, tail): (Noun, Noun)) -> Self {
+        Noun::Cell(Cell {
+            head: Box::new(head),
+            tail: Box::new(tail),
+        })
+    }
+}
+
 impl Noun {
     pub fn from_loobean(l: Loobean) -> Self {
         match l {
@@ -52,6 +90,70 @@ impl Noun {
             Loobean::No => na!(1),
         }
     }
+
+    /// Create a null-terminated list.
+    pub fn from_list(items: Vec<Noun>) -> Self {
+        items
+            .into_iter()
+            .rev()
+            .fold(na!(0), |list, item| Noun::from((item, list)))
+    }
+
+    /// Convert a null-terminated list into its items, or give the noun back if it isn't a list.
+    pub fn into_list(self) -> Result<Vec<Noun>, Noun> {
+        let mut items = Vec::new();
+        let mut list = self;
+        loop {
+            match list {
+                Noun::Atom(Atom::Direct(0)) => break Ok(items),
+                Noun::Cell(c) => {
+                    items.push(*c.head);
+                    list = *c.tail;
+                }
+                atom => {
+                    items.push(atom);
+                    break Err(Noun::from_list_with_tail(items));
+                }
+            }
+        }
+    }
+
+    /// Rebuild a list whose final item is its terminator.
+    fn from_list_with_tail(mut items: Vec<Noun>) -> Self {
+        let tail = items.pop().unwrap_or(na!(0));
+        items
+            .into_iter()
+            .rev()
+            .fold(tail, |list, item| Noun::from((item, list)))
+    }
+
+    /// Create a tuple, i.e. `[a b c]` from `[a, b, c]`.
+    ///
+    /// Panics if there are no items.
+    pub fn from_tuple(items: Vec<Noun>) -> Self {
+        assert!(!items.is_empty(), "empty tuple");
+        Noun::from_list_with_tail(items)
+    }
+
+    /// Split a tuple into `n` items, or give the noun back if it has fewer.
+    pub fn into_tuple(self, n: usize) -> Result<Vec<Noun>, Noun> {
+        let mut items = Vec::with_capacity(n);
+        let mut rest = self;
+        while items.len() + 1 < n {
+            match rest {
+                Noun::Cell(c) => {
+                    items.push(*c.head);
+                    rest = *c.tail;
+                }
+                atom => {
+                    items.push(atom);
+                    return Err(Noun::from_tuple(items));
+                }
+            }
+        }
+        items.push(rest);
+        Ok(items)
+    }
 }
 
 /// Create a noun-wrapped atom.
@@ -90,6 +192,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn list() {
+        // [1 2 3 ~] <-> [1 2 3]
+        {
+            let list = Noun::from_list(vec![na!(1), na!(2), na!(3)]);
+            assert_eq!(
+                nc!(b!(na!(1)), b!(nc!(b!(na!(2)), b!(nc!(b!(na!(3)), b!(na!(0))))))),
+                list
+            );
+            assert_eq!(Ok(vec![na!(1), na!(2), na!(3)]), list.into_list());
+        }
+
+        // ~ <-> []
+        {
+            assert_eq!(Ok(vec![]), na!(0).into_list());
+        }
+
+        // [1 2 3] isn't a list.
+        {
+            let noun = nc!(b!(na!(1)), b!(nc!(b!(na!(2)), b!(na!(3)))));
+            assert_eq!(Err(noun.clone()), noun.into_list());
+        }
+    }
+
+    #[test]
+    fn tuple() {
+        // [1 2 3] <-> [1 2 3]
+        {
+            let tuple = Noun::from_tuple(vec![na!(1), na!(2), na!(3)]);
+            assert_eq!(nc!(b!(na!(1)), b!(nc!(b!(na!(2)), b!(na!(3))))), tuple);
+            assert_eq!(Ok(vec![na!(1), na!(2), na!(3)]), tuple.clone().into_tuple(3));
+            assert_eq!(
+                Ok(vec![na!(1), nc!(b!(na!(2)), b!(na!(3)))]),
+                tuple.clone().into_tuple(2)
+            );
+            assert_eq!(Err(tuple.clone()), tuple.into_tuple(4));
+        }
+    }
+
     #[test]
     fn partialeq() {
         // 500 == 500
@@ -0,0 +1,433 @@
+use crate::{atom::Atom, cell::Cell, error::Error, hash::mugs, malformed_jam, noun::Noun};
+use std::collections::HashMap;
+
+/// Serialize into a byte buffer.
+///
+/// This is Hoon's `+jam`: repeated subtrees are encoded as backreferences to their first
+/// occurrence.
+pub trait Jam {
+    fn jam(&self) -> Vec<u8>;
+}
+
+/// Deserialize from a byte buffer.
+///
+/// This is Hoon's `+cue`, the inverse of `+jam`.
+pub trait Cue: Sized {
+    fn cue(bytes: &[u8]) -> Result<Self, Error>;
+}
+
+impl Jam for Noun {
+    fn jam(&self) -> Vec<u8> {
+        let mut jammer = Jammer::default();
+        jammer.jam(self);
+        jammer.finish()
+    }
+}
+
+impl Jam for Cell {
+    fn jam(&self) -> Vec<u8> {
+        let mut jammer = Jammer::default();
+        jammer.bits.push(true);
+        jammer.bits.push(false);
+        jammer.jam(&self.head);
+        jammer.jam(&self.tail);
+        jammer.finish()
+    }
+}
+
+/// The state of a jam, which may span several subtrees of a noun.
+#[derive(Default)]
+struct Jammer<'a> {
+    bits: Bits,
+    mugs: HashMap<*const Noun, u32>,
+    seen: HashMap<u32, Vec<(&'a Noun, u64)>>,
+}
+
+impl<'a> Jammer<'a> {
+    /// Jam a noun onto the end of the buffer.
+    fn jam(&mut self, noun: &'a Noun) {
+        self.mugs.extend(mugs(noun));
+        let mut stack = vec![noun];
+        while let Some(noun) = stack.pop() {
+            let mug = self.mugs[&(noun as *const Noun)];
+            let prior = self
+                .seen
+                .get(&mug)
+                .and_then(|nouns| nouns.iter().find(|(n, _)| *n == noun))
+                .map(|(_, pos)| *pos);
+            if let Some(pos) = prior {
+                match noun {
+                    Noun::Atom(a) if a.bits() <= Atom::Direct(pos).bits() => {
+                        self.bits.push(false);
+                        self.bits.mat(a);
+                    }
+                    _ => {
+                        self.bits.push(true);
+                        self.bits.push(true);
+                        self.bits.mat(&Atom::Direct(pos));
+                    }
+                }
+                continue;
+            }
+            self.seen
+                .entry(mug)
+                .or_default()
+                .push((noun, self.bits.len));
+            match noun {
+                Noun::Atom(a) => {
+                    self.bits.push(false);
+                    self.bits.mat(a);
+                }
+                Noun::Cell(c) => {
+                    self.bits.push(true);
+                    self.bits.push(false);
+                    stack.push(&c.tail);
+                    stack.push(&c.head);
+                }
+            }
+        }
+    }
+
+    /// Convert the buffer into bytes.
+    fn finish(self) -> Vec<u8> {
+        Atom::from_limbs(self.bits.limbs).to_bytes()
+    }
+}
+
+impl Cue for Noun {
+    fn cue(bytes: &[u8]) -> Result<Self, Error> {
+        /// A decoded subtree, whose repeated subtrees are shared as indices of earlier nodes.
+        enum Node {
+            Atom(Atom),
+            Cell(usize, usize),
+        }
+
+        /// A cell whose head or tail is still being decoded.
+        enum Frame {
+            Head(u64),
+            Tail(u64, usize),
+        }
+
+        let atom = Atom::from_bytes(bytes);
+        let len = atom.bits();
+        let mut nodes = Vec::new();
+        let mut seen: HashMap<u64, usize> = HashMap::new();
+        let mut stack = Vec::new();
+        let mut cursor = 0;
+        let root = loop {
+            let start = cursor;
+            if start >= len {
+                return Err(malformed_jam!("unexpected end of input", start));
+            }
+            let mut node = if !atom.bit(start) {
+                let (size, a) = rub(&atom, start + 1)?;
+                cursor += 1 + size;
+                nodes.push(Node::Atom(a));
+                nodes.len() - 1
+            } else if !atom.bit(start + 1) {
+                stack.push(Frame::Head(start));
+                cursor += 2;
+                continue;
+            } else {
+                let (size, pos) = rub(&atom, start + 2)?;
+                cursor += 2 + size;
+                match pos {
+                    Atom::Direct(pos) if seen.contains_key(&pos) => seen[&pos],
+                    _ => {
+                        return Err(malformed_jam!(
+                            format!("bad backreference to {:?}", pos),
+                            start
+                        ))
+                    }
+                }
+            };
+            seen.insert(start, node);
+            let done = loop {
+                match stack.pop() {
+                    Some(Frame::Head(pos)) => {
+                        stack.push(Frame::Tail(pos, node));
+                        break None;
+                    }
+                    Some(Frame::Tail(pos, head)) => {
+                        nodes.push(Node::Cell(head, node));
+                        node = nodes.len() - 1;
+                        seen.insert(pos, node);
+                    }
+                    None => break Some(node),
+                }
+            };
+            if let Some(root) = done {
+                break root;
+            }
+        };
+
+        // Build the noun from its nodes, which come after the nodes they refer to, copying a
+        // shared subtree for each reference but the last.
+        let mut uses = vec![0usize; nodes.len()];
+        for node in &nodes {
+            if let Node::Cell(head, tail) = node {
+                uses[*head] += 1;
+                uses[*tail] += 1;
+            }
+        }
+        let mut built: Vec<Option<Noun>> = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let mut take = |idx: usize| {
+                uses[idx] -= 1;
+                if 0 == uses[idx] {
+                    built[idx].take().unwrap()
+                } else {
+                    built[idx].clone().unwrap()
+                }
+            };
+            let noun = match node {
+                Node::Atom(a) => Noun::Atom(a),
+                Node::Cell(head, tail) => {
+                    let head = take(head);
+                    let tail = take(tail);
+                    Noun::Cell(Cell {
+                        head: Box::new(head),
+                        tail: Box::new(tail),
+                    })
+                }
+            };
+            built.push(Some(noun));
+        }
+        Ok(built[root].take().unwrap())
+    }
+}
+
+impl Cue for Cell {
+    fn cue(bytes: &[u8]) -> Result<Self, Error> {
+        match Noun::cue(bytes)? {
+            Noun::Cell(c) => Ok(c),
+            Noun::Atom(_) => Err(malformed_jam!("expected cell, found atom", 0)),
+        }
+    }
+}
+
+/// Decode a length-prefixed atom starting at bit `pos`, returning the number of bits consumed and
+/// the atom.
+fn rub(atom: &Atom, pos: u64) -> Result<(u64, Atom), Error> {
+    let len = atom.bits();
+    let mut zeros = 0;
+    while !atom.bit(pos + zeros) {
+        zeros += 1;
+        if pos + zeros >= len {
+            return Err(malformed_jam!("unterminated length prefix", pos));
+        }
+    }
+    if 0 == zeros {
+        return Ok((1, Atom::Direct(0)));
+    }
+    if zeros > 64 {
+        return Err(malformed_jam!("length prefix too long", pos));
+    }
+    let size = (1u64 << (zeros - 1)) | read(atom, pos + zeros + 1, zeros - 1)[0];
+    if (pos + 2 * zeros)
+        .checked_add(size)
+        .is_none_or(|end| end > len)
+    {
+        return Err(malformed_jam!("unexpected end of input", pos));
+    }
+    Ok((
+        2 * zeros + size,
+        Atom::from_limbs(read(atom, pos + 2 * zeros, size)),
+    ))
+}
+
+/// Read `count` bits of an atom starting at bit `pos`.
+fn read(atom: &Atom, pos: u64, count: u64) -> Vec<u64> {
+    let limbs = atom.limbs();
+    let limb = |i: u64| limbs.get(i as usize).copied().unwrap_or(0);
+    let mut out = Vec::with_capacity((count / 64 + 1) as usize);
+    let mut done = 0;
+    while done < count || out.is_empty() {
+        let at = pos + done;
+        let (idx, off) = (at / 64, at % 64);
+        let mut val = limb(idx) >> off;
+        if 0 != off {
+            val |= limb(idx + 1) << (64 - off);
+        }
+        let n = (count - done).min(64);
+        if n < 64 {
+            val &= (1 << n) - 1;
+        }
+        out.push(val);
+        done += n.max(1);
+    }
+    out
+}
+
+/// A little-endian bit buffer.
+#[derive(Default)]
+struct Bits {
+    limbs: Vec<u64>,
+    len: u64,
+}
+
+impl Bits {
+    /// Append a single bit.
+    fn push(&mut self, bit: bool) {
+        self.push_limb(u64::from(bit), 1);
+    }
+
+    /// Append the low `count` bits of a limb.
+    fn push_limb(&mut self, val: u64, count: u64) {
+        if 0 == count {
+            return;
+        }
+        let val = if count < 64 {
+            val & ((1 << count) - 1)
+        } else {
+            val
+        };
+        let (idx, off) = ((self.len / 64) as usize, self.len % 64);
+        self.len += count;
+        self.limbs.resize(self.len.div_ceil(64) as usize, 0);
+        self.limbs[idx] |= val << off;
+        if 0 != off && off + count > 64 {
+            self.limbs[idx + 1] |= val >> (64 - off);
+        }
+    }
+
+    /// Append the low `count` bits of an atom.
+    fn push_atom(&mut self, atom: &Atom, count: u64) {
+        let mut left = count;
+        for limb in atom.limbs() {
+            let n = left.min(64);
+            self.push_limb(*limb, n);
+            left -= n;
+        }
+        while left > 0 {
+            let n = left.min(64);
+            self.push_limb(0, n);
+            left -= n;
+        }
+    }
+
+    /// Append a length-prefixed atom.
+    fn mat(&mut self, atom: &Atom) {
+        let size = atom.bits();
+        if 0 == size {
+            self.push(true);
+            return;
+        }
+        let size_size = u64::from(64 - size.leading_zeros());
+        self.push_limb(0, size_size);
+        self.push(true);
+        self.push_limb(size, size_size - 1);
+        self.push_atom(atom, size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{b, na, nc};
+
+    #[test]
+    fn jam() {
+        // (jam 0) -> 2
+        {
+            assert_eq!(vec![2], na!(0).jam());
+        }
+
+        // (jam 1) -> 12
+        {
+            assert_eq!(vec![12], na!(1).jam());
+        }
+
+        // (jam 19) -> 2.480
+        {
+            assert_eq!(2480u16.to_le_bytes().to_vec(), na!(19).jam());
+        }
+
+        // (jam [0 0]) -> 41
+        {
+            assert_eq!(vec![41], nc!(b!(na!(0)), b!(na!(0))).jam());
+        }
+
+        // (jam [1 1]) -> 817
+        {
+            assert_eq!(
+                817u16.to_le_bytes().to_vec(),
+                nc!(b!(na!(1)), b!(na!(1))).jam()
+            );
+        }
+
+        // (jam [1 2]) -> 4.657
+        {
+            assert_eq!(
+                4657u16.to_le_bytes().to_vec(),
+                nc!(b!(na!(1)), b!(na!(2))).jam()
+            );
+        }
+    }
+
+    #[test]
+    fn cue() {
+        // (cue 2) -> 0
+        {
+            assert_eq!(na!(0), Noun::cue(&[2]).unwrap());
+        }
+
+        // (cue 817) -> [1 1]
+        {
+            assert_eq!(
+                nc!(b!(na!(1)), b!(na!(1))),
+                Noun::cue(&817u16.to_le_bytes()).unwrap()
+            );
+        }
+
+        // (cue 0) -> crash
+        {
+            assert!(Noun::cue(&[]).is_err());
+        }
+
+        // (cue 3) -> crash
+        {
+            assert!(Noun::cue(&[3]).is_err());
+        }
+
+        // An atom whose 64-bit length prefix claims nearly 2^64 bits -> crash
+        {
+            let mut bytes = [0; 17];
+            bytes[8] = 0xfe;
+            bytes[9..16].fill(0xff);
+            bytes[16] = 0x01;
+            assert!(Noun::cue(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        // [[1 2] [1 2] 2^64 [2^64 2^200] 0]
+        let pair = nc!(b!(na!(1)), b!(na!(2)));
+        let big = Noun::Atom(Atom::Indirect(vec![0, 1]));
+        let bigger = Noun::Atom(Atom::Indirect(vec![0, 0, 0, 0x100]));
+        let noun = nc!(
+            b!(pair.clone()),
+            b!(nc!(
+                b!(pair),
+                b!(nc!(
+                    b!(big.clone()),
+                    b!(nc!(b!(nc!(b!(big), b!(bigger))), b!(na!(0))))
+                ))
+            ))
+        );
+        assert_eq!(noun, Noun::cue(&noun.jam()).unwrap());
+        if let Noun::Cell(c) = noun {
+            assert_eq!(Noun::Cell(c.clone()).jam(), c.jam());
+            assert_eq!(c, Cell::cue(&c.jam()).unwrap());
+        }
+
+        // A long list whose items repeat a subtree.
+        let list = Noun::from_list(
+            (0..2_000)
+                .map(|i| nc!(b!(na!(i % 7)), b!(na!(1 << 40))))
+                .collect(),
+        );
+        assert_eq!(list, Noun::cue(&list.jam()).unwrap());
+    }
+}
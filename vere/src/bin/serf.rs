@@ -0,0 +1,54 @@
+use std::{env, io, path::Path, process};
+use vere::{
+    config::{parse_size, Config},
+    error::Error,
+    serf::Serf,
+};
+
+const USAGE: &str = "\
+usage: serf <pier> [--loom <size>]
+
+Run a pier's kernel for the king that started it, speaking the serf protocol in newt frames
+over stdin and stdout. The serf starts from the pier's latest snapshot and must not be run by
+hand.
+
+options:
+  --loom <size>
+      Override the pier's loom size, e.g. 8G. Must be between 2G and 64G.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (mut pier, mut loom) = (None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--loom" => match iter.next().map(|size| parse_size(size)) {
+                Some(Ok(size)) => loom = Some(size),
+                _ => usage(),
+            },
+            _ if pier.is_none() && !arg.starts_with("--") => pier = Some(Path::new(arg)),
+            _ => usage(),
+        }
+    }
+    let pier = pier.unwrap_or_else(|| usage());
+
+    if let Err(err) = serve(pier, loom) {
+        eprintln!("serf: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Configure the loom and serve the king until it asks the serf to exit.
+fn serve(pier: &Path, loom: Option<u64>) -> Result<(), Error> {
+    let mut conf = Config::load(pier)?;
+    if let Some(loom) = loom {
+        conf.set_loom(loom)?;
+    }
+    conf.apply();
+    Serf::load(pier)?.serve(io::stdin().lock(), io::stdout().lock())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
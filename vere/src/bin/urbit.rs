@@ -1,3 +1,874 @@
+use loom::{mark::Marker, Loom};
+use nock::{atom::Atom, mark::duplicates, noun::Noun};
+use std::{
+    env,
+    fs::File,
+    io::{BufReader, BufWriter},
+    net::{IpAddr, Ipv4Addr, SocketAddrV4},
+    path::Path,
+    process,
+};
+use vere::{
+    config::{parse_size, Config},
+    driver::{
+        ames::{Ames, DnsCzars, FakeCzars, Peers, FAKE_PORT, PORT},
+        behn::{Behn, SystemClock},
+        clay::Clay,
+        dill::Dill,
+        eyre::Eyre,
+        iris::{Iris, Ureq, TIMEOUT as IRIS_TIMEOUT},
+        khan::Khan,
+        Driver, Drivers,
+    },
+    error::Error,
+    event_log::{epoch::Epoch, replay::Progress, EvtLog, Log},
+    kernel::Kernel,
+    king::King,
+    pier::{self, Identity, Lock, Pier},
+    scry::Scry,
+    snapshot::Snapshot,
+    verify,
+};
+
+const USAGE: &str = "\
+usage: urbit <command> [<args>]
+
+commands:
+  chop <pier> [--dry-run]
+      Remove the events covered by the pier's snapshot from its event log, after which the
+      pier can only be replayed from that snapshot. The snapshot is checked against the log
+      first, and the pier must not be running. --dry-run only reports what would be removed.
+  export <pier> <file> [--from <event>] [--to <event>]
+      Write the pier's events, by default all of them, to <file> along with its identity and
+      the mug of the kernel the events start from.
+  import <pier> <file> [--snapshot <snapshot>]
+      Create the event log of a new pier from an export. An export that doesn't start at
+      event 1 needs the snapshot of the kernel it starts from.
+  info <pier>
+      Print the pier's identity, event range, epochs, snapshot and configuration, and whether
+      it's running.
+  new <pier> <pill> --who <ship> [--fake] [--loom <size>]
+      Create a pier for <ship>, given as a number, from a local pill: compute the kernel from
+      the pill's lifecycle events, apply the rest of its events and snapshot the result.
+      --fake marks the ship as one that never touches the live network.
+  prune <pier>
+      Delete the epochs of the pier's event log that precede its latest epoch with a
+      snapshot, which are no longer needed to replay it. The pier must not be running.
+  replay <pier> [--to <event>] [--loom <size>]
+      Replay the pier's event log from its latest snapshot up to <event>, by default its last
+      event, checking each event against its recorded mug.
+  roll <pier> [--loom <size>]
+      Boot the pier and start a new epoch of its event log from a snapshot of its kernel as
+      of its last event.
+  run <pier> [--loom <size>] [--http-port <port>] [--http-public] [--no-http]
+      [--ames-port <port>] [--no-ames]
+      Boot the pier with its kernel in a separate serf process, run from the serf executable
+      next to this one, by replaying its event log from its latest snapshot into the serf.
+      Process the events of the I/O drivers, which are the timer, terminal, filesystem sync,
+      HTTP server, HTTP client and networking drivers, until the ship logs out or its input ends, then
+      snapshot the pier. A serf that dies is restarted and the log replayed into it.
+      The terminal is put in raw mode; if stdin isn't a terminal, each line of it is typed
+      in. The HTTP server listens on the loopback interface on --http-port, by default the
+      first free port from 8080 on; --http-public listens on every interface and --no-http
+      turns the server off. Networking listens for UDP on --ames-port, by default any free
+      port or, for a galaxy, 13337 plus the galaxy's number, which is where ships look for
+      their galaxies at the galaxies' names in DNS, e.g. ~zod at zod.urbit.org. Fake galaxies
+      listen on 31337 plus their number, where fake ships look for them on the loopback
+      interface; --no-ames turns networking off. Desks mounted by the ship are kept in
+      sync with directories of the pier. The ship's namespace can be read, as jam or
+      as JSON, through the Unix socket .urb/scry.sock, one `<jam|json> <path>` line at a time.
+      The ship can run threads, poke agents, take events, report its status and its packet
+      counts per peer, and snapshot, pack or exit through the Unix socket .urb/conn.sock,
+      which takes newt-framed `[id request]` nouns.
+  snapshot <pier> [--loom <size>]
+      Boot the pier and save a snapshot of its kernel as of its last event.
+  sweep <pier> [--pack] [--duplicates] [--loom <size>]
+      Mark everything reachable from the pier's kernel and sweep the loom for live allocations
+      that aren't reachable from it. --pack compacts the kernel before rewriting the snapshot,
+      and --duplicates counts the kernel's subtrees that are equal to another, which is what
+      deduplicating them would save. The pier must not be running.
+  truncate <pier> <event> [--dry-run]
+      Roll the pier back to <event> by removing every later event from its event log and
+      deleting its snapshot if the snapshot is of a later event. The pier must not be running.
+      --dry-run only reports what would be removed.
+  verify <pier> [--snapshot <snapshot>] [--replay]
+      Check the pier's event log and snapshots without booting it, printing each finding and
+      then a summary as JSON lines. Every event is read back and checked for framing,
+      decodability and numbering, and every snapshot is checked against the mug recorded for
+      its event, as is <snapshot> if given. --replay also replays every event and checks it
+      against its recorded mug. Exits with 1 if any check fails.
+
+options:
+  --loom <size>
+      Override the pier's loom size for this run, e.g. 8G. Must be between 2G and 64G.
+
+exit codes:
+  0   success
+  1   a check failed
+  2   bad usage
+  65  bad pill, event log, snapshot or jam, or replay diverged from the log
+  70  the kernel crashed
+  74  I/O failure
+  75  the pier is running in another process
+  76  a peer process violated its protocol
+  78  bad configuration";
+
 fn main() {
-    unimplemented!();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match args.first().map(String::as_str) {
+        Some("chop") => chop(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("import") => import(&args[1..]),
+        Some("info") => info(&args[1..]),
+        Some("new") => new(&args[1..]),
+        Some("prune") => prune(&args[1..]),
+        Some("replay") => replay(&args[1..]),
+        Some("roll") => roll(&args[1..]),
+        Some("run") => run(&args[1..]),
+        Some("snapshot") => snapshot(&args[1..]),
+        Some("sweep") => sweep(&args[1..]),
+        Some("truncate") => truncate(&args[1..]),
+        Some("verify") => verify(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    };
+    process::exit(code);
+}
+
+/// Report an error from a command, producing the exit code for it.
+fn fail(cmd: &str, err: Error) -> i32 {
+    eprintln!("urbit: {}: {}", cmd, err);
+    match err.root() {
+        Error::BadPill(_)
+        | Error::BadEventLog(_)
+        | Error::BadSnapshot(_)
+        | Error::SnapshotMismatch { .. }
+        | Error::Cue(_)
+        | Error::Diverged(_) => 65,
+        Error::Nock(_) | Error::Crash { .. } => 70,
+        Error::Io { .. } | Error::Lmdb(_) => 74,
+        Error::PierLive(_) => 75,
+        Error::Ipc(_) => 76,
+        Error::BadConfig(_) => 78,
+        Error::Path { .. } | Error::Event { .. } => unreachable!(),
+    }
+}
+
+/// Load a pier's configuration, overriding its loom size if given, and apply it.
+fn configure(pier: &Path, loom: Option<u64>) -> Result<Config, Error> {
+    let mut conf = Config::load(pier)?;
+    if let Some(loom) = loom {
+        conf.set_loom(loom)?;
+    }
+    conf.apply();
+    Ok(conf)
+}
+
+/// Parse the arguments of a command that takes a pier and an optional loom size.
+fn pier_args(args: &[String]) -> Option<(&Path, Option<u64>)> {
+    let (mut pier, mut loom) = (None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--loom" => loom = Some(parse_size(args.next()?).ok()?),
+            _ if pier.is_none() && !arg.starts_with("--") => pier = Some(Path::new(arg)),
+            _ => return None,
+        }
+    }
+    Some((pier?, loom))
+}
+
+/// Print a report of a replay's progress.
+fn progress(progress: &Progress) {
+    eprintln!("urbit: {}", progress);
+}
+
+/// Remove the events covered by a pier's snapshot from its event log.
+fn chop(args: &[String]) -> i32 {
+    let (mut pier, mut dry_run) = (None, false);
+    for arg in args {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let pier = match pier {
+        Some(pier) => pier,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let chop = match pier::chop(pier, dry_run) {
+        Ok(chop) => chop,
+        Err(err) => return fail("chop", err),
+    };
+    if chop.is_empty() {
+        println!("no events before snapshot of event {}", chop.evt_num);
+    } else {
+        let verb = if dry_run { "would remove" } else { "removed" };
+        println!("{} events {}..={}", verb, chop.first, chop.evt_num);
+    }
+    0
+}
+
+/// Export a range of a pier's events to a file.
+fn export(args: &[String]) -> i32 {
+    let (mut pier, mut path, mut from, mut to) = (None, None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" | "--to" => match args.next().map(|num| num.parse::<u64>()) {
+                Some(Ok(num)) if "--from" == arg => from = Some(num),
+                Some(Ok(num)) => to = Some(num),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ if path.is_none() => path = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let (pier, path) = match (pier, path) {
+        (Some(pier), Some(path)) => (pier, path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let header = File::create(path)
+        .map_err(Error::from)
+        .and_then(|file| pier::export(pier, from, to, BufWriter::new(file)));
+    match header {
+        Ok(header) => {
+            println!(
+                "exported events {}..={} from kernel {:#x}",
+                header.first, header.last, header.kernel
+            );
+            0
+        }
+        Err(err) => fail("export", err),
+    }
+}
+
+/// Create a new pier's event log from an export.
+fn import(args: &[String]) -> i32 {
+    let (mut pier, mut path, mut snap) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--snapshot" => match args.next() {
+                Some(arg) => snap = Some(Path::new(arg)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ if path.is_none() => path = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let (pier, path) = match (pier, path) {
+        (Some(pier), Some(path)) => (pier, path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let header = snap.map(Snapshot::read).transpose().and_then(|snap| {
+        let file = File::open(path)?;
+        pier::import(pier, BufReader::new(file), snap)
+    });
+    match header {
+        Ok(header) => {
+            println!("imported events {}..={}", header.first, header.last);
+            0
+        }
+        Err(err) => fail("import", err),
+    }
+}
+
+/// Print a pier's metadata.
+fn info(args: &[String]) -> i32 {
+    let pier = match args {
+        [pier] => Path::new(pier),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    if !pier.join(".urb").exists() {
+        return fail(
+            "info",
+            Error::BadEventLog(format!("{} isn't a pier", pier.display())),
+        );
+    }
+
+    match print_info(pier) {
+        Ok(()) => 0,
+        Err(err) => fail("info", err),
+    }
+}
+
+/// Print a pier's metadata, failing if any of it can't be read.
+fn print_info(pier: &Path) -> Result<(), Error> {
+    let log = Log::open(pier)?;
+    match Identity::load(&log)? {
+        Some(ident) => println!(
+            "who: {}{}",
+            Noun::from(ident.who),
+            if ident.fake { " (fake)" } else { "" }
+        ),
+        None => println!("who: unknown"),
+    }
+    let epochs = Epoch::list(pier)?;
+    match epochs.first().map_or(log.first(), |epoch| epoch.first) {
+        first if 0 == first || first > log.last() => {
+            println!("events: none after event {}", log.last())
+        }
+        first => println!("events: {}..={}", first, log.last()),
+    }
+    for epoch in epochs {
+        let kernel = epoch
+            .kernel
+            .map_or("lifecycle".to_string(), |mug| format!("kernel {:#x}", mug));
+        println!(
+            "epoch: from event {}, vere {}, {}",
+            epoch.first, epoch.vere, kernel
+        );
+    }
+    match Snapshot::header(pier)? {
+        Some((evt_num, mug)) => println!("snapshot: event {}, mug {:#x}", evt_num, mug),
+        None => println!("snapshot: none"),
+    }
+    let conf = Config::load(pier)?;
+    println!("loom: {} bytes, high-water {}%", conf.loom, conf.high_water);
+    match Lock::holder(pier)? {
+        Some(pid) => println!("running: pid {}", pid),
+        None => println!("running: no"),
+    }
+    Ok(())
+}
+
+/// Create a pier from a pill.
+fn new(args: &[String]) -> i32 {
+    let (mut pier, mut pill, mut who, mut fake, mut loom) = (None, None, None, false, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fake" => fake = true,
+            "--who" => match args.next().and_then(|who| parse_ship(who)) {
+                Some(ship) => who = Some(ship),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            "--loom" => match args.next().map(|size| parse_size(size)) {
+                Some(Ok(size)) => loom = Some(size),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ if pill.is_none() => pill = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let (pier, pill, who) = match (pier, pill, who) {
+        (Some(pier), Some(pill), Some(who)) => (pier, pill, who),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let conf = match configure(pier, loom) {
+        Ok(conf) => conf,
+        Err(err) => return fail("new", err),
+    };
+    let ident = Identity { who, fake };
+    match Pier::create(pier, pill, &ident, &conf) {
+        Ok(live) => {
+            println!(
+                "created {} at event {}, mug {:#x}",
+                pier.display(),
+                live.evt_num(),
+                live.kernel().mug()
+            );
+            0
+        }
+        Err(err) => fail("new", err),
+    }
+}
+
+/// Parse a ship given as a decimal or `0x`-prefixed hexadecimal number.
+fn parse_ship(ship: &str) -> Option<Atom> {
+    let num = match ship.strip_prefix("0x") {
+        Some(hex) => u128::from_str_radix(hex, 16),
+        None => ship.parse(),
+    };
+    num.ok().map(|num| Atom::from_bytes(&num.to_le_bytes()))
+}
+
+/// Delete the epochs of a pier's event log that are no longer needed to replay it.
+fn prune(args: &[String]) -> i32 {
+    let pier = match args {
+        [pier] if !pier.starts_with("--") => Path::new(pier),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    match pier::prune(pier) {
+        Ok(pruned) if pruned.is_empty() => println!("no epochs to prune"),
+        Ok(pruned) => {
+            for epoch in pruned {
+                println!("removed epoch from event {}", epoch.first);
+            }
+        }
+        Err(err) => return fail("prune", err),
+    }
+    0
+}
+
+/// Replay a pier's event log.
+fn replay(args: &[String]) -> i32 {
+    let (mut pier, mut to, mut loom) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => match args.next().map(|num| num.parse::<u64>()) {
+                Some(Ok(num)) => to = Some(num),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            "--loom" => match args.next().map(|size| parse_size(size)) {
+                Some(Ok(size)) => loom = Some(size),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let pier = match pier {
+        Some(pier) => pier,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let snap = configure(pier, loom).and_then(|_| pier::replay(pier, to, &mut progress));
+    match snap {
+        Ok(snap) => {
+            println!(
+                "replayed to event {}, mug {:#x}",
+                snap.evt_num,
+                snap.kernel.mug()
+            );
+            0
+        }
+        Err(err) => fail("replay", err),
+    }
+}
+
+/// Boot a pier and process its events.
+fn run(args: &[String]) -> i32 {
+    let (mut pier, mut loom) = (None, None);
+    let (mut http, mut ames) = (Some((Ipv4Addr::LOCALHOST, None)), Some(None));
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), &mut http) {
+            ("--loom", _) => match iter.next().map(|size| parse_size(size)) {
+                Some(Ok(size)) => loom = Some(size),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            ("--http-port", Some((_, port))) => match iter.next().map(|port| port.parse()) {
+                Some(Ok(num)) => *port = Some(num),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            ("--http-public", Some((ip, _))) => *ip = Ipv4Addr::UNSPECIFIED,
+            ("--no-http", _) => http = None,
+            ("--ames-port", _) if ames.is_some() => match iter.next().map(|port| port.parse()) {
+                Some(Ok(port)) => ames = Some(Some(port)),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            ("--no-ames", _) => ames = None,
+            _ if pier.is_none() && !arg.starts_with("--") => pier = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let pier = match pier {
+        Some(pier) => pier,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let king = configure(pier, loom)
+        .and_then(|_| env::current_exe().map_err(Error::from))
+        .and_then(|exe| King::boot(pier, &exe.with_file_name("serf"), loom, &mut progress));
+    let mut king = match king {
+        Ok(king) => king,
+        Err(err) => return fail("run", err),
+    };
+    println!(
+        "{} is live at event {}, mug {:#x}, with its serf in process {}",
+        pier.display(),
+        king.evt_num(),
+        king.mug(),
+        king.serf_id()
+    );
+    let mut drivers = Drivers::new();
+    drivers.register(Box::new(Behn::new(SystemClock)));
+    match Dill::start(pier, &drivers) {
+        Ok(dill) => {
+            drivers.register(Box::new(dill));
+        }
+        Err(err) => return fail("run", err),
+    }
+    let iris = Iris::new(Ureq, IRIS_TIMEOUT, &drivers);
+    drivers.register(Box::new(iris));
+    match Clay::start(pier, &drivers) {
+        Ok(clay) => {
+            drivers.register(Box::new(clay));
+        }
+        Err(err) => return fail("run", err),
+    }
+    if let Some((ip, port)) = http {
+        match Eyre::start(IpAddr::V4(ip), port, &drivers) {
+            Ok(eyre) => {
+                println!("http: serving on {}:{}", ip, eyre.port());
+                drivers.register(Box::new(eyre));
+            }
+            Err(err) => return fail("run", err),
+        }
+    }
+    let peers = match ames.map(|port| start_ames(&king, port, &mut drivers)) {
+        Some(Ok((port, peers))) => {
+            println!("ames: listening on port {}", port);
+            Some(peers)
+        }
+        Some(Err(err)) => return fail("run", err),
+        None => None,
+    };
+    match Khan::start(pier, &drivers, peers) {
+        Ok(khan) => {
+            drivers.register(Box::new(khan));
+        }
+        Err(err) => return fail("run", err),
+    }
+    let _scry = match Scry::start(pier, &drivers) {
+        Ok(scry) => scry,
+        Err(err) => return fail("run", err),
+    };
+    let ran = king.run(&mut drivers);
+    // Restore the terminal before anything else is printed.
+    drop(drivers);
+    match ran.and_then(|()| king.snapshot()) {
+        Ok(()) => 0,
+        Err(err) => fail("run", err),
+    }
+}
+
+/// Start the networking driver on a port, by default any free port or, for a galaxy, the port
+/// other ships expect it on, producing the port and the driver's packet counts.
+fn start_ames(
+    king: &King,
+    port: Option<u16>,
+    drivers: &mut Drivers,
+) -> Result<(u16, Peers), Error> {
+    let identity = king.identity()?;
+    let fake = identity.as_ref().is_some_and(|identity| identity.fake);
+    let galaxy = identity.and_then(|identity| match identity.who {
+        Atom::Direct(who) => u8::try_from(who).ok(),
+        Atom::Indirect(_) => None,
+    });
+    let port = match (port, galaxy) {
+        (Some(port), _) => port,
+        (None, Some(galaxy)) if fake => FAKE_PORT + u16::from(galaxy),
+        (None, Some(galaxy)) => PORT + u16::from(galaxy),
+        (None, None) => 0,
+    };
+    let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port);
+    let (ames, port, peers): (Box<dyn Driver>, _, _) = if fake {
+        let ames = Ames::start(addr, FakeCzars, drivers)?;
+        let (port, peers) = (ames.port()?, ames.peers());
+        (Box::new(ames), port, peers)
+    } else {
+        let ames = Ames::start(addr, DnsCzars::default(), drivers)?;
+        let (port, peers) = (ames.port()?, ames.peers());
+        (Box::new(ames), port, peers)
+    };
+    drivers.register(ames);
+    Ok((port, peers))
+}
+
+/// Boot a pier and snapshot it.
+fn snapshot(args: &[String]) -> i32 {
+    let (pier, loom) = match pier_args(args) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let live = configure(pier, loom)
+        .and_then(|_| Pier::boot(pier, &mut progress))
+        .and_then(|mut live| live.snapshot().map(|_| live));
+    match live {
+        Ok(live) => {
+            println!(
+                "saved snapshot of event {}, mug {:#x}",
+                live.evt_num(),
+                live.kernel().mug()
+            );
+            0
+        }
+        Err(err) => fail("snapshot", err),
+    }
+}
+
+/// Start a new epoch of a pier's event log.
+fn roll(args: &[String]) -> i32 {
+    let (pier, loom) = match pier_args(args) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let epoch = configure(pier, loom)
+        .and_then(|_| Pier::boot(pier, &mut progress))
+        .and_then(|mut live| live.rollover());
+    match epoch {
+        Ok(epoch) => {
+            println!("started epoch from event {}", epoch.first);
+            0
+        }
+        Err(err) => fail("roll", err),
+    }
+}
+
+/// Run a mark-and-sweep pass over a pier's snapshot, optionally packing it and counting its
+/// duplicate subtrees.
+fn sweep(args: &[String]) -> i32 {
+    let mut pier = None;
+    let (mut pack, mut dups, mut loom) = (false, false, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pack" => pack = true,
+            "--duplicates" => dups = true,
+            "--loom" => match args.next().map(|size| parse_size(size)) {
+                Some(Ok(size)) => loom = Some(size),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let pier = match pier {
+        Some(pier) => pier,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let _lock = match Lock::acquire(pier) {
+        Ok(lock) => lock,
+        Err(err) => return fail("sweep", err),
+    };
+    if let Err(err) = configure(pier, loom) {
+        return fail("sweep", err);
+    }
+
+    let base = Loom::stats();
+    let mut snap = match Snapshot::load(pier) {
+        Ok(snap) => snap,
+        Err(err) => return fail("sweep", err),
+    };
+    let live = Loom::stats().since(&base);
+    let mut marker = Marker::new();
+    marker.root("kernel", &snap.kernel);
+    let report = marker.sweep(live);
+
+    for root in &report.roots {
+        println!(
+            "{}: {} allocations, {} bytes",
+            root.name, root.allocs, root.bytes
+        );
+    }
+    println!(
+        "leaked: {} allocations, {} bytes",
+        report.leaked.allocs, report.leaked.bytes
+    );
+
+    if dups {
+        let noun = Noun::from(snap.kernel);
+        let dups = duplicates(&noun);
+        println!(
+            "duplicates: {} subtrees, {} bytes",
+            dups.duplicates, dups.bytes
+        );
+        snap.kernel = Kernel::try_from(noun).unwrap();
+    }
+    if pack {
+        let before = Loom::stats();
+        snap.kernel = snap.kernel.pack();
+        let after = Loom::stats();
+        println!("pack: {} -> {} loom bytes", before.bytes, after.bytes);
+        if let Err(err) = snap.save(pier) {
+            return fail("sweep", err);
+        }
+    }
+
+    if report.is_clean() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Remove every event after a given event from a pier's event log.
+fn truncate(args: &[String]) -> i32 {
+    let (mut pier, mut evt_num, mut dry_run) = (None, None, false);
+    for arg in args {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ if evt_num.is_none() => match arg.parse::<u64>() {
+                Ok(num) => evt_num = Some(num),
+                Err(_) => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let (pier, evt_num) = match (pier, evt_num) {
+        (Some(pier), Some(evt_num)) => (pier, evt_num),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let trunc = match pier::truncate(pier, evt_num, dry_run) {
+        Ok(trunc) => trunc,
+        Err(err) => return fail("truncate", err),
+    };
+    let verb = if dry_run { "would remove" } else { "removed" };
+    if trunc.evt_num == trunc.last {
+        println!("no events after event {}", trunc.evt_num);
+    } else {
+        println!("{} events {}..={}", verb, trunc.evt_num + 1, trunc.last);
+    }
+    if let Some(snap) = trunc.snapshot {
+        println!("{} snapshot of event {}", verb, snap);
+    }
+    0
+}
+
+/// Check a pier's event log and snapshots, printing findings as JSON lines.
+fn verify(args: &[String]) -> i32 {
+    let (mut pier, mut snap, mut replay) = (None, None, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => replay = true,
+            "--snapshot" => match args.next() {
+                Some(arg) => snap = Some(Path::new(arg)),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return 2;
+                }
+            },
+            _ if pier.is_none() => pier = Some(Path::new(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let pier = match pier {
+        Some(pier) => pier,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let summary = verify::verify(pier, snap, replay, &mut |finding| {
+        println!("{}", finding.to_json())
+    });
+    match summary {
+        Ok(summary) => {
+            println!("{}", summary.to_json());
+            if 0 == summary.failures {
+                0
+            } else {
+                1
+            }
+        }
+        Err(err) => fail("verify", err),
+    }
 }
@@ -0,0 +1,180 @@
+use crate::error::{Context, Error};
+use loom::Loom;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Smallest loom size: 2 GiB.
+pub const MIN_LOOM: u64 = 2 << 30;
+
+/// Largest loom size: 64 GiB.
+pub const MAX_LOOM: u64 = 64 << 30;
+
+/// Runtime configuration stored in a pier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Maximum loom size in bytes.
+    pub loom: u64,
+    /// Percentage of the loom above which the kernel is packed before further events are
+    /// processed.
+    pub high_water: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            loom: MIN_LOOM,
+            high_water: 90,
+        }
+    }
+}
+
+impl Config {
+    /// Get the path of a pier's configuration.
+    pub fn path(pier: &Path) -> PathBuf {
+        pier.join(".urb").join("conf")
+    }
+
+    /// Load a pier's configuration, falling back to the default if the pier has none.
+    pub fn load(pier: &Path) -> Result<Self, Error> {
+        let path = Self::path(pier);
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).at_path(&path),
+            Err(err) if ErrorKind::NotFound == err.kind() => Ok(Self::default()),
+            Err(err) => Err(err).at_path(&path),
+        }
+    }
+
+    /// Parse a configuration's `key=value` lines.
+    fn parse(text: &str) -> Result<Self, Error> {
+        let mut conf = Self::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line.split_once('=') {
+                Some(("loom", val)) => conf.set_loom(parse_size(val)?)?,
+                Some(("high-water", val)) => conf.set_high_water(
+                    val.parse()
+                        .map_err(|_| Error::BadConfig(format!("bad high-water {}", val)))?,
+                )?,
+                _ => return Err(Error::BadConfig(format!("bad line {}", line))),
+            }
+        }
+        Ok(conf)
+    }
+
+    /// Save a pier's configuration.
+    pub fn save(&self, pier: &Path) -> Result<(), Error> {
+        let path = Self::path(pier);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).at_path(dir)?;
+        }
+        fs::write(
+            &path,
+            format!("loom={}\nhigh-water={}\n", self.loom, self.high_water),
+        )
+        .at_path(&path)
+    }
+
+    /// Set the loom size, which must be between [`MIN_LOOM`] and [`MAX_LOOM`].
+    pub fn set_loom(&mut self, loom: u64) -> Result<(), Error> {
+        if !(MIN_LOOM..=MAX_LOOM).contains(&loom) {
+            return Err(Error::BadConfig(format!(
+                "loom size {} is outside of {}..={}",
+                loom, MIN_LOOM, MAX_LOOM
+            )));
+        }
+        self.loom = loom;
+        Ok(())
+    }
+
+    /// Set the high-water mark as a percentage of the loom.
+    pub fn set_high_water(&mut self, high_water: u8) -> Result<(), Error> {
+        if !(1..=100).contains(&high_water) {
+            return Err(Error::BadConfig(format!(
+                "high-water {}% is outside of 1..=100",
+                high_water
+            )));
+        }
+        self.high_water = high_water;
+        Ok(())
+    }
+
+    /// Get the number of live loom bytes above which the loom is under pressure.
+    pub fn high_water_bytes(&self) -> u64 {
+        self.loom * u64::from(self.high_water) / 100
+    }
+
+    /// Limit the loom to this configuration.
+    pub fn apply(&self) {
+        let to_usize = |bytes| usize::try_from(bytes).unwrap_or(usize::MAX);
+        Loom::set_cap(Some(to_usize(self.loom)));
+        Loom::set_high_water(Some(to_usize(self.high_water_bytes())));
+    }
+}
+
+/// Parse a byte count with an optional binary unit suffix, e.g. `8G`, `8GiB` or `512M`.
+pub fn parse_size(size: &str) -> Result<u64, Error> {
+    let bad = || Error::BadConfig(format!("bad size {}", size));
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let shift = match &size[digits.len()..] {
+        "" | "B" => 0,
+        "K" | "KiB" => 10,
+        "M" | "MiB" => 20,
+        "G" | "GiB" => 30,
+        _ => return Err(bad()),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(bad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn parse() {
+        assert_eq!(2 << 30, parse_size("2G").unwrap());
+        assert_eq!(8 << 30, parse_size("8GiB").unwrap());
+        assert_eq!(512 << 20, parse_size("512M").unwrap());
+        assert_eq!(4096, parse_size("4096").unwrap());
+        assert!(parse_size("8T").is_err());
+        assert!(parse_size("G").is_err());
+    }
+
+    #[test]
+    fn save_load() {
+        let pier = env::temp_dir().join(format!("vere-config-{}", process::id()));
+
+        // A pier without a configuration gets the default.
+        {
+            assert_eq!(Config::default(), Config::load(&pier).unwrap());
+        }
+
+        // Load what was saved.
+        {
+            let mut conf = Config::default();
+            conf.set_loom(8 << 30).unwrap();
+            conf.set_high_water(75).unwrap();
+            conf.save(&pier).unwrap();
+            assert_eq!(conf, Config::load(&pier).unwrap());
+            assert_eq!(6 << 30, conf.high_water_bytes());
+        }
+
+        // Reject out of range values.
+        {
+            let mut conf = Config::default();
+            assert!(conf.set_loom(1 << 30).is_err());
+            assert!(conf.set_loom(128 << 30).is_err());
+            assert!(conf.set_high_water(0).is_err());
+            fs::write(Config::path(&pier), "loom=1G\n").unwrap();
+            assert!(Config::load(&pier).is_err());
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
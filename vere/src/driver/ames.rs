@@ -0,0 +1,450 @@
+//! Ames, the networking driver, which carries Arvo's `%ames` packets over UDP.
+//!
+//! Ames sends a packet with `[%send lane blob]`, where a lane is either `[%& galaxy]` for a
+//! galaxy, whose address is looked up through a [`Czars`] hook such as [`DnsCzars`] for the
+//! live network or [`FakeCzars`] for fake ships, or `[%| address]` for an IPv4
+//! address, given as `(con (lsh 5 port) ip)`. Every packet the driver hears becomes a
+//! `[//ames %hear [%| address] blob]` event, and the driver announces itself with a
+//! `[//ames %born ~]` event when it starts. Packets sent and heard are counted per peer, and the
+//! counts can be read through [`Khan`](crate::driver::khan::Khan) while the driver runs.
+
+use crate::{
+    driver::{Driver, Drivers, Effect},
+    error::Error,
+    noun::tagged,
+};
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Port a fake galaxy listens on, plus the galaxy's number.
+pub const FAKE_PORT: u16 = 31337;
+
+/// Port a galaxy on the live network listens on, plus the galaxy's number.
+pub const PORT: u16 = 13337;
+
+/// Domain under which galaxies on the live network are named, as in the C runtime.
+pub const DNS_DOMAIN: &str = "urbit.org";
+
+/// How long a galaxy's address is used before it's looked up again.
+const DNS_TTL: Duration = Duration::from_secs(300);
+
+/// The names of galaxies, which are the suffix syllables of `@p`.
+const GALAXIES: &str = "\
+    zodnecbudwessevpersutletfulpensytdurwepserwylsunrypsyxdyrnuphebpeglupdepdysputlughecryttyv\
+    sydnexlunmeplutseppesdelsulpedtemledtulmetwenbynhexfebpyldulhetmevruttylwydtepbesdexsefwyc\
+    burderneppurrysrebdennutsubpetrulsynregtydsupsemwynrecmegnetsecmulnymtevwebsummutnyxrextebf\
+    ushepbenmuswyxsymselrucdecwexsyrwetdylmynmesdetbetbeltuxtugmyrpelsyptermebsetdutdegtexsurfe\
+    ltudnuxruxrenwytnubmedlytdusnebrumtynseglyxpunresredfunrevrefmectedrusbexlebduxrynnumpyxryg\
+    ryxfeptyrtustyclegnemfermertenlusnussyltecmexpubrymtucfyllepdebbermughuttunbylsudpemdevlurd\
+    efbusbeprunmelpexdytbyttyplevmylwedducfurfexnulluclennerlexrupnedlecrydlydfenwelnydhusrelru\
+    dneshesfetdesretdunlernyrsebhulrylludremlysfynwerrycsugnysnyllyndyndemluxfedsedbecmunlyrtes\
+    mudnytbyrsenwegfyrmurtelreptegpecnelnevfes";
+
+/// Get a galaxy's name, without its sig, e.g. `zod` for galaxy 0.
+pub fn galaxy_name(galaxy: u8) -> &'static str {
+    let start = 3 * usize::from(galaxy);
+    &GALAXIES[start..start + 3]
+}
+
+/// Largest packet heard.
+const MAX_PACKET: usize = 8192;
+
+/// Make an `//ames` event.
+fn event(card: Noun) -> Noun {
+    Noun::from(Effect {
+        wire: vec![String::new(), "ames".to_string()],
+        card,
+    })
+}
+
+/// Make an address as a lane gives it, which is `(con (lsh 5 port) ip)`.
+pub(crate) fn to_address(addr: SocketAddrV4) -> Noun {
+    Noun::from(u64::from(addr.port()) << 32 | u64::from(u32::from(*addr.ip())))
+}
+
+/// Parse an address as a lane gives it.
+fn from_address(address: u64) -> SocketAddrV4 {
+    SocketAddrV4::new(Ipv4Addr::from(address as u32), (address >> 32) as u16)
+}
+
+/// A hook that looks up galaxies' addresses.
+pub trait Czars {
+    /// Get a galaxy's address, if it's known.
+    fn lane(&mut self, galaxy: u8) -> Option<SocketAddrV4>;
+}
+
+/// The galaxies of fake ships, which listen on the loopback interface at [`FAKE_PORT`] plus
+/// their number.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FakeCzars;
+
+impl Czars for FakeCzars {
+    fn lane(&mut self, galaxy: u8) -> Option<SocketAddrV4> {
+        Some(SocketAddrV4::new(
+            Ipv4Addr::LOCALHOST,
+            FAKE_PORT + u16::from(galaxy),
+        ))
+    }
+}
+
+/// Galaxies at fixed addresses.
+impl Czars for HashMap<u8, SocketAddrV4> {
+    fn lane(&mut self, galaxy: u8) -> Option<SocketAddrV4> {
+        self.get(&galaxy).copied()
+    }
+}
+
+/// The galaxies of the live network, which are looked up in DNS as in the C runtime: galaxy
+/// `~zod` is at `zod.urbit.org`, listening on [`PORT`] plus its number.
+///
+/// Lookups run on their own threads so that sending never waits on DNS. Until a galaxy's first
+/// lookup finishes, it has no lane. An address is looked up again once it's [`DNS_TTL`] old,
+/// and is used until the new lookup succeeds.
+#[derive(Clone, Debug)]
+pub struct DnsCzars {
+    domain: String,
+    /// Addresses found, and when they were looked up.
+    found: Arc<Mutex<HashMap<u8, (SocketAddrV4, Instant)>>>,
+    /// Galaxies being looked up.
+    looking: Arc<Mutex<HashSet<u8>>>,
+}
+
+impl DnsCzars {
+    /// Look up galaxies under a domain.
+    pub fn new(domain: &str) -> Self {
+        Self {
+            domain: domain.to_string(),
+            found: Arc::default(),
+            looking: Arc::default(),
+        }
+    }
+
+    /// Look up a galaxy's address in DNS.
+    fn resolve(domain: &str, galaxy: u8) -> Option<SocketAddrV4> {
+        let host = format!("{}.{}", galaxy_name(galaxy), domain);
+        let addrs = match (host.as_str(), PORT + u16::from(galaxy)).to_socket_addrs() {
+            Ok(addrs) => addrs,
+            Err(err) => {
+                eprintln!("ames: can't look up {}: {}", host, err);
+                return None;
+            }
+        };
+        addrs.into_iter().find_map(|addr| match addr {
+            SocketAddr::V4(addr) => Some(addr),
+            SocketAddr::V6(_) => None,
+        })
+    }
+}
+
+impl Default for DnsCzars {
+    fn default() -> Self {
+        Self::new(DNS_DOMAIN)
+    }
+}
+
+impl Czars for DnsCzars {
+    fn lane(&mut self, galaxy: u8) -> Option<SocketAddrV4> {
+        let found = self.found.lock().unwrap().get(&galaxy).copied();
+        match found {
+            Some((addr, at)) if at.elapsed() < DNS_TTL => return Some(addr),
+            _ => {}
+        }
+        if self.looking.lock().unwrap().insert(galaxy) {
+            let (domain, found, looking) = (
+                self.domain.clone(),
+                self.found.clone(),
+                self.looking.clone(),
+            );
+            thread::spawn(move || {
+                if let Some(addr) = Self::resolve(&domain, galaxy) {
+                    found.lock().unwrap().insert(galaxy, (addr, Instant::now()));
+                }
+                looking.lock().unwrap().remove(&galaxy);
+            });
+        }
+        found.map(|(addr, _)| addr)
+    }
+}
+
+/// Where a `%send` card sends its packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Lane {
+    Galaxy(u8),
+    Addr(SocketAddrV4),
+}
+
+/// Parse a `%send` card's `[lane blob]`.
+fn parse_send(dat: Noun) -> Option<(Lane, Vec<u8>)> {
+    let (lane, blob) = match dat {
+        Noun::Cell(cell) => (*cell.head, *cell.tail),
+        Noun::Atom(_) => return None,
+    };
+    let lane = match lane {
+        Noun::Cell(cell) => match (*cell.head, *cell.tail) {
+            (Noun::Atom(Atom::Direct(0)), Noun::Atom(Atom::Direct(galaxy))) => {
+                Lane::Galaxy(u8::try_from(galaxy).ok()?)
+            }
+            (Noun::Atom(Atom::Direct(1)), Noun::Atom(Atom::Direct(address))) => {
+                Lane::Addr(from_address(address))
+            }
+            _ => return None,
+        },
+        Noun::Atom(_) => return None,
+    };
+    match blob {
+        Noun::Atom(blob) => Some((lane, blob.to_bytes())),
+        Noun::Cell(_) => None,
+    }
+}
+
+/// Counts of the packets exchanged with a peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub sent: u64,
+    pub sent_bytes: u64,
+    /// Packets that couldn't be sent.
+    pub failed: u64,
+    pub heard: u64,
+    pub heard_bytes: u64,
+}
+
+/// Counts of the packets exchanged with each peer, shared with whatever reports them.
+pub type Peers = Arc<Mutex<HashMap<SocketAddrV4, Stats>>>;
+
+/// The networking driver.
+pub struct Ames<C: Czars = FakeCzars> {
+    socket: UdpSocket,
+    czars: C,
+    /// Counts of packets by peer, shared with the thread that hears packets.
+    stats: Peers,
+}
+
+impl<C: Czars> Ames<C> {
+    /// Start listening on an address, looking up galaxies through `czars`. Each packet heard is
+    /// injected as a `%hear` event, after a `%born` event.
+    pub fn start(addr: SocketAddrV4, czars: C, drivers: &Drivers) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(addr)?;
+        let stats = Peers::default();
+        let injector = drivers.injector();
+        injector.inject(event(tagged("born", Noun::from(0))));
+        let hearing = (socket.try_clone()?, stats.clone());
+        thread::spawn(move || {
+            let (socket, stats) = hearing;
+            let mut buf = [0; MAX_PACKET];
+            loop {
+                let (len, peer) = match socket.recv_from(&mut buf) {
+                    Ok((len, SocketAddr::V4(peer))) => (len, peer),
+                    Ok((_, SocketAddr::V6(_))) => continue,
+                    Err(err) => {
+                        eprintln!("ames: failed to hear a packet: {}", err);
+                        continue;
+                    }
+                };
+                {
+                    let mut stats = stats.lock().unwrap();
+                    let peer = stats.entry(peer).or_default();
+                    peer.heard += 1;
+                    peer.heard_bytes += len as u64;
+                }
+                let lane = Noun::from((Noun::from(1), to_address(peer)));
+                let blob = Noun::from(Atom::from_bytes(&buf[..len]));
+                if !injector.inject(event(tagged("hear", Noun::from((lane, blob))))) {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            socket,
+            czars,
+            stats,
+        })
+    }
+
+    /// Get the port the driver is listening on.
+    pub fn port(&self) -> Result<u16, Error> {
+        Ok(self.socket.local_addr()?.port())
+    }
+
+    /// Get the counts of the packets exchanged with each peer.
+    pub fn stats(&self) -> HashMap<SocketAddrV4, Stats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Get a handle on the counts of the packets exchanged with each peer, which keeps
+    /// counting after the driver is handed off.
+    pub fn peers(&self) -> Peers {
+        self.stats.clone()
+    }
+
+    /// Send a packet, counting it against the peer. A packet that can't be sent is only
+    /// counted, since the network is unreliable anyway.
+    fn send(&mut self, lane: Lane, blob: &[u8]) {
+        let peer = match lane {
+            Lane::Addr(peer) => peer,
+            Lane::Galaxy(galaxy) => match self.czars.lane(galaxy) {
+                Some(peer) => peer,
+                None => {
+                    eprintln!("ames: no lane for galaxy {}", galaxy);
+                    return;
+                }
+            },
+        };
+        let sent = self.socket.send_to(blob, peer);
+        let mut stats = self.stats.lock().unwrap();
+        let stats = stats.entry(peer).or_default();
+        match sent {
+            Ok(len) => {
+                stats.sent += 1;
+                stats.sent_bytes += len as u64;
+            }
+            Err(_) => stats.failed += 1,
+        }
+    }
+}
+
+impl<C: Czars> Driver for Ames<C> {
+    fn name(&self) -> &str {
+        "ames"
+    }
+
+    /// Send a `%send` card's packet. Other cards are logged and ignored.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+        let send = match (effect.tag().as_deref(), &effect.card) {
+            (Some("send"), Noun::Cell(cell)) => parse_send(cell.tail.as_ref().clone()),
+            _ => None,
+        };
+        match send {
+            Some((lane, blob)) => self.send(lane, &blob),
+            None => eprintln!("ames: ignoring effect {}", effect),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(lane: Noun, blob: &str) -> Effect {
+        Effect {
+            wire: vec![String::new(), "ames".to_string()],
+            card: tagged("send", Noun::from((lane, Noun::from(Atom::from(blob))))),
+        }
+    }
+
+    #[test]
+    fn czars() {
+        assert_eq!(
+            ["zod", "nec", "fes"],
+            [galaxy_name(0), galaxy_name(1), galaxy_name(255)]
+        );
+
+        // A galaxy that can't be looked up has no lane, unless one was found before, which is
+        // used while the galaxy is looked up again.
+        let mut czars = DnsCzars::new("invalid");
+        let settle = |czars: &DnsCzars| {
+            while !czars.looking.lock().unwrap().is_empty() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        assert_eq!(None, czars.lane(0));
+        settle(&czars);
+        assert_eq!(None, czars.lane(0));
+        let old = SocketAddrV4::new(Ipv4Addr::LOCALHOST, PORT);
+        let stale = Instant::now() - 2 * DNS_TTL;
+        czars.found.lock().unwrap().insert(0, (old, stale));
+        assert_eq!(Some(old), czars.lane(0));
+        settle(&czars);
+        assert_eq!(Some(old), czars.lane(0));
+    }
+
+    #[test]
+    fn send_hear() {
+        let loopback = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0);
+        let (mut a_drivers, mut b_drivers) = (Drivers::new(), Drivers::new());
+        let mut b = Ames::start(loopback, FakeCzars, &b_drivers).unwrap();
+        let b_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, b.port().unwrap());
+        let mut a = Ames::start(loopback, HashMap::from([(7, b_addr)]), &a_drivers).unwrap();
+        let a_addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, a.port().unwrap());
+        let born = event(tagged("born", Noun::from(0)));
+        assert_eq!(Some(born.clone()), a_drivers.wait());
+        assert_eq!(Some(born), b_drivers.wait());
+
+        // Packets reach their peers whether sent to an address or to a galaxy.
+        a.handle(send(
+            Noun::from((Noun::from(1), to_address(b_addr))),
+            "ping",
+        ))
+        .unwrap();
+        a.handle(send(Noun::from((Noun::from(0), Noun::from(7))), "ping"))
+            .unwrap();
+        let hear = event(tagged(
+            "hear",
+            Noun::from((
+                Noun::from((Noun::from(1), to_address(a_addr))),
+                Noun::from(Atom::from("ping")),
+            )),
+        ));
+        assert_eq!(Some(hear.clone()), b_drivers.wait());
+        assert_eq!(Some(hear), b_drivers.wait());
+        b.handle(send(
+            Noun::from((Noun::from(1), to_address(a_addr))),
+            "pong",
+        ))
+        .unwrap();
+        assert!(matches!(a_drivers.wait(), Some(Noun::Cell(_))));
+
+        // A galaxy without a lane and a malformed card send nothing.
+        a.handle(send(Noun::from((Noun::from(0), Noun::from(8))), "ping"))
+            .unwrap();
+        a.handle(send(Noun::from((Noun::from(2), Noun::from(0))), "ping"))
+            .unwrap();
+        assert_eq!(
+            HashMap::from([(
+                b_addr,
+                Stats {
+                    sent: 2,
+                    sent_bytes: 8,
+                    failed: 0,
+                    heard: 1,
+                    heard_bytes: 4,
+                }
+            )]),
+            a.stats()
+        );
+        assert_eq!(
+            Some(&Stats {
+                sent: 1,
+                sent_bytes: 4,
+                failed: 0,
+                heard: 2,
+                heard_bytes: 8,
+            }),
+            b.stats().get(&a_addr)
+        );
+
+        // The counts can be read through a handle after the driver is handed off.
+        let peers = a.peers();
+        drop(a);
+        assert_eq!(1, peers.lock().unwrap()[&b_addr].heard);
+    }
+
+    #[test]
+    fn address() {
+        let addr = SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 31337);
+        match to_address(addr) {
+            Noun::Atom(Atom::Direct(address)) => assert_eq!(addr, from_address(address)),
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            Some(SocketAddrV4::new(Ipv4Addr::LOCALHOST, FAKE_PORT + 1)),
+            FakeCzars.lane(1)
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Behn, the timer driver, which wakes Arvo's `%behn` vane when the timer it set fires.
+//!
+//! Behn sets its timer with `[%doze ~ date]` and cancels it with `[%doze ~]`, and the driver
+//! wakes it with a `[//behn %wake ~]` event once the timer's date has passed.
+
+use crate::{
+    driver::{Driver, Effect},
+    error::Error,
+    time,
+};
+use nock::{atom::Atom, noun::Noun};
+use std::time::{Duration, SystemTime};
+
+/// A source of the current time.
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system's clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// The timer driver, which has at most one timer set.
+pub struct Behn<C: Clock = SystemClock> {
+    clock: C,
+    /// When the timer fires, if it's set.
+    deadline: Option<SystemTime>,
+}
+
+impl<C: Clock> Behn<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            deadline: None,
+        }
+    }
+
+    /// Get when the timer fires, if it's set.
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.deadline
+    }
+
+    /// Parse a `%doze` card's `(unit @da)`, producing the deadline it sets, if any. A date
+    /// before the Unix epoch fires immediately, and one too far in the future to represent
+    /// never fires.
+    fn doze(dat: Noun) -> Option<Option<SystemTime>> {
+        match dat {
+            Noun::Atom(Atom::Direct(0)) => Some(None),
+            Noun::Cell(cell) if Noun::from(0) == *cell.head => match *cell.tail {
+                Noun::Atom(date) => match time::from_date(&date) {
+                    Some(deadline) => Some(Some(deadline)),
+                    None if time::before_epoch(&date) => Some(Some(SystemTime::UNIX_EPOCH)),
+                    None => {
+                        eprintln!("behn: not setting a timer for {}", Noun::Atom(date));
+                        Some(None)
+                    }
+                },
+                Noun::Cell(_) => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl<C: Clock> Driver for Behn<C> {
+    fn name(&self) -> &str {
+        "behn"
+    }
+
+    /// Set or cancel the timer as a `%doze` card asks. Other cards are logged and ignored.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+        let doze = match (effect.tag().as_deref(), &effect.card) {
+            (Some("doze"), Noun::Cell(cell)) => Self::doze(cell.tail.as_ref().clone()),
+            _ => None,
+        };
+        match doze {
+            Some(deadline) => self.deadline = deadline,
+            None => eprintln!("behn: ignoring effect {}", effect),
+        }
+        Ok(())
+    }
+
+    /// Wake Behn if its timer has fired, which unsets the timer.
+    fn poll(&mut self) -> Vec<Noun> {
+        match self.deadline {
+            Some(deadline) if deadline <= self.clock.now() => {
+                self.deadline = None;
+                vec![Noun::from(Effect {
+                    wire: vec![String::new(), "behn".to_string()],
+                    card: Noun::from((Noun::from(Atom::from("wake")), Noun::from(0))),
+                })]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        let deadline = self.deadline?;
+        Some(
+            deadline
+                .duration_since(self.clock.now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::Drivers;
+    use std::{cell::Cell, rc::Rc};
+
+    /// A clock that only moves when it's told to.
+    #[derive(Clone)]
+    struct FakeClock(Rc<Cell<SystemTime>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> SystemTime {
+            self.0.get()
+        }
+    }
+
+    fn doze(date: Option<SystemTime>) -> Effect {
+        let dat = match date {
+            Some(date) => Noun::from((Noun::from(0), Noun::from(time::to_date(date)))),
+            None => Noun::from(0),
+        };
+        Effect {
+            wire: vec![String::new(), "behn".to_string()],
+            card: Noun::from((Noun::from(Atom::from("doze")), dat)),
+        }
+    }
+
+    #[test]
+    fn doze_wake() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_641_038_400);
+        let clock = FakeClock(Rc::new(Cell::new(start)));
+        let mut behn = Behn::new(clock.clone());
+        assert_eq!((None, None), (behn.timeout(), behn.deadline()));
+
+        // A timer fires once its deadline passes, and only once.
+        {
+            behn.handle(doze(Some(start + Duration::from_secs(5))))
+                .unwrap();
+            assert_eq!(Some(Duration::from_secs(5)), behn.timeout());
+            assert!(behn.poll().is_empty());
+            clock.0.set(start + Duration::from_secs(5));
+            assert_eq!(Some(Duration::ZERO), behn.timeout());
+            let wake = behn.poll();
+            assert_eq!(1, wake.len());
+            let wake = Effect::try_from(wake[0].clone()).unwrap();
+            assert_eq!(
+                (Some("behn"), Some("wake".to_string())),
+                (wake.driver(), wake.tag())
+            );
+            assert!(behn.poll().is_empty());
+        }
+
+        // A cancelled timer doesn't fire, and a malformed doze changes nothing.
+        {
+            behn.handle(doze(Some(start + Duration::from_secs(10))))
+                .unwrap();
+            behn.handle(Effect {
+                wire: vec!["behn".to_string()],
+                card: Noun::from((Noun::from(Atom::from("doze")), Noun::from(1))),
+            })
+            .unwrap();
+            assert!(behn.deadline().is_some());
+            behn.handle(doze(None)).unwrap();
+            clock.0.set(start + Duration::from_secs(10));
+            assert!(behn.poll().is_empty());
+            assert_eq!(None, behn.timeout());
+        }
+
+        // A date before the Unix epoch fires now, and one past any system time never fires.
+        {
+            let at = |date: Atom| Effect {
+                wire: vec![String::new(), "behn".to_string()],
+                card: Noun::from((
+                    Noun::from(Atom::from("doze")),
+                    Noun::from((Noun::from(0), Noun::from(date))),
+                )),
+            };
+            behn.handle(at(Atom::from(1))).unwrap();
+            assert_eq!(Some(SystemTime::UNIX_EPOCH), behn.deadline());
+            behn.handle(at(Atom::from_limbs(vec![0, 0, 1]))).unwrap();
+            assert_eq!(None, behn.deadline());
+        }
+    }
+
+    #[test]
+    fn drivers() {
+        let mut drivers = Drivers::new();
+        let injector = drivers.injector();
+        drivers.register(Box::new(Behn::new(SystemClock)));
+        drivers
+            .dispatch(vec![doze(Some(SystemTime::now()))])
+            .unwrap();
+
+        // The injected event and the wake both come through, and then nothing can arrive.
+        injector.inject(Noun::from(1));
+        drop(injector);
+        let mut evts = [drivers.wait().unwrap(), drivers.wait().unwrap()];
+        evts.sort_by_key(|evt| matches!(evt, Noun::Cell(_)));
+        assert_eq!(Noun::from(1), evts[0]);
+        assert_eq!(
+            Some("behn"),
+            Effect::try_from(evts[1].clone()).unwrap().driver()
+        );
+        assert_eq!(None, drivers.wait());
+    }
+}
@@ -0,0 +1,555 @@
+//! Clay's filesystem sync driver, which mirrors desks mounted by Arvo's `%clay` vane into
+//! directories of the pier and commits the changes made to those directories.
+//!
+//! Clay writes a mount's files with `[%ergo mount mode]`, where a mode is a list of
+//! `[path (unit mime)]` that writes a file for each mime and deletes it for each `~`, and
+//! removes a mount with `[%ogre mount]`. `[%hill (list mount)]` names the mounts that exist
+//! when the ship starts, and `[%dirk mount]` asks for a mount's changes to be committed. Each
+//! mount's directory is watched with inotify, and changes to its files are committed with a
+//! `[//sync %into mount %.n mode]` event, giving each file a text or binary mime type
+//! depending on its extension. A path's last segment is its file's extension, so
+//! `/app/hood/hoon` is `<pier>/<mount>/app/hood.hoon`, and files without an extension and
+//! hidden files are ignored.
+
+use crate::{
+    driver::{Driver, Drivers, Effect, Injector},
+    error::{Context, Error},
+    noun::{cord, text},
+};
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    ffi::CString,
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, ErrorKind},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// How long changes are left to settle before they're committed.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Changes inotify watches for.
+const WATCH: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF;
+
+/// Extensions of files whose mime type is `/text/plain`. Every other file is
+/// `/application/octet-stream`.
+const TEXT: &[&str] = &[
+    "bill", "css", "csv", "docket-0", "hoon", "html", "js", "json", "kelvin", "md", "mjs", "ship",
+    "svg", "txt", "udon", "umd", "xml",
+];
+
+/// Get the mime type of a file with an extension, as a `mite`.
+fn mite(ext: &str) -> Vec<&'static str> {
+    if TEXT.contains(&ext) {
+        vec!["text", "plain"]
+    } else {
+        vec!["application", "octet-stream"]
+    }
+}
+
+/// Make a `(unit mime)` for a file's contents, which is `[~ mite octs]`.
+fn mime(ext: &str, bytes: &[u8]) -> Noun {
+    let mite = mite(ext).into_iter().map(cord).collect();
+    let octs = Noun::from((
+        Noun::from(bytes.len() as u64),
+        Noun::from(Atom::from_bytes(bytes)),
+    ));
+    Noun::from((Noun::from(0), Noun::from((Noun::from_list(mite), octs))))
+}
+
+/// Parse a `(unit mime)`, producing the file's contents if it's not deleted.
+fn parse_mime(noun: Noun) -> Option<Option<Vec<u8>>> {
+    match noun {
+        Noun::Atom(Atom::Direct(0)) => Some(None),
+        Noun::Cell(cell) if Noun::from(0) == *cell.head => {
+            let octs = match *cell.tail {
+                Noun::Cell(mime) => *mime.tail,
+                Noun::Atom(_) => return None,
+            };
+            let mut octs = octs.into_tuple(2).ok()?.into_iter();
+            let len = match octs.next()? {
+                Noun::Atom(Atom::Direct(len)) => usize::try_from(len).ok()?,
+                _ => return None,
+            };
+            let mut bytes = match octs.next()? {
+                Noun::Atom(atom) => atom.to_bytes(),
+                Noun::Cell(_) => return None,
+            };
+            bytes.resize(len, 0);
+            Some(Some(bytes))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a `path`, which must have at least a name and an extension and no segment that could
+/// leave the mount.
+fn parse_path(noun: Noun) -> Option<Vec<String>> {
+    let path = noun
+        .into_list()
+        .ok()?
+        .into_iter()
+        .map(|segment| text(&segment))
+        .collect::<Option<Vec<_>>>()?;
+    let safe = path
+        .iter()
+        .all(|segment| !segment.is_empty() && !segment.contains('/') && !segment.starts_with('.'));
+    if path.len() < 2 || !safe {
+        return None;
+    }
+    Some(path)
+}
+
+/// Changes to a mount's files: the contents of each file by path, or nothing for a file that's
+/// deleted.
+type Mode = Vec<(Vec<String>, Option<Vec<u8>>)>;
+
+/// Parse an `%ergo` card's `[mount mode]`.
+fn parse_ergo(dat: Noun) -> Option<(String, Mode)> {
+    let (mount, mode) = match dat {
+        Noun::Cell(cell) => (text(&cell.head)?, *cell.tail),
+        Noun::Atom(_) => return None,
+    };
+    let mode = mode
+        .into_list()
+        .ok()?
+        .into_iter()
+        .map(|change| match change {
+            Noun::Cell(cell) => Some((parse_path(*cell.head)?, parse_mime(*cell.tail)?)),
+            Noun::Atom(_) => None,
+        })
+        .collect::<Option<_>>()?;
+    Some((mount, mode))
+}
+
+/// Hash a file's contents.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The mounts, shared between the driver and the thread that watches them.
+struct Mounts {
+    pier: PathBuf,
+    /// The inotify instance's file descriptor.
+    inotify: i32,
+    /// The files of each mount as they were last synced with Clay, as hashes of their contents
+    /// by path.
+    files: HashMap<String, HashMap<Vec<String>, u64>>,
+    /// The mount each watch is in, by watch descriptor.
+    watches: HashMap<i32, String>,
+}
+
+impl Mounts {
+    /// Get the directory of a mount, which must be a name that stays in the pier.
+    fn dir(&self, mount: &str) -> Option<PathBuf> {
+        if mount.is_empty() || mount.contains('/') || mount.starts_with('.') {
+            return None;
+        }
+        Some(self.pier.join(mount))
+    }
+
+    /// Watch a directory of a mount.
+    fn watch(&mut self, dir: &Path, mount: &str) {
+        let path = match CString::new(dir.as_os_str().as_bytes()) {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        let wd = unsafe { libc::inotify_add_watch(self.inotify, path.as_ptr(), WATCH) };
+        if wd >= 0 {
+            self.watches.insert(wd, mount.to_string());
+        }
+    }
+
+    /// Read the files of a mount, watching each of its directories.
+    fn scan(&mut self, mount: &str) -> Result<HashMap<Vec<String>, Vec<u8>>, Error> {
+        let root = match self.dir(mount) {
+            Some(root) => root,
+            None => return Ok(HashMap::new()),
+        };
+        let mut files = HashMap::new();
+        let mut dirs = vec![(root, Vec::new())];
+        while let Some((dir, prefix)) = dirs.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(err) if ErrorKind::NotFound == err.kind() => continue,
+                Err(err) => return Err(err).at_path(&dir),
+            };
+            self.watch(&dir, mount);
+            for entry in entries {
+                let entry = entry.at_path(&dir)?;
+                let name = match entry.file_name().to_str() {
+                    Some(name) if !name.starts_with('.') => name.to_string(),
+                    _ => continue,
+                };
+                let path = entry.path();
+                let kind = entry.file_type().at_path(&path)?;
+                if kind.is_dir() {
+                    let mut prefix = prefix.clone();
+                    prefix.push(name);
+                    dirs.push((path, prefix));
+                } else if kind.is_file() {
+                    let (stem, ext) = match name.rsplit_once('.') {
+                        Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => (stem, ext),
+                        _ => continue,
+                    };
+                    let bytes = match fs::read(&path) {
+                        Ok(bytes) => bytes,
+                        Err(err) if ErrorKind::NotFound == err.kind() => continue,
+                        Err(err) => return Err(err).at_path(&path),
+                    };
+                    let mut key = prefix.clone();
+                    key.extend([stem.to_string(), ext.to_string()]);
+                    files.insert(key, bytes);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Find the changes to a mount's files since they were last synced, as a mode, and
+    /// consider them synced.
+    fn changes(&mut self, mount: &str) -> Result<Vec<Noun>, Error> {
+        let files = self.scan(mount)?;
+        let synced = self.files.entry(mount.to_string()).or_default();
+        let mut mode = Vec::new();
+        for (path, bytes) in &files {
+            let hashed = hash(bytes);
+            if synced.get(path) != Some(&hashed) {
+                synced.insert(path.clone(), hashed);
+                let ext = path.last().map(String::as_str).unwrap_or_default();
+                mode.push((path.clone(), mime(ext, bytes)));
+            }
+        }
+        synced.retain(|path, _| {
+            let kept = files.contains_key(path);
+            if !kept {
+                mode.push((path.clone(), Noun::from(0)));
+            }
+            kept
+        });
+        mode.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(mode
+            .into_iter()
+            .map(|(path, mime)| {
+                let path = path.iter().map(|segment| cord(segment)).collect();
+                Noun::from((Noun::from_list(path), mime))
+            })
+            .collect())
+    }
+
+    /// Make the `%into` event that commits a mount's changes, if it has any.
+    fn commit(&mut self, mount: &str) -> Result<Option<Noun>, Error> {
+        let mode = self.changes(mount)?;
+        if mode.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Noun::from(Effect {
+            wire: vec![String::new(), "sync".to_string()],
+            card: Noun::from_tuple(vec![
+                cord("into"),
+                cord(mount),
+                Noun::from(1),
+                Noun::from_list(mode),
+            ]),
+        })))
+    }
+
+    /// Write and delete a mount's files, considering them synced.
+    fn ergo(&mut self, mount: &str, mode: Mode) -> Result<(), Error> {
+        let root = match self.dir(mount) {
+            Some(root) => root,
+            None => {
+                eprintln!("clay: can't mount {}", mount);
+                return Ok(());
+            }
+        };
+        fs::create_dir_all(&root).at_path(&root)?;
+        if !self.files.contains_key(mount) {
+            self.changes(mount)?;
+        }
+        for (path, bytes) in mode {
+            let (ext, dirs) = path.split_last().unwrap();
+            let (name, dirs) = dirs.split_last().unwrap();
+            let dir = dirs
+                .iter()
+                .fold(root.clone(), |dir, segment| dir.join(segment));
+            let file = dir.join(format!("{}.{}", name, ext));
+            let synced = self.files.entry(mount.to_string()).or_default();
+            match bytes {
+                Some(bytes) => {
+                    fs::create_dir_all(&dir).at_path(&dir)?;
+                    fs::write(&file, &bytes).at_path(&file)?;
+                    synced.insert(path, hash(&bytes));
+                    self.watch(&dir, mount);
+                }
+                None => {
+                    match fs::remove_file(&file) {
+                        Err(err) if ErrorKind::NotFound != err.kind() => {
+                            return Err(err).at_path(&file)
+                        }
+                        _ => {}
+                    }
+                    synced.remove(&path);
+                    let mut dir = dir.as_path();
+                    while dir != root && fs::remove_dir(dir).is_ok() {
+                        dir = dir.parent().unwrap();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the watch descriptors of a buffer of inotify events.
+fn watches(buf: &[u8]) -> Vec<i32> {
+    const HEAD: usize = std::mem::size_of::<libc::inotify_event>();
+    let mut wds = Vec::new();
+    let mut at = 0;
+    while at + HEAD <= buf.len() {
+        let field = |offset: usize| [0, 1, 2, 3].map(|i| buf[at + offset + i]);
+        wds.push(i32::from_ne_bytes(field(0)));
+        at += HEAD + u32::from_ne_bytes(field(12)) as usize;
+    }
+    wds
+}
+
+/// The filesystem sync driver. Its name is `sync`, the first segment of the wires of Clay's
+/// sync effects.
+pub struct Clay {
+    mounts: Arc<Mutex<Mounts>>,
+    injector: Injector,
+}
+
+impl Clay {
+    /// Start watching for changes to the mounts in a pier, which are committed as they happen.
+    pub fn start(pier: &Path, drivers: &Drivers) -> Result<Self, Error> {
+        let inotify = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if inotify < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mounts = Arc::new(Mutex::new(Mounts {
+            pier: pier.to_path_buf(),
+            inotify,
+            files: HashMap::new(),
+            watches: HashMap::new(),
+        }));
+        let watched = mounts.clone();
+        let injector = drivers.injector();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            loop {
+                let len = unsafe { libc::read(inotify, buf.as_mut_ptr().cast(), buf.len()) };
+                if len < 0 {
+                    if ErrorKind::Interrupted == io::Error::last_os_error().kind() {
+                        continue;
+                    }
+                    return;
+                }
+                thread::sleep(SETTLE);
+                let mut mounts = watched.lock().unwrap();
+                let mut changed: Vec<_> = watches(&buf[..len as usize])
+                    .into_iter()
+                    .filter_map(|wd| mounts.watches.get(&wd).cloned())
+                    .collect();
+                changed.sort();
+                changed.dedup();
+                for mount in changed {
+                    match mounts.commit(&mount) {
+                        Ok(Some(into)) => {
+                            if !injector.inject(into) {
+                                return;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(err) => eprintln!("clay: failed to commit {}: {}", mount, err),
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            mounts,
+            injector: drivers.injector(),
+        })
+    }
+}
+
+impl Driver for Clay {
+    fn name(&self) -> &str {
+        "sync"
+    }
+
+    /// Carry out an `%ergo`, `%hill`, `%ogre` or `%dirk` card. Other cards are logged and
+    /// ignored.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+        let dat = match &effect.card {
+            Noun::Cell(cell) => cell.tail.as_ref().clone(),
+            Noun::Atom(_) => Noun::from(0),
+        };
+        let mut mounts = self.mounts.lock().unwrap();
+        match (effect.tag().as_deref(), dat) {
+            (Some("ergo"), dat) => match parse_ergo(dat) {
+                Some((mount, mode)) => mounts.ergo(&mount, mode)?,
+                None => eprintln!("clay: ignoring malformed effect {}", effect),
+            },
+            (Some("hill"), dat) => {
+                for mount in dat
+                    .into_list()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|mount| text(&mount))
+                {
+                    if let Some(dir) = mounts.dir(&mount) {
+                        fs::create_dir_all(&dir).at_path(&dir)?;
+                        mounts.changes(&mount)?;
+                    }
+                }
+            }
+            (Some("ogre"), Noun::Atom(mount)) => {
+                let mount = String::from_utf8(mount.to_bytes()).unwrap_or_default();
+                if let Some(dir) = mounts.dir(&mount) {
+                    mounts.files.remove(&mount);
+                    mounts.watches.retain(|_, watched| *watched != mount);
+                    match fs::remove_dir_all(&dir) {
+                        Err(err) if ErrorKind::NotFound != err.kind() => {
+                            return Err(err).at_path(&dir)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            (Some("dirk"), Noun::Atom(mount)) => {
+                let mount = String::from_utf8(mount.to_bytes()).unwrap_or_default();
+                if let Some(into) = mounts.commit(&mount)? {
+                    self.injector.inject(into);
+                }
+            }
+            _ => eprintln!("clay: ignoring effect {}", effect),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn path(segments: &[&str]) -> Noun {
+        Noun::from_list(segments.iter().map(|segment| cord(segment)).collect())
+    }
+
+    fn effect(card: Noun) -> Effect {
+        Effect {
+            wire: vec![String::new(), "sync".to_string()],
+            card,
+        }
+    }
+
+    fn into(mount: &str, mode: Vec<Noun>) -> Noun {
+        Noun::from(effect(Noun::from_tuple(vec![
+            cord("into"),
+            cord(mount),
+            Noun::from(1),
+            Noun::from_list(mode),
+        ])))
+    }
+
+    #[test]
+    fn sync() {
+        let pier = env::temp_dir().join(format!("vere-clay-{}", process::id()));
+        fs::create_dir_all(&pier).unwrap();
+        let mut drivers = Drivers::new();
+        let mut clay = Clay::start(&pier, &drivers).unwrap();
+
+        // A mode with a path that would leave the mount is ignored.
+        let ergo = Noun::from_tuple(vec![
+            cord("ergo"),
+            cord("base"),
+            Noun::from_list(vec![
+                Noun::from((path(&["app", "hood", "hoon"]), mime("hoon", b"|%\n"))),
+                Noun::from((path(&["img", "png"]), mime("png", b"\x89PNG\0"))),
+                Noun::from((path(&["..", "hoon"]), mime("hoon", b""))),
+            ]),
+        ]);
+        clay.handle(effect(ergo)).unwrap();
+        assert!(!pier.join("base").join("app").join("hood.hoon").exists());
+
+        // Clay's files are written to the mount without being committed back.
+        clay.handle(effect(Noun::from_tuple(vec![
+            cord("ergo"),
+            cord("base"),
+            Noun::from_list(vec![
+                Noun::from((path(&["app", "hood", "hoon"]), mime("hoon", b"|%\n"))),
+                Noun::from((path(&["img", "png"]), mime("png", b"\x89PNG\0"))),
+            ]),
+        ])))
+        .unwrap();
+        let base = pier.join("base");
+        assert_eq!(
+            b"|%\n",
+            &fs::read(base.join("app").join("hood.hoon")).unwrap()[..]
+        );
+        assert_eq!(b"\x89PNG\0", &fs::read(base.join("img.png")).unwrap()[..]);
+
+        // Changes made to the mount are committed, and only those.
+        fs::write(base.join("app").join("hood.hoon"), "|%\n++  a  1\n").unwrap();
+        fs::write(base.join("app").join(".hood.hoon.swp"), "swap").unwrap();
+        assert_eq!(
+            Some(into(
+                "base",
+                vec![Noun::from((
+                    path(&["app", "hood", "hoon"]),
+                    mime("hoon", b"|%\n++  a  1\n"),
+                ))],
+            )),
+            drivers.wait()
+        );
+        fs::remove_file(base.join("app").join(".hood.hoon.swp")).unwrap();
+        fs::remove_file(base.join("img.png")).unwrap();
+        assert_eq!(
+            Some(into(
+                "base",
+                vec![Noun::from((path(&["img", "png"]), Noun::from(0)))]
+            )),
+            drivers.wait()
+        );
+
+        // Deleting through Clay removes empty directories, and unmounting removes the mount.
+        clay.handle(effect(Noun::from_tuple(vec![
+            cord("ergo"),
+            cord("base"),
+            Noun::from_list(vec![Noun::from((
+                path(&["app", "hood", "hoon"]),
+                Noun::from(0),
+            ))]),
+        ])))
+        .unwrap();
+        assert!(!base.join("app").exists());
+        clay.handle(effect(Noun::from((cord("ogre"), cord("base")))))
+            .unwrap();
+        assert!(!base.exists());
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn mime_types() {
+        assert_eq!(vec!["text", "plain"], mite("hoon"));
+        assert_eq!(vec!["application", "octet-stream"], mite("png"));
+        assert_eq!(Some(Some(b"a\0".to_vec())), parse_mime(mime("txt", b"a\0")));
+        assert_eq!(Some(None), parse_mime(Noun::from(0)));
+    }
+}
@@ -0,0 +1,655 @@
+//! Dill, the terminal driver, which connects Arvo's `%dill` vane to the process's terminal.
+//!
+//! Keystrokes are read with the terminal in raw mode and become `[//term/1 %belt belt]`
+//! events, and the terminal's size is reported with `[//term/1 %blew cols rows]` when the
+//! driver starts and whenever the terminal is resized. Dill's `[%blit (list blit)]` effects are
+//! rendered to the terminal and `[%logo ~]` stops the processing of events. When stdin isn't a
+//! terminal, each line of input becomes a `%txt` belt followed by a `%ret` belt, blits are
+//! rendered as plain lines, and the end of the input stops the processing of events.
+
+use crate::{
+    driver::{Driver, Drivers, Effect, Injector},
+    error::{Context, Error},
+    event_log::Log,
+    noun::{tagged, text},
+};
+use nock::{atom::Atom, noun::Noun, serdes::Jam};
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// How often the terminal's size is checked.
+const RESIZE_POLL: Duration = Duration::from_millis(250);
+
+/// Make a `[//term/1 %tag dat]` event.
+fn event(tag: &str, dat: Noun) -> Noun {
+    Noun::from(Effect {
+        wire: vec![String::new(), "term".to_string(), "1".to_string()],
+        card: tagged(tag, dat),
+    })
+}
+
+/// Make a `%blew` event reporting the terminal's size.
+fn blew((cols, rows): (u16, u16)) -> Noun {
+    event(
+        "blew",
+        Noun::from((Noun::from(u64::from(cols)), Noun::from(u64::from(rows)))),
+    )
+}
+
+/// Parse keystrokes into belts, producing the belts and the number of bytes parsed. An escape
+/// sequence or UTF-8 character cut off at the end of `bytes` is left unparsed.
+///
+/// Text becomes `[%txt (list @c)]`, return `[%ret ~]`, backspace `[%bac ~]`, delete
+/// `[%del ~]`, the arrow keys `[%aro ?(%u %d %r %l)]`, control characters `[%mod %ctl @c]`
+/// and escaped characters `[%mod %met @c]`.
+pub fn belts(bytes: &[u8]) -> (Vec<Noun>, usize) {
+    let null = || Noun::from(0);
+    let term = |tag: &str| Noun::from(Atom::from(tag));
+    let modified =
+        |key: &str, c: u8| tagged("mod", Noun::from((term(key), Noun::from(u64::from(c)))));
+    let mut belts = Vec::new();
+    let mut txt = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (belt, len) = match bytes[i] {
+            b'\r' | b'\n' => (Some(tagged("ret", null())), 1),
+            0x7f | 0x08 => (Some(tagged("bac", null())), 1),
+            0x1b => match bytes.get(i + 1) {
+                None => break,
+                Some(b'[') => {
+                    let end = match bytes[i + 2..]
+                        .iter()
+                        .position(|b| (0x40..=0x7e).contains(b))
+                    {
+                        Some(pos) => i + 2 + pos,
+                        None => break,
+                    };
+                    let belt = match (&bytes[i + 2..end], bytes[end]) {
+                        (b"", b'A') => Some(tagged("aro", term("u"))),
+                        (b"", b'B') => Some(tagged("aro", term("d"))),
+                        (b"", b'C') => Some(tagged("aro", term("r"))),
+                        (b"", b'D') => Some(tagged("aro", term("l"))),
+                        (b"3", b'~') => Some(tagged("del", null())),
+                        _ => None,
+                    };
+                    (belt, end + 1 - i)
+                }
+                Some(&c) => (Some(modified("met", c)), 2),
+            },
+            c @ 0x00..=0x1f => (Some(modified("ctl", c | 0x60)), 1),
+            lead => {
+                let len = match lead {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf7 => 4,
+                    _ => 1,
+                };
+                if i + len > bytes.len() {
+                    break;
+                }
+                if let Ok(text) = std::str::from_utf8(&bytes[i..i + len]) {
+                    txt.extend(text.chars().map(|c| Noun::from(u64::from(c))));
+                }
+                i += len;
+                continue;
+            }
+        };
+        if !txt.is_empty() {
+            belts.push(tagged("txt", Noun::from_list(mem::take(&mut txt))));
+        }
+        belts.extend(belt);
+        i += len;
+    }
+    if !txt.is_empty() {
+        belts.push(tagged("txt", Noun::from_list(txt)));
+    }
+    (belts, i)
+}
+
+/// A color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tint {
+    /// One of the eight standard colors, as its offset from the first color's SGR code.
+    Named(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// The style of a run of `%klr` text.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Style {
+    /// SGR codes of the text's decorations: blinking, bright or underlined.
+    decos: Vec<u8>,
+    bg: Option<Tint>,
+    fg: Option<Tint>,
+}
+
+impl Style {
+    /// Get the SGR escape sequence that applies the style, if it's not the default style.
+    fn sgr(&self) -> Option<String> {
+        let mut codes: Vec<String> = self.decos.iter().map(u8::to_string).collect();
+        for (tint, base) in [(self.bg, 40), (self.fg, 30)] {
+            match tint {
+                Some(Tint::Named(offset)) => codes.push((base + offset).to_string()),
+                Some(Tint::Rgb(r, g, b)) => codes.push(format!("{};2;{};{};{}", base + 8, r, g, b)),
+                None => {}
+            }
+        }
+        if codes.is_empty() {
+            None
+        } else {
+            Some(format!("\x1b[{}m", codes.join(";")))
+        }
+    }
+}
+
+/// An instruction to render something.
+#[derive(Clone, Debug, PartialEq)]
+enum Blit {
+    /// `[%bel ~]`: ring the bell.
+    Bel,
+    /// `[%clr ~]`: clear the screen.
+    Clr,
+    /// `[%hop col]`: move the cursor to a column of the current line.
+    Hop(u64),
+    /// `[%klr stub]`: replace the current line with styled text.
+    Klr(Vec<(Style, String)>),
+    /// `[%lin (list @c)]`: replace the current line with text.
+    Lin(String),
+    /// `[%mor (list blit)]`: several blits.
+    Mor(Vec<Blit>),
+    /// `[%sag path noun]`: save a noun's jam to a file.
+    Sag(Vec<String>, Noun),
+    /// `[%sav path atom]`: save an atom's bytes to a file.
+    Sav(Vec<String>, Atom),
+}
+
+/// Blit from Noun.
+impl TryFrom<Noun> for Blit {
+    type Error = ();
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let (tag, dat) = match noun {
+            Noun::Cell(cell) => (text(&cell.head).ok_or(())?, *cell.tail),
+            Noun::Atom(_) => return Err(()),
+        };
+        match tag.as_str() {
+            "bel" => Ok(Blit::Bel),
+            "clr" => Ok(Blit::Clr),
+            "hop" => match dat {
+                Noun::Atom(Atom::Direct(col)) => Ok(Blit::Hop(col)),
+                _ => Err(()),
+            },
+            "klr" => dat
+                .into_list()
+                .map_err(|_| ())?
+                .into_iter()
+                .map(|run| match run {
+                    Noun::Cell(cell) => Ok((style(*cell.head)?, chars(*cell.tail)?)),
+                    Noun::Atom(_) => Err(()),
+                })
+                .collect::<Result<_, _>>()
+                .map(Blit::Klr),
+            "lin" => chars(dat).map(Blit::Lin),
+            "mor" => dat
+                .into_list()
+                .map_err(|_| ())?
+                .into_iter()
+                .map(Blit::try_from)
+                .collect::<Result<_, _>>()
+                .map(Blit::Mor),
+            "sag" | "sav" => {
+                let (path, dat) = match dat {
+                    Noun::Cell(cell) => (*cell.head, *cell.tail),
+                    Noun::Atom(_) => return Err(()),
+                };
+                let path = path
+                    .into_list()
+                    .map_err(|_| ())?
+                    .into_iter()
+                    .map(|segment| text(&segment).ok_or(()))
+                    .collect::<Result<_, _>>()?;
+                match (tag.as_str(), dat) {
+                    ("sag", noun) => Ok(Blit::Sag(path, noun)),
+                    (_, Noun::Atom(atom)) => Ok(Blit::Sav(path, atom)),
+                    (_, Noun::Cell(_)) => Err(()),
+                }
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+/// Parse a `(list @c)`.
+fn chars(noun: Noun) -> Result<String, ()> {
+    noun.into_list()
+        .map_err(|_| ())?
+        .into_iter()
+        .map(|c| match c {
+            Noun::Atom(Atom::Direct(c)) => u32::try_from(c).ok().and_then(char::from_u32),
+            _ => None,
+        })
+        .collect::<Option<_>>()
+        .ok_or(())
+}
+
+/// Parse a `stye`, which is `[(set deco) bg=tint fg=tint]`.
+fn style(noun: Noun) -> Result<Style, ()> {
+    let mut fields = noun.into_tuple(3).map_err(|_| ())?.into_iter();
+    let mut decos = Vec::new();
+    let mut sets = vec![fields.next().unwrap()];
+    while let Some(set) = sets.pop() {
+        match set {
+            Noun::Atom(Atom::Direct(0)) => {}
+            Noun::Cell(_) => {
+                let mut node = set.into_tuple(3).map_err(|_| ())?.into_iter();
+                match text(&node.next().unwrap()).as_deref() {
+                    Some("bl") => decos.push(5),
+                    Some("br") => decos.push(1),
+                    Some("un") => decos.push(4),
+                    _ => {}
+                }
+                sets.extend(node);
+            }
+            Noun::Atom(_) => return Err(()),
+        }
+    }
+    decos.sort_unstable();
+    Ok(Style {
+        decos,
+        bg: tint(fields.next().unwrap())?,
+        fg: tint(fields.next().unwrap())?,
+    })
+}
+
+/// Parse a `tint`, which is a color's name, `%~` for the default color, or `[r g b]`.
+fn tint(noun: Noun) -> Result<Option<Tint>, ()> {
+    match noun {
+        Noun::Atom(Atom::Direct(0)) => Ok(None),
+        Noun::Atom(atom) => {
+            let names = ["k", "r", "g", "y", "b", "m", "c", "w"];
+            let name = String::from_utf8(atom.to_bytes()).map_err(|_| ())?;
+            match names.iter().position(|tint| *tint == name) {
+                Some(offset) => Ok(Some(Tint::Named(offset as u8))),
+                None => Err(()),
+            }
+        }
+        Noun::Cell(_) => {
+            let byte = |noun: Noun| match noun {
+                Noun::Atom(Atom::Direct(byte)) => u8::try_from(byte).map_err(|_| ()),
+                _ => Err(()),
+            };
+            let mut rgb = noun.into_tuple(3).map_err(|_| ())?.into_iter();
+            Ok(Some(Tint::Rgb(
+                byte(rgb.next().unwrap())?,
+                byte(rgb.next().unwrap())?,
+                byte(rgb.next().unwrap())?,
+            )))
+        }
+    }
+}
+
+/// The terminal driver. Its name is `term`, as in the C runtime, since that's the first
+/// segment of the wires of Dill's effects.
+pub struct Dill<W: Write> {
+    out: W,
+    /// Whether `out` is a terminal, as opposed to a plain stream of lines.
+    tty: bool,
+    /// Directory that `%sag` and `%sav` write files to.
+    put: PathBuf,
+    injector: Injector,
+    /// Terminal settings to restore once the driver is dropped, if it put the terminal in raw
+    /// mode.
+    saved: Option<libc::termios>,
+}
+
+impl<W: Write> Dill<W> {
+    /// Make a driver that renders blits to `out`, as a terminal if `tty`, and writes files to
+    /// the pier's `.urb/put` directory. Stopping the processing of events goes through
+    /// `injector`.
+    pub fn new(out: W, tty: bool, pier: &Path, injector: Injector) -> Self {
+        Self {
+            out,
+            tty,
+            put: Log::path(pier).with_file_name("put"),
+            injector,
+            saved: None,
+        }
+    }
+
+    /// Get the file a `%sag` or `%sav` path refers to, whose last segment is the file's
+    /// extension, e.g. `/foo/bar/txt` is `foo/bar.txt`.
+    fn file(&self, path: &[String]) -> Option<PathBuf> {
+        if path
+            .iter()
+            .any(|segment| segment.is_empty() || segment.contains('/') || segment.starts_with('.'))
+        {
+            return None;
+        }
+        let (file, dirs) = match path {
+            [] => return None,
+            [name] => (name.clone(), &[][..]),
+            [dirs @ .., name, ext] => (format!("{}.{}", name, ext), dirs),
+        };
+        Some(
+            dirs.iter()
+                .fold(self.put.clone(), |dir, seg| dir.join(seg))
+                .join(file),
+        )
+    }
+
+    /// Render a blit.
+    fn render(&mut self, blit: Blit) -> Result<(), Error> {
+        match blit {
+            Blit::Bel if self.tty => write!(self.out, "\x07")?,
+            Blit::Clr if self.tty => write!(self.out, "\x1b[H\x1b[2J")?,
+            Blit::Hop(col) if self.tty => {
+                write!(self.out, "\r")?;
+                if col > 0 {
+                    write!(self.out, "\x1b[{}C", col)?;
+                }
+            }
+            Blit::Bel | Blit::Clr | Blit::Hop(_) => {}
+            Blit::Klr(runs) => {
+                if self.tty {
+                    write!(self.out, "\r\x1b[K")?;
+                }
+                for (style, text) in runs {
+                    match style.sgr().filter(|_| self.tty) {
+                        Some(sgr) => write!(self.out, "{}{}\x1b[0m", sgr, text)?,
+                        None => write!(self.out, "{}", text)?,
+                    }
+                }
+                if !self.tty {
+                    writeln!(self.out)?;
+                }
+            }
+            Blit::Lin(text) if self.tty => write!(self.out, "\r\x1b[K{}", text)?,
+            Blit::Lin(text) => writeln!(self.out, "{}", text)?,
+            Blit::Mor(blits) => {
+                for blit in blits {
+                    self.render(blit)?;
+                }
+            }
+            Blit::Sag(path, noun) => self.save(&path, &noun.jam())?,
+            Blit::Sav(path, atom) => self.save(&path, &atom.to_bytes())?,
+        }
+        Ok(())
+    }
+
+    /// Write a file for `%sag` or `%sav`.
+    fn save(&self, path: &[String], bytes: &[u8]) -> Result<(), Error> {
+        let file = match self.file(path) {
+            Some(file) => file,
+            None => {
+                eprintln!("dill: can't save to /{}", path.join("/"));
+                return Ok(());
+            }
+        };
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir).at_path(dir)?;
+        }
+        fs::write(&file, bytes).at_path(&file)
+    }
+}
+
+impl Dill<io::Stdout> {
+    /// Start the driver on the process's terminal: put the terminal in raw mode, report its
+    /// size and read keystrokes as belts on a background thread, which also watches for the
+    /// terminal being resized. If stdin or stdout isn't a terminal, lines of input are read
+    /// instead. Either way, the processing of events stops once the input ends.
+    pub fn start(pier: &Path, drivers: &Drivers) -> Result<Self, Error> {
+        let tty = unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+            && unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 };
+        let mut dill = Self::new(io::stdout(), tty, pier, drivers.injector());
+        if !tty {
+            let injector = drivers.injector();
+            thread::spawn(move || {
+                for line in io::stdin().lock().lines().map_while(Result::ok) {
+                    let (mut belts, _) = belts(line.as_bytes());
+                    belts.push(tagged("ret", Noun::from(0)));
+                    if !belts
+                        .into_iter()
+                        .all(|belt| injector.inject(event("belt", belt)))
+                    {
+                        break;
+                    }
+                }
+                injector.exit();
+            });
+            return Ok(dill);
+        }
+        dill.saved = Some(raw_mode()?);
+        let injector = drivers.injector();
+        let mut size = window_size();
+        if let Some(size) = size {
+            injector.inject(blew(size));
+        }
+        {
+            let injector = injector.clone();
+            thread::spawn(move || loop {
+                thread::sleep(RESIZE_POLL);
+                let now = window_size();
+                if now != size {
+                    size = now;
+                    if let Some(size) = size {
+                        if !injector.inject(blew(size)) {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+        thread::spawn(move || {
+            let mut stdin = io::stdin().lock();
+            let mut buf = Vec::new();
+            let mut chunk = [0; 256];
+            while let Ok(len) = stdin.read(&mut chunk) {
+                if 0 == len {
+                    break;
+                }
+                buf.extend_from_slice(&chunk[..len]);
+                let (belts, parsed) = belts(&buf);
+                buf.drain(..parsed);
+                if !belts
+                    .into_iter()
+                    .all(|belt| injector.inject(event("belt", belt)))
+                {
+                    return;
+                }
+            }
+            injector.exit();
+        });
+        Ok(dill)
+    }
+}
+
+impl<W: Write> Driver for Dill<W> {
+    fn name(&self) -> &str {
+        "term"
+    }
+
+    /// Render a `%blit` card's blits, or stop the processing of events for a `%logo` card.
+    /// Other cards, and blits that can't be parsed, are logged and ignored.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+        let dat = match &effect.card {
+            Noun::Cell(cell) => cell.tail.as_ref().clone(),
+            Noun::Atom(_) => Noun::from(0),
+        };
+        match effect.tag().as_deref() {
+            Some("blit") => {
+                let blits = dat.into_list().unwrap_or_default();
+                for blit in blits {
+                    match Blit::try_from(blit) {
+                        Ok(blit) => self.render(blit)?,
+                        Err(()) => eprintln!("dill: ignoring malformed blit"),
+                    }
+                }
+                self.out.flush()?;
+            }
+            Some("logo") => self.injector.exit(),
+            _ => eprintln!("dill: ignoring effect {}", effect),
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for Dill<W> {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved) };
+            let _ = writeln!(self.out);
+        }
+    }
+}
+
+/// Put the terminal in raw mode, keeping output processing so that newlines still return the
+/// cursor, and produce the settings it had.
+fn raw_mode() -> Result<libc::termios, Error> {
+    let mut saved = unsafe { mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let mut raw = saved;
+    unsafe { libc::cfmakeraw(&mut raw) };
+    raw.c_oflag |= libc::OPOST;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(saved)
+}
+
+/// Get the terminal's size in columns and rows.
+fn window_size() -> Option<(u16, u16)> {
+    let mut size = unsafe { mem::zeroed::<libc::winsize>() };
+    match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 => Some((size.ws_col, size.ws_row)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nock::serdes::Cue;
+    use std::{env, process};
+
+    fn term(tag: &str) -> Noun {
+        Noun::from(Atom::from(tag))
+    }
+
+    fn list(text: &str) -> Noun {
+        Noun::from_list(text.chars().map(|c| Noun::from(u64::from(c))).collect())
+    }
+
+    fn blit(blits: Vec<Noun>) -> Effect {
+        Effect {
+            wire: vec![String::new(), "term".to_string(), "1".to_string()],
+            card: tagged("blit", Noun::from_list(blits)),
+        }
+    }
+
+    #[test]
+    fn parse_belts() {
+        let (belts, parsed) = belts("hé\r\x7f\x1b[A\x1b[3~\x03\x1bx\x1b[".as_bytes());
+        assert_eq!(
+            vec![
+                tagged("txt", list("hé")),
+                tagged("ret", Noun::from(0)),
+                tagged("bac", Noun::from(0)),
+                tagged("aro", term("u")),
+                tagged("del", Noun::from(0)),
+                tagged(
+                    "mod",
+                    Noun::from((term("ctl"), Noun::from(u64::from(b'c'))))
+                ),
+                tagged(
+                    "mod",
+                    Noun::from((term("met"), Noun::from(u64::from(b'x'))))
+                ),
+            ],
+            belts
+        );
+        // The cut off escape sequence is left for later, as is a cut off character.
+        assert_eq!("hé\r\x7f\x1b[A\x1b[3~\x03\x1bx".len(), parsed);
+        assert_eq!((vec![], 0), super::belts(&"é".as_bytes()[..1]));
+    }
+
+    #[test]
+    fn render() {
+        let pier = env::temp_dir().join(format!("vere-dill-{}", process::id()));
+        let drivers = Drivers::new();
+        let styled = Noun::from_list(vec![Noun::from((
+            Noun::from_tuple(vec![
+                Noun::from_tuple(vec![term("br"), Noun::from(0), Noun::from(0)]),
+                Noun::from(0),
+                term("r"),
+            ]),
+            list("hi"),
+        ))]);
+        let blits = vec![
+            tagged("lin", list("> ")),
+            tagged("hop", Noun::from(2)),
+            tagged(
+                "mor",
+                Noun::from_list(vec![tagged("bel", Noun::from(0)), tagged("klr", styled)]),
+            ),
+            tagged(
+                "sav",
+                Noun::from((
+                    Noun::from_list(vec![term("notes"), term("txt")]),
+                    Noun::from(Atom::from("saved")),
+                )),
+            ),
+            tagged(
+                "sag",
+                Noun::from((
+                    Noun::from_list(vec![term("out"), term("noun"), term("jam")]),
+                    Noun::from((Noun::from(1), Noun::from(2))),
+                )),
+            ),
+        ];
+
+        // Render to a terminal.
+        {
+            let mut dill = Dill::new(Vec::new(), true, &pier, drivers.injector());
+            dill.handle(blit(blits.clone())).unwrap();
+            assert_eq!(
+                "\r\x1b[K> \r\x1b[2C\x07\r\x1b[K\x1b[1;31mhi\x1b[0m",
+                String::from_utf8(dill.out.clone()).unwrap()
+            );
+            let put = Log::path(&pier).with_file_name("put");
+            assert_eq!(b"saved", &fs::read(put.join("notes.txt")).unwrap()[..]);
+            assert_eq!(
+                Noun::from((Noun::from(1), Noun::from(2))),
+                Noun::cue(&fs::read(put.join("out").join("noun.jam")).unwrap()).unwrap()
+            );
+        }
+
+        // Render plain lines, skipping anything that isn't text.
+        {
+            let mut dill = Dill::new(Vec::new(), false, &pier, drivers.injector());
+            dill.handle(blit(blits)).unwrap();
+            assert_eq!("> \nhi\n", String::from_utf8(dill.out.clone()).unwrap());
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn logo() {
+        let mut drivers = Drivers::new();
+        let mut dill = Dill::new(Vec::new(), false, Path::new("/"), drivers.injector());
+        dill.handle(Effect {
+            wire: vec![String::new(), "term".to_string(), "1".to_string()],
+            card: tagged("logo", Noun::from(0)),
+        })
+        .unwrap();
+        assert_eq!(None, drivers.wait());
+    }
+}
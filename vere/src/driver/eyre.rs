@@ -0,0 +1,701 @@
+//! Eyre, the HTTP server driver, which serves HTTP/1.1 on behalf of Arvo's `%eyre` vane.
+//!
+//! Each request becomes a `[//http-server/<server>/<request>/1 %request secure address request]`
+//! event, or `%request-local` for a request from the loopback interface, and Eyre answers it
+//! with `%response` effects on the same wire: a `%start` with the status, headers and maybe
+//! the whole body, then `%continue`s with more of the body until one completes the response,
+//! or a `%cancel`. A response that isn't complete when it starts is streamed with chunked
+//! encoding, each chunk sent as soon as it arrives, which is how channels' server-sent events
+//! reach the browser. A client that goes away before its response is complete produces a
+//! `%cancel` event. The server listens on the loopback interface unless told otherwise.
+
+use crate::{
+    driver::{Driver, Drivers, Effect, Injector},
+    error::Error,
+    noun::{cord, header_list, octs, parse_flag, parse_header_list, parse_octs, tagged},
+};
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    os::unix::io::AsRawFd,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// First port tried when no port is asked for.
+const FIRST_PORT: u16 = 8080;
+
+/// Number of ports tried, starting with [`FIRST_PORT`], before settling for any free port.
+const PORTS: u16 = 100;
+
+/// Largest request line and headers accepted.
+const MAX_HEAD: usize = 64 << 10;
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 64 << 20;
+
+/// How often a connection waiting on its response is checked for the client hanging up.
+const HANGUP_POLL: Duration = Duration::from_millis(100);
+
+/// An HTTP request.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Request {
+    method: String,
+    url: String,
+    /// Headers in the order they were sent, with lowercase names.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Get the value of a header, given its lowercase name.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, val)| val.as_str())
+    }
+}
+
+/// Noun from Request, as `[method=@t url=@t header-list=(list [key=@t value=@t]) body=(unit octs)]`.
+impl From<Request> for Noun {
+    fn from(req: Request) -> Self {
+        Noun::from_tuple(vec![
+            cord(&req.method),
+            cord(&req.url),
+            header_list(&req.headers),
+            octs(&req.body),
+        ])
+    }
+}
+
+/// Make an error for a malformed request.
+fn bad(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Read a line of a request's head, failing if the head grows past [`MAX_HEAD`].
+fn read_line<R: BufRead>(inp: &mut R, head: &mut usize) -> io::Result<String> {
+    let mut line = Vec::new();
+    let len = inp
+        .by_ref()
+        .take((MAX_HEAD - *head) as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    *head += len;
+    if *head > MAX_HEAD {
+        return Err(bad("request head too large"));
+    }
+    if !line.ends_with(b"\n") {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    let line = String::from_utf8(line).map_err(|_| bad("request head isn't UTF-8"))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Read a request from a connection, producing nothing if the connection closes before the
+/// request starts.
+fn read_request<R: BufRead>(inp: &mut R) -> io::Result<Option<Request>> {
+    if inp.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut head = 0;
+    let line = read_line(inp, &mut head)?;
+    let mut parts = line.split(' ');
+    let (method, url) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(url), Some(version), None) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), url.to_string())
+        }
+        _ => return Err(bad("malformed request line")),
+    };
+    let mut req = Request {
+        method,
+        url,
+        ..Request::default()
+    };
+    loop {
+        let line = read_line(inp, &mut head)?;
+        if line.is_empty() {
+            break;
+        }
+        match line.split_once(':') {
+            Some((key, val)) => req
+                .headers
+                .push((key.trim().to_ascii_lowercase(), val.trim().to_string())),
+            None => return Err(bad("malformed header")),
+        }
+    }
+
+    let chunked = req
+        .header("transfer-encoding")
+        .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"));
+    if chunked {
+        loop {
+            let line = read_line(inp, &mut 0)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = usize::from_str_radix(size, 16).map_err(|_| bad("malformed chunk"))?;
+            if 0 == size {
+                while !read_line(inp, &mut 0)?.is_empty() {}
+                break;
+            }
+            if req.body.len() + size > MAX_BODY {
+                return Err(bad("request body too large"));
+            }
+            let start = req.body.len();
+            req.body.resize(start + size, 0);
+            inp.read_exact(&mut req.body[start..])?;
+            if !read_line(inp, &mut 0)?.is_empty() {
+                return Err(bad("malformed chunk"));
+            }
+        }
+    } else if let Some(len) = req.header("content-length") {
+        let len: usize = len.parse().map_err(|_| bad("malformed content-length"))?;
+        if len > MAX_BODY {
+            return Err(bad("request body too large"));
+        }
+        req.body.resize(len, 0);
+        inp.read_exact(&mut req.body)?;
+    }
+    Ok(Some(req))
+}
+
+/// A part of a response, from an `http-event`.
+#[derive(Clone, Debug, PartialEq)]
+enum Response {
+    /// `[%start [status-code=@ud headers=(list [@t @t])] data=(unit octs) complete=?]`
+    Start {
+        status: u16,
+        headers: Vec<(String, String)>,
+        data: Vec<u8>,
+        complete: bool,
+    },
+    /// `[%continue data=(unit octs) complete=?]`
+    Continue { data: Vec<u8>, complete: bool },
+    /// `[%cancel ~]`
+    Cancel,
+}
+
+/// Response from Noun.
+impl TryFrom<Noun> for Response {
+    type Error = ();
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let (tag, dat) = match noun {
+            Noun::Cell(cell) => (*cell.head, *cell.tail),
+            Noun::Atom(_) => return Err(()),
+        };
+        if tag == cord("cancel") {
+            return Ok(Response::Cancel);
+        }
+        if tag == cord("continue") {
+            let mut dat = dat.into_tuple(2).map_err(|_| ())?.into_iter();
+            let data = parse_octs(dat.next().unwrap()).ok_or(())?;
+            let complete = parse_flag(&dat.next().unwrap()).ok_or(())?;
+            return Ok(Response::Continue { data, complete });
+        }
+        if tag != cord("start") {
+            return Err(());
+        }
+        let mut dat = dat.into_tuple(3).map_err(|_| ())?.into_iter();
+        let (status, headers) = match dat.next().unwrap() {
+            Noun::Cell(cell) => match *cell.head {
+                Noun::Atom(Atom::Direct(status)) => (
+                    u16::try_from(status).map_err(|_| ())?,
+                    parse_header_list(*cell.tail).ok_or(())?,
+                ),
+                _ => return Err(()),
+            },
+            Noun::Atom(_) => return Err(()),
+        };
+        let data = parse_octs(dat.next().unwrap()).ok_or(())?;
+        let complete = parse_flag(&dat.next().unwrap()).ok_or(())?;
+        Ok(Response::Start {
+            status,
+            headers,
+            data,
+            complete,
+        })
+    }
+}
+
+/// Get the reason phrase of a status code.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+/// Write a chunk of a chunked response.
+fn write_chunk<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    if !data.is_empty() {
+        write!(out, "{:x}\r\n", data.len())?;
+        out.write_all(data)?;
+        out.write_all(b"\r\n")?;
+    }
+    Ok(())
+}
+
+/// Check whether the client of a connection has hung up, without waiting or reading anything.
+fn hung_up(stream: &TcpStream) -> bool {
+    let mut fd = libc::pollfd {
+        fd: stream.as_raw_fd(),
+        events: libc::POLLRDHUP,
+        revents: 0,
+    };
+    1 == unsafe { libc::poll(&mut fd, 1, 0) }
+        && 0 != fd.revents & (libc::POLLRDHUP | libc::POLLHUP | libc::POLLERR)
+}
+
+/// Write a response as its parts arrive, producing whether the response was completed, after
+/// which the connection can be reused. Fails as soon as `hung_up` finds that the client has
+/// gone, even if nothing is being written.
+fn respond<W: Write>(
+    out: &mut W,
+    parts: &Receiver<Response>,
+    hung_up: impl Fn() -> bool,
+) -> io::Result<bool> {
+    let mut started = false;
+    loop {
+        let part = match parts.recv_timeout(HANGUP_POLL) {
+            Ok(part) => part,
+            Err(RecvTimeoutError::Timeout) if hung_up() => {
+                return Err(io::Error::from(ErrorKind::ConnectionAborted));
+            }
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return Ok(false),
+        };
+        match part {
+            Response::Start {
+                status,
+                headers,
+                data,
+                complete,
+            } if !started => {
+                started = true;
+                write!(out, "HTTP/1.1 {} {}\r\n", status, reason(status))?;
+                for (key, val) in headers.iter().filter(|(key, _)| {
+                    !key.eq_ignore_ascii_case("content-length")
+                        && !key.eq_ignore_ascii_case("transfer-encoding")
+                }) {
+                    write!(out, "{}: {}\r\n", key, val)?;
+                }
+                if complete {
+                    write!(out, "content-length: {}\r\n\r\n", data.len())?;
+                    out.write_all(&data)?;
+                    out.flush()?;
+                    return Ok(true);
+                }
+                write!(out, "transfer-encoding: chunked\r\n\r\n")?;
+                write_chunk(out, &data)?;
+                out.flush()?;
+            }
+            Response::Continue { data, complete } if started => {
+                write_chunk(out, &data)?;
+                if complete {
+                    out.write_all(b"0\r\n\r\n")?;
+                    out.flush()?;
+                    return Ok(true);
+                }
+                out.flush()?;
+            }
+            Response::Start { .. } | Response::Continue { .. } => {
+                eprintln!("eyre: ignoring out of order response");
+            }
+            Response::Cancel => return Ok(false),
+        }
+    }
+}
+
+/// What the server's threads share with the driver.
+struct Shared {
+    /// Identifies this run of the server in wires, so that responses to requests made of an
+    /// earlier run are ignored.
+    sev: String,
+    /// Number of the next request.
+    next: AtomicU64,
+    /// Connections waiting on responses, by the number of the request they're waiting on.
+    open: Mutex<HashMap<u64, Sender<Response>>>,
+    injector: Injector,
+}
+
+impl Shared {
+    /// Make an event on the wire of a generation of a request, which are both 0 for events
+    /// about the server itself.
+    fn event(&self, num: u64, gen: u64, card: Noun) -> Noun {
+        Noun::from(Effect {
+            wire: vec![
+                String::new(),
+                "http-server".to_string(),
+                self.sev.clone(),
+                num.to_string(),
+                gen.to_string(),
+            ],
+            card,
+        })
+    }
+}
+
+/// Serve a connection's requests in turn until it closes.
+fn serve(stream: TcpStream, peer: SocketAddr, shared: Arc<Shared>) {
+    let _ = stream.set_nodelay(true);
+    let mut inp = match stream.try_clone() {
+        Ok(inp) => BufReader::new(inp),
+        Err(_) => return,
+    };
+    let mut out = stream;
+    let address = match peer.ip() {
+        IpAddr::V4(ip) => tagged("ipv4", Noun::from(u64::from(u32::from(ip)))),
+        IpAddr::V6(ip) => tagged(
+            "ipv6",
+            Noun::from(Atom::from_bytes(&u128::from(ip).to_le_bytes())),
+        ),
+    };
+    let local = if peer.ip().is_loopback() {
+        "request-local"
+    } else {
+        "request"
+    };
+    loop {
+        let req = match read_request(&mut inp) {
+            Ok(Some(req)) => req,
+            Ok(None) => return,
+            Err(err) => {
+                if ErrorKind::InvalidData == err.kind() {
+                    let _ = write!(
+                        out,
+                        "HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                    );
+                }
+                return;
+            }
+        };
+        let close = req
+            .header("connection")
+            .is_some_and(|conn| conn.eq_ignore_ascii_case("close"));
+        let num = shared.next.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        shared.open.lock().unwrap().insert(num, tx);
+        let card = Noun::from_tuple(vec![
+            cord(local),
+            Noun::from(1),
+            address.clone(),
+            Noun::from(req),
+        ]);
+        if !shared.injector.inject(shared.event(num, 1, card)) {
+            return;
+        }
+        let res = respond(&mut out, &rx, || hung_up(inp.get_ref()));
+        shared.open.lock().unwrap().remove(&num);
+        match res {
+            Ok(true) if !close => {}
+            Ok(_) => return,
+            Err(_) => {
+                shared
+                    .injector
+                    .inject(shared.event(num, 1, tagged("cancel", Noun::from(0))));
+                return;
+            }
+        }
+    }
+}
+
+/// The HTTP server driver.
+pub struct Eyre {
+    port: u16,
+    shared: Arc<Shared>,
+}
+
+impl Eyre {
+    /// Start serving on an interface and port, announcing the server with a `%born` event and
+    /// its port with a `%live` event. Without a port, the first free port from 8080 on is used.
+    pub fn start(ip: IpAddr, port: Option<u16>, drivers: &Drivers) -> Result<Self, Error> {
+        let listener = match port {
+            Some(port) => TcpListener::bind((ip, port))?,
+            None => (FIRST_PORT..FIRST_PORT + PORTS)
+                .find_map(|port| TcpListener::bind((ip, port)).ok())
+                .map_or_else(|| TcpListener::bind((ip, 0)), Ok)?,
+        };
+        let port = listener.local_addr()?.port();
+        let sev = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let shared = Arc::new(Shared {
+            sev: sev.to_string(),
+            next: AtomicU64::new(1),
+            open: Mutex::new(HashMap::new()),
+            injector: drivers.injector(),
+        });
+
+        shared
+            .injector
+            .inject(shared.event(0, 0, tagged("born", Noun::from(0))));
+        shared.injector.inject(shared.event(
+            0,
+            0,
+            tagged(
+                "live",
+                Noun::from((Noun::from(u64::from(port)), Noun::from(0))),
+            ),
+        ));
+
+        let accepting = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        eprintln!("eyre: failed to accept a connection: {}", err);
+                        continue;
+                    }
+                };
+                let peer = match stream.peer_addr() {
+                    Ok(peer) => peer,
+                    Err(_) => continue,
+                };
+                let shared = accepting.clone();
+                thread::spawn(move || serve(stream, peer, shared));
+            }
+        });
+        Ok(Self { port, shared })
+    }
+
+    /// Get the port the server is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Get the number of the request a wire is for, if it's for a request of this server, i.e.
+    /// `/http-server/<server>/<request>/<generation>`.
+    fn request(&self, effect: &Effect) -> Option<u64> {
+        let mut wire = effect.wire.iter().skip_while(|segment| segment.is_empty());
+        match (wire.next(), wire.next(), wire.next()) {
+            (Some(_), Some(sev), Some(num)) if *sev == self.shared.sev => num.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl Driver for Eyre {
+    fn name(&self) -> &str {
+        "http-server"
+    }
+
+    /// Hand a `%response` card's `http-event` to the connection waiting on the request. A
+    /// response to a request that's gone is dropped, and other cards are logged and ignored.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+        if Some("response") != effect.tag().as_deref() {
+            eprintln!("eyre: ignoring effect {}", effect);
+            return Ok(());
+        }
+        let num = self.request(&effect);
+        let res = match &effect.card {
+            Noun::Cell(cell) => Response::try_from(cell.tail.as_ref().clone()),
+            Noun::Atom(_) => Err(()),
+        };
+        match (num, res) {
+            (Some(num), Ok(res)) => {
+                if let Some(tx) = self.shared.open.lock().unwrap().get(&num) {
+                    let _ = tx.send(res);
+                }
+            }
+            _ => eprintln!("eyre: ignoring malformed response {}", effect),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn parse_request() {
+        let mut inp = &b"POST /~/channel/1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello\
+            PUT /a HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n"[..];
+        assert_eq!(
+            Some(Request {
+                method: "POST".to_string(),
+                url: "/~/channel/1".to_string(),
+                headers: vec![
+                    ("host".to_string(), "localhost".to_string()),
+                    ("content-length".to_string(), "5".to_string()),
+                ],
+                body: b"hello".to_vec(),
+            }),
+            read_request(&mut inp).unwrap()
+        );
+        assert_eq!(
+            b"abcde".to_vec(),
+            read_request(&mut inp).unwrap().unwrap().body
+        );
+        assert_eq!(None, read_request(&mut inp).unwrap());
+
+        // Malformed and truncated requests are rejected.
+        for req in [&b"GET /\r\n\r\n"[..], b"GET / HTTP/1.1\r\nbad\r\n\r\n"] {
+            let err = read_request(&mut &req[..]).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, err.kind());
+        }
+        let err = read_request(&mut &b"GET / HTTP/1.1\r\nContent-Length: 3\r\n\r\nab"[..]);
+        assert_eq!(ErrorKind::UnexpectedEof, err.unwrap_err().kind());
+    }
+
+    #[test]
+    fn serve_request() {
+        let mut drivers = Drivers::new();
+        let mut eyre = Eyre::start(IpAddr::V4(Ipv4Addr::LOCALHOST), Some(0), &drivers).unwrap();
+        let port = eyre.port();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            write!(
+                stream,
+                "GET /~/channel/1 HTTP/1.1\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            let mut res = String::new();
+            stream.read_to_string(&mut res).unwrap();
+            res
+        });
+
+        // The server announces itself, then the request arrives from the loopback interface.
+        let mut tags = Vec::new();
+        let req = loop {
+            let evt = Effect::try_from(drivers.wait().unwrap()).unwrap();
+            assert_eq!(Some("http-server"), evt.driver());
+            tags.push(evt.tag().unwrap());
+            if evt.tag().as_deref() == Some("request-local") {
+                break evt;
+            }
+        };
+        assert_eq!(vec!["born", "live", "request-local"], tags);
+        let card = match req.card.clone() {
+            Noun::Cell(cell) => cell.tail.into_tuple(3).unwrap(),
+            Noun::Atom(_) => unreachable!(),
+        };
+        assert_eq!(Noun::from(1), card[0]);
+        assert_eq!(
+            tagged(
+                "ipv4",
+                Noun::from(u64::from(u32::from(Ipv4Addr::LOCALHOST)))
+            ),
+            card[1]
+        );
+        let fields = card[2].clone().into_tuple(4).unwrap();
+        assert_eq!(
+            (cord("GET"), cord("/~/channel/1"), Noun::from(0)),
+            (fields[0].clone(), fields[1].clone(), fields[3].clone())
+        );
+
+        // Stream a response as server-sent events.
+        let respond = |eyre: &mut Eyre, event: Noun| {
+            eyre.handle(Effect {
+                wire: req.wire.clone(),
+                card: tagged("response", event),
+            })
+            .unwrap();
+        };
+        let headers = vec![("content-type".to_string(), "text/event-stream".to_string())];
+        respond(
+            &mut eyre,
+            tagged(
+                "start",
+                Noun::from_tuple(vec![
+                    Noun::from((Noun::from(200), header_list(&headers))),
+                    Noun::from(0),
+                    Noun::from(1),
+                ]),
+            ),
+        );
+        for (data, complete) in [("data: 1\n\n", 1), ("data: 2\n\n", 0)] {
+            respond(
+                &mut eyre,
+                tagged(
+                    "continue",
+                    Noun::from((octs(data.as_bytes()), Noun::from(complete))),
+                ),
+            );
+        }
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n\
+             9\r\ndata: 1\n\n\r\n9\r\ndata: 2\n\n\r\n0\r\n\r\n",
+            client.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn hang_up() {
+        let mut drivers = Drivers::new();
+        let eyre = Eyre::start(IpAddr::V4(Ipv4Addr::LOCALHOST), Some(0), &drivers).unwrap();
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, eyre.port())).unwrap();
+        write!(stream, "GET / HTTP/1.1\r\n\r\n").unwrap();
+        let req = loop {
+            let evt = Effect::try_from(drivers.wait().unwrap()).unwrap();
+            if evt.tag().as_deref() == Some("request-local") {
+                break evt;
+            }
+        };
+
+        // A client that hangs up while waiting on its response cancels the request.
+        drop(stream);
+        assert_eq!(
+            Some(Noun::from(Effect {
+                wire: req.wire,
+                card: tagged("cancel", Noun::from(0)),
+            })),
+            drivers.wait()
+        );
+    }
+
+    #[test]
+    fn convert() {
+        let data = b"ab\0\0".to_vec();
+        assert_eq!(Some(data.clone()), parse_octs(octs(&data)));
+        assert_eq!(Some(Vec::new()), parse_octs(octs(&[])));
+        let headers = vec![("a".to_string(), "b".to_string())];
+        assert_eq!(
+            Some(headers.clone()),
+            parse_header_list(header_list(&headers))
+        );
+        assert_eq!(
+            Ok(Response::Start {
+                status: 404,
+                headers,
+                data,
+                complete: true,
+            }),
+            Response::try_from(tagged(
+                "start",
+                Noun::from_tuple(vec![
+                    Noun::from((Noun::from(404), header_list(&[("a".into(), "b".into())]))),
+                    octs(b"ab\0\0"),
+                    Noun::from(0),
+                ]),
+            ))
+        );
+        assert_eq!(Err(()), Response::try_from(tagged("start", Noun::from(0))));
+    }
+}
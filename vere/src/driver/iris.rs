@@ -0,0 +1,458 @@
+//! Iris, the HTTP client driver, which fetches URLs for Arvo's `%iris` vane.
+//!
+//! Iris asks for a fetch with `[%request id request]`, where a request is
+//! `[method=@t url=@t header-list=(list [key=@t value=@t]) body=(unit octs)]`, and abandons
+//! one with `[%cancel-request id]`. The response comes back as
+//! `[//http-client/<client> %receive id http-event]` events: a `%start` with the status,
+//! headers and the first of the body, then `%continue`s with the rest of the body as it
+//! arrives, the last of which completes the response. A fetch that fails or takes longer than
+//! the driver's timeout gets a `%cancel` instead. The fetching itself is done by a
+//! [`Backend`], which by default is [`Ureq`].
+
+use crate::{
+    driver::{Driver, Drivers, Effect, Injector},
+    error::Error,
+    noun::{flag, header_list, octs, parse_header_list, parse_octs, tagged, text},
+};
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How long a fetch can take by default.
+pub const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest chunk of a response body sent in one event.
+const CHUNK: usize = 64 << 10;
+
+/// An HTTP request to fetch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Request from Noun.
+impl TryFrom<Noun> for Request {
+    type Error = ();
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let mut fields = noun.into_tuple(4).map_err(|_| ())?.into_iter();
+        Ok(Self {
+            method: text(&fields.next().unwrap()).ok_or(())?,
+            url: text(&fields.next().unwrap()).ok_or(())?,
+            headers: parse_header_list(fields.next().unwrap()).ok_or(())?,
+            body: parse_octs(fields.next().unwrap()).ok_or(())?,
+        })
+    }
+}
+
+/// An HTTP response whose body is still to be read.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Box<dyn Read + Send>,
+}
+
+/// A way of fetching requests.
+pub trait Backend: Send + Sync + 'static {
+    /// Send a request and produce its response once its headers arrive, taking no longer than
+    /// `timeout` to send the request or to read any of the response.
+    fn fetch(&self, req: &Request, timeout: Duration) -> io::Result<Response>;
+}
+
+/// A backend that fetches HTTP and HTTPS URLs with `ureq`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ureq;
+
+impl Backend for Ureq {
+    fn fetch(&self, req: &Request, timeout: Duration) -> io::Result<Response> {
+        let mut request = ureq::request(&req.method, &req.url).timeout(timeout);
+        for (key, val) in &req.headers {
+            request = request.set(key, val);
+        }
+        let res = match request.send_bytes(&req.body) {
+            Ok(res) | Err(ureq::Error::Status(_, res)) => res,
+            Err(err) => return Err(io::Error::other(err)),
+        };
+        let headers = res
+            .headers_names()
+            .into_iter()
+            .filter_map(|key| {
+                let val = res.header(&key)?.to_string();
+                Some((key, val))
+            })
+            .collect();
+        Ok(Response {
+            status: res.status(),
+            headers,
+            body: Box::new(res.into_reader()),
+        })
+    }
+}
+
+/// Fetch a request and send its response as `http-event`s, until the response is complete,
+/// the fetch fails or times out, or the request is cancelled.
+fn fetch<B: Backend>(
+    backend: &B,
+    req: &Request,
+    timeout: Duration,
+    cancelled: &AtomicBool,
+    send: impl Fn(Noun) -> bool,
+) {
+    let cancel = || {
+        send(tagged("cancel", Noun::from(0)));
+    };
+    let started = Instant::now();
+    let mut res = match backend.fetch(req, timeout) {
+        Ok(res) => res,
+        Err(err) => {
+            eprintln!("iris: failed to fetch {}: {}", req.url, err);
+            return cancel();
+        }
+    };
+    let mut head = Some((res.status, res.headers));
+    let mut held: Option<Vec<u8>> = None;
+    let mut buf = vec![0; CHUNK];
+    loop {
+        let read = res.body.read(&mut buf);
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        let len = match read {
+            Ok(len) => len,
+            Err(err) if ErrorKind::Interrupted == err.kind() => continue,
+            Err(err) => {
+                eprintln!("iris: failed to read {}: {}", req.url, err);
+                return cancel();
+            }
+        };
+        if started.elapsed() > timeout {
+            eprintln!("iris: timed out fetching {}", req.url);
+            return cancel();
+        }
+
+        // A chunk is held until the next read says whether it completes the response.
+        let complete = 0 == len;
+        if complete || held.is_some() {
+            let data = octs(&held.take().unwrap_or_default());
+            let event = match head.take() {
+                Some((status, headers)) => tagged(
+                    "start",
+                    Noun::from_tuple(vec![
+                        Noun::from((Noun::from(u64::from(status)), header_list(&headers))),
+                        data,
+                        flag(complete),
+                    ]),
+                ),
+                None => tagged("continue", Noun::from((data, flag(complete)))),
+            };
+            if !send(event) || complete {
+                return;
+            }
+        }
+        held = Some(buf[..len].to_vec());
+    }
+}
+
+/// The HTTP client driver.
+pub struct Iris<B: Backend = Ureq> {
+    backend: Arc<B>,
+    timeout: Duration,
+    /// Identifies this run of the driver in wires.
+    sev: String,
+    /// Flags that cancel the fetches in progress, by request ID.
+    fetches: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>>,
+    injector: Injector,
+}
+
+impl<B: Backend> Iris<B> {
+    /// Make a driver that fetches through `backend`, giving up on fetches that take longer than
+    /// `timeout`, and announce it with a `%born` event.
+    pub fn new(backend: B, timeout: Duration, drivers: &Drivers) -> Self {
+        let sev = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let iris = Self {
+            backend: Arc::new(backend),
+            timeout,
+            sev: sev.to_string(),
+            fetches: Arc::new(Mutex::new(HashMap::new())),
+            injector: drivers.injector(),
+        };
+        iris.injector
+            .inject(iris.event(tagged("born", Noun::from(0))));
+        iris
+    }
+
+    /// Make an event.
+    fn event(&self, card: Noun) -> Noun {
+        Noun::from(Effect {
+            wire: vec![String::new(), "http-client".to_string(), self.sev.clone()],
+            card,
+        })
+    }
+
+    /// Start fetching a request on its own thread.
+    fn request(&mut self, id: u64, req: Request) {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let replaced = self.fetches.lock().unwrap().insert(id, cancelled.clone());
+        if let Some(replaced) = replaced {
+            replaced.store(true, Ordering::Relaxed);
+        }
+        let (backend, timeout, fetches) =
+            (self.backend.clone(), self.timeout, self.fetches.clone());
+        let (injector, sev) = (self.injector.clone(), self.sev.clone());
+        thread::spawn(move || {
+            fetch(&*backend, &req, timeout, &cancelled, |event| {
+                injector.inject(Noun::from(Effect {
+                    wire: vec![String::new(), "http-client".to_string(), sev.clone()],
+                    card: tagged("receive", Noun::from((Noun::from(id), event))),
+                }))
+            });
+            let mut fetches = fetches.lock().unwrap();
+            if fetches
+                .get(&id)
+                .is_some_and(|fetch| Arc::ptr_eq(fetch, &cancelled))
+            {
+                fetches.remove(&id);
+            }
+        });
+    }
+}
+
+impl<B: Backend> Driver for Iris<B> {
+    fn name(&self) -> &str {
+        "http-client"
+    }
+
+    /// Start fetching for a `%request` card or stop fetching for a `%cancel-request` card.
+    /// Other cards are logged and ignored.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+        let (id, dat) = match &effect.card {
+            Noun::Cell(cell) => match cell.tail.as_ref() {
+                Noun::Cell(dat) => (dat.head.as_ref().clone(), dat.tail.as_ref().clone()),
+                Noun::Atom(id) => (Noun::from(id.clone()), Noun::from(0)),
+            },
+            Noun::Atom(_) => (Noun::from(0), Noun::from(0)),
+        };
+        let id = match id {
+            Noun::Atom(Atom::Direct(id)) => Some(id),
+            _ => None,
+        };
+        match (effect.tag().as_deref(), id) {
+            (Some("request"), Some(id)) => match Request::try_from(dat) {
+                Ok(req) => self.request(id, req),
+                Err(()) => eprintln!("iris: ignoring malformed effect {}", effect),
+            },
+            (Some("cancel-request"), Some(id)) => {
+                if let Some(cancelled) = self.fetches.lock().unwrap().remove(&id) {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            }
+            _ => eprintln!("iris: ignoring effect {}", effect),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc::{self, Receiver},
+    };
+
+    /// A response body whose reads produce the chunks sent through a channel, sleeping first
+    /// if asked to.
+    struct Chunks {
+        chunks: Receiver<Vec<u8>>,
+        delay: Duration,
+    }
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(self.delay);
+            let chunk = self.chunks.recv().unwrap_or_default();
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    /// A backend that answers every request with a body fed through a channel.
+    struct Fake {
+        body: Mutex<Option<Chunks>>,
+    }
+
+    impl Backend for Fake {
+        fn fetch(&self, req: &Request, _timeout: Duration) -> io::Result<Response> {
+            if req.url.starts_with("bad:") {
+                return Err(ErrorKind::InvalidInput.into());
+            }
+            Ok(Response {
+                status: 200,
+                headers: vec![("content-type".to_string(), "text/plain".to_string())],
+                body: Box::new(self.body.lock().unwrap().take().unwrap()),
+            })
+        }
+    }
+
+    fn request(id: u64, url: &str) -> Effect {
+        let req = Noun::from_tuple(vec![
+            Noun::from(Atom::from("GET")),
+            Noun::from(Atom::from(url)),
+            Noun::from(0),
+            Noun::from(0),
+        ]);
+        Effect {
+            wire: vec![String::new(), "http-client".to_string()],
+            card: tagged("request", Noun::from((Noun::from(id), req))),
+        }
+    }
+
+    /// Get the `http-event` of a `%receive` event for a request.
+    fn receive(id: u64, evt: Noun) -> Noun {
+        let evt = Effect::try_from(evt).unwrap();
+        assert_eq!(Some("receive".to_string()), evt.tag());
+        match evt.card {
+            Noun::Cell(cell) => match *cell.tail {
+                Noun::Cell(receive) => {
+                    assert_eq!(Noun::from(id), *receive.head);
+                    *receive.tail
+                }
+                Noun::Atom(_) => unreachable!(),
+            },
+            Noun::Atom(_) => unreachable!(),
+        }
+    }
+
+    fn start(data: &str, complete: bool) -> Noun {
+        let headers = vec![("content-type".to_string(), "text/plain".to_string())];
+        tagged(
+            "start",
+            Noun::from_tuple(vec![
+                Noun::from((Noun::from(200), header_list(&headers))),
+                octs(data.as_bytes()),
+                flag(complete),
+            ]),
+        )
+    }
+
+    #[test]
+    fn stream_cancel() {
+        let mut drivers = Drivers::new();
+        let (tx, chunks) = mpsc::channel();
+        let backend = Fake {
+            body: Mutex::new(Some(Chunks {
+                chunks,
+                delay: Duration::ZERO,
+            })),
+        };
+        let mut iris = Iris::new(backend, TIMEOUT, &drivers);
+        let marker = drivers.injector();
+        assert_eq!(
+            Some("born".to_string()),
+            Effect::try_from(drivers.wait().unwrap()).unwrap().tag()
+        );
+
+        // A failed fetch is cancelled.
+        iris.handle(request(1, "bad://")).unwrap();
+        assert_eq!(
+            tagged("cancel", Noun::from(0)),
+            receive(1, drivers.wait().unwrap())
+        );
+
+        // The body streams in as it arrives.
+        iris.handle(request(2, "http://example.com")).unwrap();
+        for chunk in ["a", "b", "c"] {
+            tx.send(chunk.as_bytes().to_vec()).unwrap();
+        }
+        assert_eq!(start("a", false), receive(2, drivers.wait().unwrap()));
+        assert_eq!(
+            tagged("continue", Noun::from((octs(b"b"), flag(false)))),
+            receive(2, drivers.wait().unwrap())
+        );
+
+        // Once the request is cancelled, the body stops being read and nothing more arrives.
+        iris.handle(Effect {
+            wire: vec![String::new(), "http-client".to_string()],
+            card: tagged("cancel-request", Noun::from(2)),
+        })
+        .unwrap();
+        for _ in 0..500 {
+            if tx.send(b"d".to_vec()).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(tx.send(Vec::new()).is_err());
+        marker.inject(Noun::from(0));
+        assert_eq!(Some(Noun::from(0)), drivers.wait());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut drivers = Drivers::new();
+        let (tx, chunks) = mpsc::channel();
+        let backend = Fake {
+            body: Mutex::new(Some(Chunks {
+                chunks,
+                delay: Duration::from_millis(200),
+            })),
+        };
+        let mut iris = Iris::new(backend, Duration::from_millis(100), &drivers);
+        drivers.wait().unwrap();
+        tx.send(b"slow".to_vec()).unwrap();
+        iris.handle(request(1, "http://example.com")).unwrap();
+        assert_eq!(
+            tagged("cancel", Noun::from(0)),
+            receive(1, drivers.wait().unwrap())
+        );
+    }
+
+    #[test]
+    fn ureq() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hi", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut inp = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            inp.read_line(&mut line).unwrap();
+            while inp.read_line(&mut String::new()).unwrap() > 2 {}
+            let mut out = stream;
+            write!(
+                out,
+                "HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\ncontent-length: 2\r\n\r\nhi"
+            )
+            .unwrap();
+            line
+        });
+
+        let mut drivers = Drivers::new();
+        let mut iris = Iris::new(Ureq, TIMEOUT, &drivers);
+        drivers.wait().unwrap();
+        iris.handle(request(1, &url)).unwrap();
+        let start = match receive(1, drivers.wait().unwrap()) {
+            Noun::Cell(cell) => cell.tail.into_tuple(3).unwrap(),
+            Noun::Atom(_) => unreachable!(),
+        };
+        assert_eq!(
+            (octs(b"hi"), flag(true)),
+            (start[1].clone(), start[2].clone())
+        );
+        assert_eq!("GET /hi HTTP/1.1\r\n", server.join().unwrap());
+    }
+}
@@ -0,0 +1,634 @@
+//! Khan, the control driver, which lets local tools drive the ship over a Unix socket in the
+//! pier, `.urb/conn.sock`, rather than by typing into its terminal.
+//!
+//! Tools and the driver exchange nouns in [`newt`](crate::newt) frames. Each request is
+//! `[id request]`, where `id` is any noun the tool picks, and is answered with `[id %ok result]`
+//! or `[id %error message]`, so that answers, which can arrive out of order, can be matched to
+//! their requests. A request is one of:
+//!
+//! - `[%fyrd desk thread mark mark noun]`, which runs a thread by name: Arvo's `%fyrd` task,
+//!   `[%fyrd bear name [mark page]]`, with the desk as the `bear`, the mark the thread's result
+//!   is to be converted to, and its argument as a `page` of a mark and a noun;
+//! - `[%poke agent mark noun]`, which pokes an agent on the ship with the noun under the mark.
+//!   It's run as the `%fyrd` of `%base`'s `%khan-eval` thread with the Hoon of a thread that
+//!   sends the agent a `%poke-as` of the noun, which Gall converts to the mark, and is answered
+//!   as a `%fyrd` is;
+//! - `[%ovum wire card]`, which injects an arbitrary event as the C runtime's `%ovum` does,
+//!   answered with `~` once it's injected, since the effects it produces go to the drivers its
+//!   wire names;
+//! - `[%status ~]`, answered with `[eve mug uptime bytes peak cap roots]`: the number of the
+//!   most recent event, the kernel's mug, the seconds since the pier was booted, the number of
+//!   bytes in use in the loom, the most ever in use and the most that can be, or 0 if unbounded,
+//!   and a list of `[name bytes]` attributing the bytes in use to the kernel and to `other`;
+//! - `[%peers ~]`, answered with a list of `[address sent sent-bytes failed heard heard-bytes]`
+//!   counting the packets the networking driver has exchanged with each peer, by address as a
+//!   lane gives it, or with an error if networking is off;
+//! - `[%snapshot ~]`, `[%pack ~]` or `[%exit ~]`, which save a snapshot of the kernel, compact
+//!   its loom allocations or shut the ship down, answered with `~`.
+//!
+//! The driver announces itself with a `[//khan/<sev> %born ~]` event, where `sev` identifies
+//! the run. Threads are injected as `[//khan/<sev>/<num> request]` events, and are answered with
+//! the card of the first effect on the event's wire, which is `[%avow (each page goof)]`, or
+//! with an error if the event is dropped. Effects on the wires of other runs are ignored.
+
+use crate::{
+    driver::{
+        ames::{to_address, Peers},
+        Driver, Drivers, Effect, Injector, Status,
+    },
+    error::{Context, Error},
+    event_log::Log,
+    newt,
+    noun::{cord, tagged},
+};
+use nock::{atom::Atom, noun::Noun, serdes::Jam};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufReader, ErrorKind},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::SystemTime,
+};
+
+/// The writing end of a tool's connection.
+type Out = Arc<Mutex<UnixStream>>;
+
+/// Requests waiting on their events, by the number on the events' wires, with each request's id
+/// and the connection to answer it on.
+type Pending = Arc<Mutex<HashMap<u64, (Noun, Out)>>>;
+
+/// What a tool asks for.
+#[derive(Clone, Debug, PartialEq)]
+enum Request {
+    /// Run a thread, injecting an event whose card is the request.
+    Fyrd(Noun),
+    /// Inject an event as it's given.
+    Ovum(Effect),
+    Status,
+    Peers,
+    Snapshot,
+    Pack,
+    Exit,
+}
+
+/// Parse a request.
+fn parse(req: Noun) -> Result<Request, String> {
+    let (tag, args) = match &req {
+        Noun::Cell(cell) => (cell.head.as_ref(), cell.tail.as_ref()),
+        Noun::Atom(_) => return Err("expected [%tag args]".to_string()),
+    };
+    let tag = match tag {
+        Noun::Atom(tag) => String::from_utf8(tag.to_bytes()).unwrap_or_default(),
+        Noun::Cell(_) => String::new(),
+    };
+    match tag.as_str() {
+        "fyrd" => {
+            let fields = args.clone().into_tuple(5).unwrap_or_default();
+            if fields.len() == 5
+                && fields[..4]
+                    .iter()
+                    .all(|field| matches!(field, Noun::Atom(_)))
+            {
+                Ok(Request::Fyrd(req))
+            } else {
+                Err("expected [%fyrd desk thread mark mark noun]".to_string())
+            }
+        }
+        "poke" => match args.clone().into_tuple(3).as_deref() {
+            Ok([Noun::Atom(agent), Noun::Atom(mark), noun]) => {
+                let agent = String::from_utf8(agent.to_bytes()).unwrap_or_default();
+                let mark = String::from_utf8(mark.to_bytes()).unwrap_or_default();
+                Ok(Request::Fyrd(poke(&agent, &mark, noun)))
+            }
+            _ => Err("expected [%poke agent mark noun]".to_string()),
+        },
+        "ovum" => Effect::try_from(args.clone())
+            .map(Request::Ovum)
+            .map_err(|_| "expected [%ovum wire card]".to_string()),
+        "status" => Ok(Request::Status),
+        "peers" => Ok(Request::Peers),
+        "snapshot" => Ok(Request::Snapshot),
+        "pack" => Ok(Request::Pack),
+        "exit" => Ok(Request::Exit),
+        _ => Err(format!("unknown request %{}", tag)),
+    }
+}
+
+/// Make the `%fyrd` card of a thread that pokes an agent on the ship with a noun under a mark.
+fn poke(agent: &str, mark: &str, noun: &Noun) -> Noun {
+    let hoon = format!(
+        "=/  m  (strand ,vase)\n\
+         ;<  our=@p  bind:m  get-our\n\
+         ;<  ~  bind:m  (send-raw-card [%pass /poke %agent [our %{}] %poke-as %{} %noun !>((cue {}))])\n\
+         ;<  ~  bind:m  (take-poke-ack /poke)\n\
+         (pure:m !>(~))",
+        agent,
+        mark,
+        ux(&noun.jam()),
+    );
+    Noun::from_tuple(vec![
+        cord("fyrd"),
+        cord("base"),
+        cord("khan-eval"),
+        cord("noun"),
+        cord("ted-eval"),
+        cord(&hoon),
+    ])
+}
+
+/// Print an atom, given as its little-endian bytes, as a Hoon `@ux` literal.
+fn ux(bytes: &[u8]) -> String {
+    let hex: String = bytes
+        .iter()
+        .rev()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let hex = hex.trim_start_matches('0');
+    if hex.is_empty() {
+        return "0x0".to_string();
+    }
+    let lead = match hex.len() % 4 {
+        0 => 4,
+        lead => lead,
+    };
+    let mut lit = format!("0x{}", &hex[..lead]);
+    for group in hex.as_bytes()[lead..].chunks(4) {
+        lit.push('.');
+        lit.push_str(std::str::from_utf8(group).unwrap_or_default());
+    }
+    lit
+}
+
+/// Make the result of a `%status` request.
+fn status(status: Status) -> Noun {
+    let mass = status.mass;
+    let other = ("other".to_string(), mass.other.bytes);
+    let roots = mass
+        .roots
+        .into_iter()
+        .map(|root| (root.name, root.bytes))
+        .chain([other])
+        .map(|(name, bytes)| {
+            Noun::from((
+                Noun::from(Atom::from(name.as_str())),
+                Noun::from(bytes as u64),
+            ))
+        })
+        .collect();
+    Noun::from_tuple(vec![
+        Noun::from(status.evt_num),
+        Noun::from(u64::from(status.mug)),
+        Noun::from(status.uptime.as_secs()),
+        Noun::from(mass.live.bytes as u64),
+        Noun::from(mass.live.peak as u64),
+        Noun::from(mass.cap.unwrap_or(0) as u64),
+        Noun::from_list(roots),
+    ])
+}
+
+/// Make the result of a `%peers` request, ordered by address.
+fn peers(peers: &Peers) -> Noun {
+    let mut peers: Vec<_> = peers.lock().unwrap().clone().into_iter().collect();
+    peers.sort_by_key(|(addr, _)| (u32::from(*addr.ip()), addr.port()));
+    Noun::from_list(
+        peers
+            .into_iter()
+            .map(|(addr, stats)| {
+                Noun::from_tuple(vec![
+                    to_address(addr),
+                    Noun::from(stats.sent),
+                    Noun::from(stats.sent_bytes),
+                    Noun::from(stats.failed),
+                    Noun::from(stats.heard),
+                    Noun::from(stats.heard_bytes),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Answer a request, ignoring a tool that's gone.
+fn answer(out: &Out, id: Noun, res: Result<Noun, String>) {
+    let res = match res {
+        Ok(res) => tagged("ok", res),
+        Err(msg) => tagged("error", Noun::from(Atom::from(msg.as_str()))),
+    };
+    let _ = newt::write(&mut *out.lock().unwrap(), &Noun::from((id, res)));
+}
+
+/// Answer a tool's requests until it disconnects or sends something that isn't a newt frame.
+/// Threads are left to be answered once their events are processed.
+fn serve(
+    stream: UnixStream,
+    injector: Injector,
+    pending: Pending,
+    sev: String,
+    next: Arc<AtomicU64>,
+    stats: Option<Peers>,
+) {
+    let out = match stream.try_clone() {
+        Ok(out) => Arc::new(Mutex::new(out)),
+        Err(err) => {
+            eprintln!("khan: failed to serve a connection: {}", err);
+            return;
+        }
+    };
+    let mut inp = BufReader::new(stream);
+    loop {
+        let (id, req) = match newt::read(&mut inp) {
+            Ok(Some(Noun::Cell(cell))) => (*cell.head, parse(*cell.tail)),
+            Ok(Some(Noun::Atom(_))) => (Noun::from(0), Err("expected [id request]".to_string())),
+            Ok(None) => return,
+            Err(err) => {
+                eprintln!("khan: {}", err);
+                return;
+            }
+        };
+        let res = match req {
+            Ok(Request::Fyrd(card)) => {
+                let num = next.fetch_add(1, Ordering::Relaxed);
+                pending.lock().unwrap().insert(num, (id, out.clone()));
+                let wire = vec![
+                    String::new(),
+                    "khan".to_string(),
+                    sev.clone(),
+                    num.to_string(),
+                ];
+                if !injector.inject(Noun::from(Effect { wire, card })) {
+                    if let Some((id, out)) = pending.lock().unwrap().remove(&num) {
+                        answer(&out, id, Err("the ship is shutting down".to_string()));
+                    }
+                }
+                continue;
+            }
+            Ok(Request::Ovum(ovum)) => {
+                if injector.inject(Noun::from(ovum)) {
+                    Ok(Noun::from(0))
+                } else {
+                    Err("the ship is shutting down".to_string())
+                }
+            }
+            Ok(Request::Status) => injector.status().map(status),
+            Ok(Request::Peers) => stats
+                .as_ref()
+                .map(peers)
+                .ok_or_else(|| "networking is off".to_string()),
+            Ok(Request::Snapshot) => injector.snapshot().map(|()| Noun::from(0)),
+            Ok(Request::Pack) => injector.pack().map(|()| Noun::from(0)),
+            Ok(Request::Exit) => {
+                answer(&out, id, Ok(Noun::from(0)));
+                injector.exit();
+                continue;
+            }
+            Err(msg) => Err(msg),
+        };
+        answer(&out, id, res);
+    }
+}
+
+/// The control driver, whose socket is removed when it's dropped.
+pub struct Khan {
+    path: PathBuf,
+    /// Identifies this run of the driver in wires.
+    sev: String,
+    pending: Pending,
+}
+
+impl Khan {
+    /// Get the path of a pier's control socket.
+    pub fn path(pier: &Path) -> PathBuf {
+        Log::path(pier).with_file_name("conn.sock")
+    }
+
+    /// Start answering requests on a pier's control socket, replacing any socket left behind by
+    /// an earlier run, and announce the driver with a `%born` event. `peers` are the packet
+    /// counts of the networking driver, if it's running.
+    pub fn start(pier: &Path, drivers: &Drivers, peers: Option<Peers>) -> Result<Self, Error> {
+        let path = Self::path(pier);
+        match fs::remove_file(&path) {
+            Err(err) if ErrorKind::NotFound != err.kind() => return Err(err).at_path(&path),
+            _ => {}
+        }
+        let listener = UnixListener::bind(&path).at_path(&path)?;
+        let sev = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+        let pending = Pending::default();
+        let injector = drivers.injector();
+        injector.inject(Noun::from(Effect {
+            wire: vec![String::new(), "khan".to_string(), sev.clone()],
+            card: tagged("born", Noun::from(0)),
+        }));
+        let (serving, serving_sev) = (pending.clone(), sev.clone());
+        thread::spawn(move || {
+            let next = Arc::new(AtomicU64::new(0));
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let (injector, pending, sev, next, peers) = (
+                            injector.clone(),
+                            serving.clone(),
+                            serving_sev.clone(),
+                            next.clone(),
+                            peers.clone(),
+                        );
+                        thread::spawn(move || serve(stream, injector, pending, sev, next, peers));
+                    }
+                    Err(err) => eprintln!("khan: failed to accept a connection: {}", err),
+                }
+            }
+        });
+        Ok(Self { path, sev, pending })
+    }
+
+    /// Take the request waiting on the event with a wire, which must be from this run.
+    fn take(&self, wire: &[String]) -> Option<(Noun, Out)> {
+        match wire {
+            [_, _, sev, num] if *sev == self.sev => {
+                self.pending.lock().unwrap().remove(&num.parse().ok()?)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Driver for Khan {
+    fn name(&self) -> &str {
+        "khan"
+    }
+
+    /// Answer the request whose event the effect is on the wire of with the effect's card.
+    /// Other effects are logged and ignored.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+        match self.take(&effect.wire) {
+            Some((id, out)) => answer(&out, id, Ok(effect.card)),
+            None => eprintln!("khan: ignoring effect {}", effect),
+        }
+        Ok(())
+    }
+
+    /// Answer the request whose event was dropped with the reason.
+    fn dropped(&mut self, wire: &[String], err: &Error) {
+        if let Some((id, out)) = self.take(wire) {
+            answer(&out, id, Err(err.to_string()));
+        }
+    }
+}
+
+impl Drop for Khan {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        driver::{ames, Ask},
+        noun::text,
+    };
+    use loom::{mark::Root, mass::Mass, Stats};
+    use std::{
+        env,
+        net::{Ipv4Addr, SocketAddrV4},
+        process,
+        time::Duration,
+    };
+
+    #[test]
+    fn poke() {
+        assert_eq!("0x0", ux(&[]));
+        assert_eq!("0x1.2345", ux(&[0x45, 0x23, 0x01]));
+        assert_eq!("0x1234.abcd", ux(&[0xcd, 0xab, 0x34, 0x12]));
+
+        // A poke runs a thread that pokes the agent with the jam of the noun.
+        let req = Noun::from_tuple(vec![
+            cord("poke"),
+            cord("hood"),
+            cord("helm-hi"),
+            cord("hi"),
+        ]);
+        let fields = match parse(req) {
+            Ok(Request::Fyrd(card)) => card.into_tuple(6).unwrap(),
+            req => panic!("unexpected {:?}", req),
+        };
+        assert_eq!(
+            vec![
+                cord("fyrd"),
+                cord("base"),
+                cord("khan-eval"),
+                cord("noun"),
+                cord("ted-eval")
+            ],
+            fields[..5]
+        );
+        let hoon = text(&fields[5]).unwrap();
+        let send = format!(
+            "[our %hood] %poke-as %helm-hi %noun !>((cue {}))",
+            ux(&cord("hi").jam())
+        );
+        assert!(hoon.contains(&send), "{}", hoon);
+        assert!(hoon.contains("(take-poke-ack /poke)"));
+        assert_eq!(
+            Err("expected [%poke agent mark noun]".to_string()),
+            parse(tagged("poke", cord("hood")))
+        );
+    }
+
+    #[test]
+    fn socket() {
+        let pier = env::temp_dir().join(format!("vere-khan-{}", process::id()));
+        fs::create_dir_all(Log::path(&pier).parent().unwrap()).unwrap();
+        let mut drivers = Drivers::new();
+        let peer = SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 31337);
+        let stats = ames::Stats {
+            sent: 1,
+            sent_bytes: 2,
+            failed: 3,
+            heard: 4,
+            heard_bytes: 5,
+        };
+        let peers = Peers::new(Mutex::new(HashMap::from([(peer, stats)])));
+        let mut khan = Khan::start(&pier, &drivers, Some(peers)).unwrap();
+        let ovum = Effect {
+            wire: vec![String::new(), "behn".to_string()],
+            card: tagged("wake", Noun::from(0)),
+        };
+        let fyrd = |thread: &str| {
+            Noun::from_tuple(vec![
+                cord("fyrd"),
+                cord("base"),
+                cord(thread),
+                cord("noun"),
+                cord("noun"),
+                Noun::from(0),
+            ])
+        };
+        let requests = vec![
+            tagged("ovum", Noun::from(ovum.clone())),
+            fyrd("hi"),
+            fyrd("crash"),
+            tagged("status", Noun::from(0)),
+            tagged("snapshot", Noun::from(0)),
+            tagged("pack", Noun::from(0)),
+            tagged(
+                "fyrd",
+                Noun::from_tuple(vec![cord("base"), cord("hi"), Noun::from(0)]),
+            ),
+            tagged("ovum", Noun::from(1)),
+            tagged("peers", Noun::from(0)),
+            tagged("exit", Noun::from(0)),
+        ];
+        let count = requests.len();
+        let path = Khan::path(&pier);
+        let client = thread::spawn(move || {
+            let mut stream = UnixStream::connect(path).unwrap();
+            for (id, req) in requests.into_iter().enumerate() {
+                newt::write(&mut stream, &Noun::from((Noun::from(id as u64), req))).unwrap();
+            }
+            (0..count)
+                .map(|_| newt::read(&mut stream).unwrap().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        // The driver is born, ova are injected as given, and threads are injected and answered
+        // by the effects on their wires or by their being dropped, but not by those on the
+        // wires of an earlier run.
+        let mut serve = |ask| match ask {
+            Ask::Status { reply } => {
+                let _ = reply.send(Ok(Status {
+                    evt_num: 7,
+                    mug: 0xbeef,
+                    mass: Mass {
+                        roots: vec![Root {
+                            name: "kernel".to_string(),
+                            allocs: 4,
+                            bytes: 1000,
+                        }],
+                        other: Stats {
+                            allocs: 1,
+                            bytes: 24,
+                            peak: 2048,
+                        },
+                        live: Stats {
+                            allocs: 5,
+                            bytes: 1024,
+                            peak: 2048,
+                        },
+                        cap: None,
+                    },
+                    uptime: Duration::from_secs(60),
+                }));
+            }
+            Ask::Snapshot { reply } => {
+                let _ = reply.send(Ok(()));
+            }
+            Ask::Pack { reply } => {
+                let _ = reply.send(Err("no room".to_string()));
+            }
+            Ask::Peek { .. } => unreachable!(),
+        };
+        let sev = khan.sev.clone();
+        let wire = |num: u64| {
+            vec![
+                String::new(),
+                "khan".to_string(),
+                sev.clone(),
+                num.to_string(),
+            ]
+        };
+        let avow = tagged(
+            "avow",
+            Noun::from((Noun::from(0), Noun::from((cord("noun"), cord("hi"))))),
+        );
+        assert_eq!(
+            Some(Noun::from(Effect {
+                wire: wire(0)[..3].to_vec(),
+                card: tagged("born", Noun::from(0)),
+            })),
+            drivers.wait_serving(&mut serve)
+        );
+        assert_eq!(Some(Noun::from(ovum)), drivers.wait_serving(&mut serve));
+        assert_eq!(
+            Some(Noun::from(Effect {
+                wire: wire(0),
+                card: fyrd("hi")
+            })),
+            drivers.wait_serving(&mut serve)
+        );
+        let mut stale = wire(0);
+        stale[2] = "0".to_string();
+        khan.dropped(&stale, &Error::Ipc("serf exited".to_string()));
+        khan.handle(Effect {
+            wire: wire(0),
+            card: avow.clone(),
+        })
+        .unwrap();
+        assert_eq!(
+            Some(Noun::from(Effect {
+                wire: wire(1),
+                card: fyrd("crash")
+            })),
+            drivers.wait_serving(&mut serve)
+        );
+        khan.dropped(&wire(1), &Error::Ipc("serf exited".to_string()));
+        khan.dropped(&wire(1), &Error::Ipc("serf exited".to_string()));
+
+        // The rest are answered by the king, and the last asks for no more events.
+        assert_eq!(None, drivers.wait_serving(&mut serve));
+        let answers = client.join().unwrap();
+        let ok = |id: u64, res: Noun| Noun::from((Noun::from(id), tagged("ok", res)));
+        let error = |id: u64, msg: &str| Noun::from((Noun::from(id), tagged("error", cord(msg))));
+        assert_eq!(
+            vec![
+                ok(0, Noun::from(0)),
+                ok(1, avow),
+                error(2, "protocol violation: serf exited"),
+                ok(
+                    3,
+                    Noun::from_tuple(vec![
+                        Noun::from(7),
+                        Noun::from(0xbeef),
+                        Noun::from(60),
+                        Noun::from(1024),
+                        Noun::from(2048),
+                        Noun::from(0),
+                        Noun::from_list(vec![
+                            Noun::from((cord("kernel"), Noun::from(1000))),
+                            Noun::from((cord("other"), Noun::from(24))),
+                        ]),
+                    ])
+                ),
+                ok(4, Noun::from(0)),
+                error(5, "no room"),
+                error(6, "expected [%fyrd desk thread mark mark noun]"),
+                error(7, "expected [%ovum wire card]"),
+                ok(
+                    8,
+                    Noun::from_list(vec![Noun::from_tuple(
+                        [to_address(peer)]
+                            .into_iter()
+                            .chain([1, 2, 3, 4, 5].map(Noun::from))
+                            .collect()
+                    )])
+                ),
+                ok(9, Noun::from(0)),
+            ],
+            answers
+        );
+
+        // The socket is removed with the driver.
+        drop(khan);
+        assert!(!Khan::path(&pier).exists());
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
@@ -0,0 +1,464 @@
+//! I/O drivers, which carry out the effects the kernel produces and produce the events it
+//! processes.
+//!
+//! Every effect is `[wire card]`, and the first segment of its wire names the driver that
+//! handles it, e.g. `/behn` or `/http-server`. Drivers are registered with [`Drivers`], which
+//! routes each effect to its driver and collects the events drivers produce, either when a
+//! driver's deadline passes or from another thread through an [`Injector`]. Other threads can
+//! also make requests of the king through an injector, which are answered between events. A
+//! driver is told when an event it produced is dropped, so it can report the failure.
+
+pub mod ames;
+pub mod behn;
+pub mod clay;
+pub mod dill;
+pub mod eyre;
+pub mod iris;
+pub mod khan;
+
+use crate::error::Error;
+use loom::mass::Mass;
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+/// An effect of an event: a card to carry out and the wire that identifies its cause.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Effect {
+    pub wire: Vec<String>,
+    pub card: Noun,
+}
+
+impl Effect {
+    /// Parse the effects an event produced, which are a list of `[wire card]`, each wire being
+    /// a list of text segments.
+    pub fn parse_list(fec: Noun) -> Result<Vec<Self>, Error> {
+        let bad = |msg: &str| Error::Crash {
+            arm: "poke",
+            msg: msg.to_string(),
+            source: None,
+        };
+        fec.into_list()
+            .map_err(|_| bad("produced effects that aren't a list"))?
+            .into_iter()
+            .map(|effect| Self::try_from(effect).map_err(|_| bad("produced a malformed effect")))
+            .collect()
+    }
+
+    /// Get the name of the driver the effect is for, which is the first segment of its wire
+    /// after the empty segment Arvo's wires start with, e.g. `behn` for `//behn/0v1`.
+    pub fn driver(&self) -> Option<&str> {
+        driver(&self.wire)
+    }
+
+    /// Parse a wire, which is a list of text segments.
+    pub fn parse_wire(wire: Noun) -> Option<Vec<String>> {
+        let segments = wire.into_list().ok()?;
+        let mut wire = Vec::with_capacity(segments.len());
+        for segment in segments {
+            match segment {
+                Noun::Atom(atom) => wire.push(String::from_utf8(atom.to_bytes()).ok()?),
+                Noun::Cell(_) => return None,
+            }
+        }
+        Some(wire)
+    }
+
+    /// Get the card's tag, e.g. `doze` for `[%doze ~]`.
+    pub fn tag(&self) -> Option<String> {
+        match &self.card {
+            Noun::Cell(cell) => match cell.head.as_ref() {
+                Noun::Atom(tag) => String::from_utf8(tag.to_bytes()).ok(),
+                Noun::Cell(_) => None,
+            },
+            Noun::Atom(_) => None,
+        }
+    }
+}
+
+/// Effect from Noun.
+impl TryFrom<Noun> for Effect {
+    type Error = Noun;
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let cell = match noun {
+            Noun::Cell(cell) => cell,
+            atom => return Err(atom),
+        };
+        let wire = match Self::parse_wire(cell.head.as_ref().clone()) {
+            Some(wire) => wire,
+            None => return Err(Noun::Cell(cell)),
+        };
+        Ok(Self {
+            wire,
+            card: *cell.tail,
+        })
+    }
+}
+
+/// Noun from Effect.
+impl From<Effect> for Noun {
+    fn from(effect: Effect) -> Self {
+        let wire = effect
+            .wire
+            .iter()
+            .map(|segment| Noun::from(Atom::from(segment.as_str())))
+            .collect();
+        Noun::from((Noun::from_list(wire), effect.card))
+    }
+}
+
+/// Get the name of the driver a wire is for, skipping the empty segment Arvo's wires start with.
+fn driver(wire: &[String]) -> Option<&str> {
+    let mut wire = wire.iter().map(String::as_str).peekable();
+    wire.next_if_eq(&"");
+    wire.next()
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.wire {
+            write!(f, "/{}", segment)?;
+        }
+        if self.wire.is_empty() {
+            write!(f, "/")?;
+        }
+        match self.tag() {
+            Some(tag) => write!(f, " %{}", tag),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An I/O driver, which carries out the effects whose wires start with its name.
+pub trait Driver {
+    /// Get the driver's name, e.g. `behn`.
+    fn name(&self) -> &str;
+
+    /// Carry out an effect.
+    fn handle(&mut self, effect: Effect) -> Result<(), Error>;
+
+    /// Produce the events that are due, e.g. because a timer has fired.
+    fn poll(&mut self) -> Vec<Noun> {
+        Vec::new()
+    }
+
+    /// Get how long until the driver next has events due, if it's waiting on a deadline.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Learn that an event on one of the driver's wires was dropped, because it crashed the
+    /// kernel or the serf died.
+    fn dropped(&mut self, _wire: &[String], _err: &Error) {}
+}
+
+/// The result of a peek, as [`crate::king::King::peek`] produces it, or why it failed.
+pub type Peeked = Result<Option<Option<Noun>>, String>;
+
+/// The state of a running ship, as the king reports it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Status {
+    /// Number of the most recent event.
+    pub evt_num: u64,
+    /// Mug of the kernel as of the most recent event.
+    pub mug: u32,
+    /// Loom usage, attributed to the kernel and everything else.
+    pub mass: Mass,
+    /// Time since the pier was booted.
+    pub uptime: Duration,
+}
+
+/// A request of the king, made from another thread and answered between events.
+pub enum Ask {
+    /// Read from the kernel at a path.
+    Peek { path: Noun, reply: Sender<Peeked> },
+    /// Report the ship's state.
+    Status {
+        reply: Sender<Result<Status, String>>,
+    },
+    /// Save a snapshot of the kernel.
+    Snapshot { reply: Sender<Result<(), String>> },
+    /// Compact the kernel's loom allocations.
+    Pack { reply: Sender<Result<(), String>> },
+}
+
+/// What an injector feeds to [`Drivers`].
+enum Input {
+    Ovum(Noun),
+    Ask(Ask),
+    Exit,
+}
+
+/// A handle that feeds events to [`Drivers`] from another thread.
+#[derive(Clone)]
+pub struct Injector(Sender<Input>);
+
+impl Injector {
+    /// Feed an event, failing if the drivers are gone.
+    pub fn inject(&self, ovum: Noun) -> bool {
+        self.0.send(Input::Ovum(ovum)).is_ok()
+    }
+
+    /// Make a request of the king, failing if the drivers are gone.
+    pub fn ask(&self, ask: Ask) -> bool {
+        self.0.send(Input::Ask(ask)).is_ok()
+    }
+
+    /// Make a request of the king and wait for the answer.
+    fn request<T>(&self, ask: impl FnOnce(Sender<Result<T, String>>) -> Ask) -> Result<T, String> {
+        let (reply, answer) = mpsc::channel();
+        if !self.ask(ask(reply)) {
+            return Err("the ship is shutting down".to_string());
+        }
+        answer
+            .recv()
+            .unwrap_or_else(|_| Err("the request wasn't answered".to_string()))
+    }
+
+    /// Read from the kernel at a path, waiting for the king to answer.
+    pub fn peek(&self, path: Noun) -> Peeked {
+        self.request(|reply| Ask::Peek { path, reply })
+    }
+
+    /// Get the ship's state, waiting for the king to answer.
+    pub fn status(&self) -> Result<Status, String> {
+        self.request(|reply| Ask::Status { reply })
+    }
+
+    /// Save a snapshot of the kernel, waiting for the king to finish.
+    pub fn snapshot(&self) -> Result<(), String> {
+        self.request(|reply| Ask::Snapshot { reply })
+    }
+
+    /// Compact the kernel's loom allocations, waiting for the king to finish.
+    pub fn pack(&self) -> Result<(), String> {
+        self.request(|reply| Ask::Pack { reply })
+    }
+
+    /// Ask for no more events to be processed.
+    pub fn exit(&self) {
+        let _ = self.0.send(Input::Exit);
+    }
+}
+
+/// The registered drivers, by name, and the events they've produced.
+pub struct Drivers {
+    drivers: HashMap<String, Box<dyn Driver>>,
+    /// Events that are due but haven't been processed.
+    pending: VecDeque<Noun>,
+    /// Sender that injectors are cloned from, which is dropped once events are first
+    /// processed so that the channel closes when the last injector is dropped.
+    tx: Option<Sender<Input>>,
+    rx: Receiver<Input>,
+}
+
+impl Default for Drivers {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            drivers: HashMap::new(),
+            pending: VecDeque::new(),
+            tx: Some(tx),
+            rx,
+        }
+    }
+}
+
+impl Drivers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a handle for feeding events from another thread. Every injector must be made before
+    /// events are first processed with [`Drivers::wait`].
+    pub fn injector(&self) -> Injector {
+        let tx = self
+            .tx
+            .as_ref()
+            .expect("injectors must be made before events are processed");
+        Injector(tx.clone())
+    }
+
+    /// Register a driver, producing the driver it replaces if one was registered with the same
+    /// name.
+    pub fn register(&mut self, driver: Box<dyn Driver>) -> Option<Box<dyn Driver>> {
+        self.drivers.insert(driver.name().to_string(), driver)
+    }
+
+    /// Get the registered driver with a name.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut (dyn Driver + 'static)> {
+        self.drivers.get_mut(name).map(Box::as_mut)
+    }
+
+    /// Hand each effect to the driver it's for, in order. An effect without a driver is logged
+    /// and dropped. Every effect is handed off even if a driver fails, and the first failure is
+    /// reported.
+    pub fn dispatch(&mut self, effects: Vec<Effect>) -> Result<(), Error> {
+        let mut res = Ok(());
+        for effect in effects {
+            let driver = effect.driver().and_then(|name| self.drivers.get_mut(name));
+            match driver {
+                Some(driver) => {
+                    let handled = driver.handle(effect);
+                    if res.is_ok() {
+                        res = handled;
+                    }
+                }
+                None => eprintln!("vere: no driver for effect {}", effect),
+            }
+        }
+        res
+    }
+
+    /// Tell the driver an event was for, which is named by the event's wire like an effect's,
+    /// that the event was dropped.
+    pub fn dropped(&mut self, wire: &[String], err: &Error) {
+        if let Some(driver) = driver(wire).and_then(|name| self.drivers.get_mut(name)) {
+            driver.dropped(wire, err);
+        }
+    }
+
+    /// Wait for the next event to process: one that a driver has due or one that's injected.
+    /// Produces nothing once an injector asks for no more events, or once no more events can
+    /// arrive because no driver is waiting on a deadline and every injector is gone. Requests
+    /// of the king that arrive in the meantime go unanswered.
+    pub fn wait(&mut self) -> Option<Noun> {
+        self.wait_serving(&mut drop)
+    }
+
+    /// Wait for the next event to process like [`Drivers::wait`], handing the requests of the
+    /// king that arrive in the meantime to `serve`.
+    pub fn wait_serving(&mut self, serve: &mut dyn FnMut(Ask)) -> Option<Noun> {
+        self.tx = None;
+        loop {
+            if let Some(ovum) = self.pending.pop_front() {
+                return Some(ovum);
+            }
+            for driver in self.drivers.values_mut() {
+                self.pending.extend(driver.poll());
+            }
+            if !self.pending.is_empty() {
+                continue;
+            }
+            let timeout = self
+                .drivers
+                .values()
+                .filter_map(|driver| driver.timeout())
+                .min();
+            let input = match timeout {
+                Some(timeout) => match self.rx.recv_timeout(timeout) {
+                    Ok(input) => input,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        thread::sleep(timeout);
+                        continue;
+                    }
+                },
+                None => self.rx.recv().ok()?,
+            };
+            match input {
+                Input::Ovum(ovum) => return Some(ovum),
+                Input::Ask(ask) => serve(ask),
+                Input::Exit => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// A driver that records the effects it handles, and fails on `%fail` cards.
+    struct Recorder {
+        name: &'static str,
+        handled: Rc<RefCell<Vec<Effect>>>,
+    }
+
+    impl Driver for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn handle(&mut self, effect: Effect) -> Result<(), Error> {
+            let fail = Some("fail".to_string()) == effect.tag();
+            self.handled.borrow_mut().push(effect);
+            if fail {
+                Err(Error::Ipc("failed".to_string()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn effect(wire: &[&str], tag: &str) -> Effect {
+        Effect {
+            wire: wire.iter().map(|segment| segment.to_string()).collect(),
+            card: Noun::from((Noun::from(Atom::from(tag)), Noun::from(0))),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let effects = vec![effect(&["behn", "0v1"], "doze"), effect(&[], "blit")];
+        let fec = Noun::from_list(effects.iter().cloned().map(Noun::from).collect());
+        assert_eq!(effects, Effect::parse_list(fec).unwrap());
+        assert_eq!("/behn/0v1 %doze", effects[0].to_string());
+        assert_eq!(None, effects[1].driver());
+
+        // Reject effects that aren't a list of [wire card].
+        for fec in [
+            Noun::from((Noun::from(0), Noun::from(1))),
+            Noun::from_list(vec![Noun::from(1)]),
+            Noun::from_list(vec![Noun::from((
+                Noun::from_list(vec![Noun::from((Noun::from(1), Noun::from(2)))]),
+                Noun::from(0),
+            ))]),
+        ] {
+            assert!(matches!(
+                Effect::parse_list(fec),
+                Err(Error::Crash { arm: "poke", .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn dispatch() {
+        let behn = Rc::new(RefCell::new(Vec::new()));
+        let dill = Rc::new(RefCell::new(Vec::new()));
+        let mut drivers = Drivers::new();
+        for (name, handled) in [("behn", &behn), ("dill", &dill)] {
+            assert!(drivers
+                .register(Box::new(Recorder {
+                    name,
+                    handled: handled.clone(),
+                }))
+                .is_none());
+        }
+
+        // Effects go to the driver named by their wires, and unrouted effects are dropped.
+        let effects = vec![
+            effect(&["behn"], "doze"),
+            effect(&["ames"], "send"),
+            effect(&["dill", "1"], "fail"),
+            effect(&["behn"], "doze"),
+        ];
+        assert!(matches!(
+            drivers.dispatch(effects.clone()),
+            Err(Error::Ipc(_))
+        ));
+        assert_eq!(vec![effects[0].clone(), effects[3].clone()], *behn.borrow());
+        assert_eq!(vec![effects[2].clone()], *dill.borrow());
+        assert_eq!(
+            Some("behn"),
+            drivers.get_mut("behn").map(|driver| driver.name())
+        );
+    }
+}
@@ -1,12 +1,201 @@
-use std::io;
+use crate::event_log::replay::Divergence;
+use std::{
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug)]
 pub enum Error {
-    StdIo,
+    /// An I/O operation failed, on the file at `path` if known.
+    Io {
+        source: io::Error,
+        path: Option<PathBuf>,
+    },
+    /// A jammed noun couldn't be decoded.
+    Cue(nock::error::Error),
+    /// Nock evaluation failed outside of a kernel arm.
+    Nock(nock::error::Error),
+    Lmdb(lmdb::Error),
+    BadSnapshot(String),
+    /// A snapshot's kernel doesn't have the mug recorded for its event.
+    SnapshotMismatch {
+        evt_num: u64,
+        expected: u32,
+        found: u32,
+    },
+    BadConfig(String),
+    BadPill(String),
+    BadEventLog(String),
+    /// A kernel arm crashed, with the interpreter's error if it produced one rather than
+    /// panicking or producing a malformed result.
+    Crash {
+        arm: &'static str,
+        msg: String,
+        source: Option<nock::error::Error>,
+    },
+    Diverged(Box<Divergence>),
+    /// The pier is locked by the running process with this ID.
+    PierLive(u32),
+    /// A peer process violated the protocol it's speaking.
+    Ipc(String),
+    /// An error concerning the file or directory at `path`.
+    Path {
+        path: PathBuf,
+        source: Box<Error>,
+    },
+    /// An error concerning event `evt_num`.
+    Event {
+        evt_num: u64,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Get the error underneath any path or event context.
+    pub fn root(&self) -> &Self {
+        match self {
+            Error::Path { source, .. } | Error::Event { source, .. } => source.root(),
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io {
+                source,
+                path: Some(path),
+            } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { source, path: None } => write!(f, "{}", source),
+            Error::Cue(err) => write!(f, "bad jam: {}", err),
+            Error::Nock(err) => write!(f, "nock: {}", err),
+            Error::Lmdb(err) => write!(f, "lmdb: {}", err),
+            Error::BadSnapshot(msg) => write!(f, "bad snapshot: {}", msg),
+            Error::SnapshotMismatch {
+                evt_num,
+                expected,
+                found,
+            } => write!(
+                f,
+                "snapshot of event {} has kernel mug {:#x} but {:#x} was recorded",
+                evt_num, found, expected
+            ),
+            Error::BadConfig(msg) => write!(f, "bad configuration: {}", msg),
+            Error::BadPill(msg) => write!(f, "bad pill: {}", msg),
+            Error::BadEventLog(msg) => write!(f, "bad event log: {}", msg),
+            Error::Crash { arm, msg, .. } => write!(f, "{} crashed: {}", arm, msg),
+            Error::Diverged(div) => write!(f, "{}", div),
+            Error::PierLive(pid) => write!(f, "pier is running in process {}", pid),
+            Error::Ipc(msg) => write!(f, "protocol violation: {}", msg),
+            Error::Path { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Event { evt_num, source } => write!(f, "event {}: {}", evt_num, source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Cue(err) | Error::Nock(err) => Some(err),
+            Error::Crash {
+                source: Some(err), ..
+            } => Some(err),
+            Error::Lmdb(err) => Some(err),
+            Error::Path { source, .. } | Error::Event { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
 }
 
 impl From<io::Error> for Error {
-    fn from(_err: io::Error) -> Self {
-        Error::StdIo
+    fn from(err: io::Error) -> Self {
+        Error::Io {
+            source: err,
+            path: None,
+        }
+    }
+}
+
+/// Decoding failures are cue errors and everything else is an evaluation error.
+impl From<nock::error::Error> for Error {
+    fn from(err: nock::error::Error) -> Self {
+        match err {
+            nock::error::Error::MalformedJam(..) => Error::Cue(err),
+            err => Error::Nock(err),
+        }
+    }
+}
+
+impl From<lmdb::Error> for Error {
+    fn from(err: lmdb::Error) -> Self {
+        Error::Lmdb(err)
+    }
+}
+
+/// Attach context to the error of a result.
+pub trait Context<T> {
+    /// Say that the error concerns the file or directory at `path`. An I/O error that doesn't
+    /// name a path takes on `path` itself.
+    fn at_path(self, path: &Path) -> Result<T, Error>;
+
+    /// Say that the error concerns event `evt_num`.
+    fn at_event(self, evt_num: u64) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn at_path(self, path: &Path) -> Result<T, Error> {
+        self.map_err(|err| match err.into() {
+            Error::Io { source, path: None } => Error::Io {
+                source,
+                path: Some(path.to_path_buf()),
+            },
+            err @ Error::Io { .. } => err,
+            err => Error::Path {
+                path: path.to_path_buf(),
+                source: Box::new(err),
+            },
+        })
+    }
+
+    fn at_event(self, evt_num: u64) -> Result<T, Error> {
+        self.map_err(|err| Error::Event {
+            evt_num,
+            source: Box::new(err.into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn context() {
+        let io = || Err::<(), _>(io::Error::new(io::ErrorKind::NotFound, "gone"));
+
+        // An I/O error takes on the path it concerns.
+        {
+            let err = io().at_path(Path::new("/pier")).unwrap_err();
+            assert_eq!("/pier: gone", err.to_string());
+            assert!(err.source().is_some());
+            assert!(matches!(err.root(), Error::Io { .. }));
+        }
+
+        // Other errors are wrapped, with the innermost error at the root.
+        {
+            let err = Err::<(), _>(Error::BadPill("no kernel".to_string()))
+                .at_event(3)
+                .at_path(Path::new("/pier"))
+                .unwrap_err();
+            assert_eq!("/pier: event 3: bad pill: no kernel", err.to_string());
+            assert!(matches!(err.root(), Error::BadPill(_)));
+            assert_eq!(
+                "event 3: bad pill: no kernel",
+                err.source().unwrap().to_string()
+            );
+        }
     }
 }
@@ -0,0 +1,415 @@
+use crate::{
+    error::{Context, Error},
+    event_log::{EvtLog, Log, FAKE, WHO},
+    snapshot::Snapshot,
+};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Version of the epoch layout, which the C runtime records in each epoch's `epoc.txt`.
+const EPOCH_VERSION: u32 = 1;
+
+/// Version of this runtime, which is recorded in each epoch it creates.
+pub const VERE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A segment of a pier's event log that starts at a snapshot boundary.
+///
+/// Each epoch is a directory named `0i<N>` under `.urb/log`, where `N` is the number of the
+/// event before the epoch's first event. As in the C runtime, it holds the epoch's event log,
+/// the layout version in `epoc.txt` and the version of the runtime that created it in
+/// `vere.txt`. Every epoch but the first also holds a snapshot of the kernel as of event `N`,
+/// unless the C runtime created it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Epoch {
+    pub dir: PathBuf,
+    /// Number of the epoch's first event.
+    pub first: u64,
+    /// Version of the runtime that created the epoch.
+    pub vere: String,
+    /// Mug of the kernel as of the event before the epoch's first event, or `None` if the epoch
+    /// has no snapshot: the first epoch, which starts with the lifecycle events, or one the C
+    /// runtime created.
+    pub kernel: Option<u32>,
+}
+
+impl Epoch {
+    /// Get the directory of the epoch of a pier starting after event `evt_num`.
+    fn dir(pier: &Path, evt_num: u64) -> PathBuf {
+        Log::path(pier).join(format!("0i{}", evt_num))
+    }
+
+    /// Get the path of the snapshot the epoch starts from.
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.dir.join("snapshot.jam")
+    }
+
+    /// Open the epoch's event log.
+    pub fn log(&self) -> Result<Log, Error> {
+        Log::new(&self.dir)
+    }
+
+    /// Load the snapshot the epoch starts from, which the first epoch doesn't have.
+    pub fn snapshot(&self) -> Result<Option<Snapshot>, Error> {
+        match self.kernel {
+            Some(_) => Snapshot::read(&self.snapshot_path()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Load an epoch's metadata.
+    fn load(dir: &Path, evt_num: u64) -> Result<Self, Error> {
+        let bad = |msg: String| Error::BadEventLog(format!("epoch {}: {}", dir.display(), msg));
+        let read = |name: &str| {
+            let path = dir.join(name);
+            fs::read_to_string(&path)
+                .at_path(&path)
+                .map(|text| text.trim().to_string())
+        };
+        let version = read("epoc.txt")?;
+        if version != EPOCH_VERSION.to_string() {
+            return Err(bad(format!("unknown version {}", version)));
+        }
+        let epoch = Self {
+            dir: dir.to_path_buf(),
+            first: evt_num + 1,
+            vere: read("vere.txt")?,
+            kernel: None,
+        };
+        let kernel = match Snapshot::read_header(&epoch.snapshot_path())? {
+            Some((snap, mug)) if snap == evt_num => Some(mug),
+            Some((snap, _)) => return Err(bad(format!("snapshot of event {}", snap))),
+            None => None,
+        };
+        Ok(Self { kernel, ..epoch })
+    }
+
+    /// Save an epoch's metadata.
+    fn save(&self) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)?;
+        for (name, text) in [
+            ("epoc.txt", EPOCH_VERSION.to_string()),
+            ("vere.txt", self.vere.clone()),
+        ] {
+            let tmp = self.dir.join(format!("{}.tmp", name));
+            fs::write(&tmp, text)?;
+            fs::rename(&tmp, self.dir.join(name))?;
+        }
+        Ok(())
+    }
+
+    /// List a pier's epochs in order.
+    pub fn list(pier: &Path) -> Result<Vec<Self>, Error> {
+        let entries = match fs::read_dir(Log::path(pier)) {
+            Ok(entries) => entries,
+            Err(err) if ErrorKind::NotFound == err.kind() => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut epochs = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let evt_num = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("0i"))
+                .and_then(|num| num.parse::<u64>().ok());
+            if let Some(evt_num) = evt_num.filter(|_| entry.path().join("epoc.txt").exists()) {
+                epochs.push(Self::load(&entry.path(), evt_num)?);
+            }
+        }
+        epochs.sort_by_key(|epoch| epoch.first);
+        Ok(epochs)
+    }
+
+    /// Get a pier's most recent epoch, creating its first epoch if it has none. A pier that
+    /// predates epochs has its log moved into its first epoch.
+    pub fn current(pier: &Path) -> Result<Self, Error> {
+        if let Some(epoch) = Self::list(pier)?.pop() {
+            return Ok(epoch);
+        }
+        if Log::is_flat(pier) {
+            Self::migrate(pier)
+        } else {
+            Self::create(pier, None)
+        }
+    }
+
+    /// Move the log of a pier that predates epochs from `.urb/log` into the pier's first
+    /// epoch.
+    fn migrate(pier: &Path) -> Result<Self, Error> {
+        let path = Log::path(pier);
+        let epoch = Self {
+            dir: Self::dir(pier, 0),
+            first: 1,
+            vere: VERE_VERSION.to_string(),
+            kernel: None,
+        };
+        fs::create_dir_all(&epoch.dir)?;
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            if entry.path() != epoch.dir {
+                fs::rename(entry.path(), epoch.dir.join(entry.file_name()))?;
+            }
+        }
+        epoch.save()?;
+        Ok(epoch)
+    }
+
+    /// Create an epoch of a pier that starts from a snapshot, or the pier's first epoch if
+    /// there's no snapshot.
+    pub(crate) fn create(pier: &Path, snap: Option<&Snapshot>) -> Result<Self, Error> {
+        let evt_num = snap.map_or(0, |snap| snap.evt_num);
+        let epoch = Self {
+            dir: Self::dir(pier, evt_num),
+            first: evt_num + 1,
+            vere: VERE_VERSION.to_string(),
+            kernel: snap.map(|snap| snap.kernel.mug()),
+        };
+        fs::create_dir_all(&epoch.dir)?;
+        if let Some(snap) = snap {
+            snap.write(&epoch.snapshot_path())?;
+            epoch.log()?.set_first(epoch.first)?;
+        }
+        epoch.save()?;
+        Ok(epoch)
+    }
+
+    /// Start a new epoch of a pier from a snapshot of the last event of the current epoch,
+    /// carrying over the pier's identity.
+    pub fn rollover(pier: &Path, snap: &Snapshot) -> Result<Self, Error> {
+        let current = Self::current(pier)?;
+        let mut log = current.log()?;
+        log.commit()?;
+        if snap.evt_num != log.last() || snap.evt_num + 1 == current.first {
+            return Err(Error::BadEventLog(format!(
+                "can't start an epoch after event {} when the current epoch has events {}..={}",
+                snap.evt_num,
+                current.first,
+                log.last()
+            )));
+        }
+        let epoch = Self::create(pier, Some(snap))?;
+        let mut next = epoch.log()?;
+        for key in [WHO, FAKE] {
+            if let Some(val) = log.meta(key)? {
+                next.set_meta(key, &val)?;
+            }
+        }
+        Ok(epoch)
+    }
+
+    /// Start a new epoch of a pier if the current epoch was created by a different runtime
+    /// version, producing the new epoch if there is one. A current epoch that has no events yet
+    /// can't be rolled over, and is claimed for this version instead.
+    pub fn upgrade(pier: &Path, snap: &Snapshot) -> Result<Option<Self>, Error> {
+        let mut current = Self::current(pier)?;
+        if VERE_VERSION == current.vere {
+            Ok(None)
+        } else if current.log()?.last() < current.first {
+            current.vere = VERE_VERSION.to_string();
+            current.save()?;
+            Ok(None)
+        } else {
+            Self::rollover(pier, snap).map(Some)
+        }
+    }
+
+    /// Delete a pier's epochs that precede its latest epoch with a snapshot, which are no
+    /// longer needed to replay the pier. Produces the deleted epochs.
+    pub fn prune(pier: &Path) -> Result<Vec<Self>, Error> {
+        let mut epochs = Self::list(pier)?;
+        let keep = match epochs.iter().rposition(|epoch| epoch.kernel.is_some()) {
+            Some(keep) => keep,
+            None => return Ok(Vec::new()),
+        };
+        Snapshot::read_header(&epochs[keep].snapshot_path())?.ok_or_else(|| {
+            Error::BadSnapshot(format!("epoch {} has no snapshot", epochs[keep].first))
+        })?;
+        epochs.truncate(keep);
+        for epoch in &epochs {
+            fs::remove_dir_all(&epoch.dir)?;
+        }
+        Ok(epochs)
+    }
+
+    /// Remove a pier's events up to and including the event of `snap`, deleting the epochs
+    /// before it. The epoch holding the event becomes an epoch that starts from `snap`.
+    /// Produces the deleted epochs.
+    pub fn chop(pier: &Path, snap: &Snapshot) -> Result<Vec<Self>, Error> {
+        let mut epochs = Self::list(pier)?;
+        let idx = match epochs
+            .iter()
+            .rposition(|epoch| epoch.first <= snap.evt_num + 1)
+        {
+            Some(idx) => idx,
+            None => return Ok(Vec::new()),
+        };
+        let epoch = epochs[idx].clone();
+        if epoch.first <= snap.evt_num {
+            epoch.log()?.chop(snap.evt_num)?;
+            snap.write(&epoch.snapshot_path())?;
+            let dir = Self::dir(pier, snap.evt_num);
+            fs::rename(&epoch.dir, &dir)?;
+        }
+        epochs.truncate(idx);
+        for epoch in &epochs {
+            fs::remove_dir_all(&epoch.dir)?;
+        }
+        Ok(epochs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event_log::{file::FileLog, Event},
+        kernel::Kernel,
+        pier,
+        test_util::{poke, toy_pill},
+        time,
+    };
+    use nock::noun::Noun;
+    use std::{env, process};
+
+    #[test]
+    fn epochs() {
+        let pier = env::temp_dir().join(format!("vere-epoch-{}", process::id()));
+        let path = toy_pill(&pier);
+
+        // The first epoch holds the lifecycle events.
+        let epoch = Epoch::current(&pier).unwrap();
+        assert_eq!((1, None), (epoch.first, epoch.kernel));
+        let (kernel, _) = Kernel::new(&path, &mut epoch.log().unwrap()).unwrap();
+        let kernel = poke(&mut Log::open(&pier).unwrap(), kernel, 3..=4);
+
+        // Roll over to a new epoch, which continues from a snapshot.
+        let snap = Snapshot {
+            evt_num: 4,
+            kernel: kernel.clone(),
+        };
+        {
+            assert!(Epoch::rollover(
+                &pier,
+                &Snapshot {
+                    evt_num: 3,
+                    kernel: kernel.clone()
+                }
+            )
+            .is_err());
+            let epoch = Epoch::rollover(&pier, &snap).unwrap();
+            assert_eq!((5, Some(kernel.mug())), (epoch.first, epoch.kernel));
+            assert!(epoch.dir.ends_with("0i4"));
+            assert_eq!(4, epoch.log().unwrap().last());
+            assert!(Epoch::rollover(&pier, &snap).is_err());
+            assert_eq!(None, Epoch::upgrade(&pier, &snap).unwrap());
+        }
+        let kernel = poke(&mut Log::open(&pier).unwrap(), kernel, 5..=6);
+
+        // Replay across both epochs from the lifecycle events.
+        {
+            let epochs = Epoch::list(&pier).unwrap();
+            assert_eq!(
+                vec![1, 5],
+                epochs.iter().map(|e| e.first).collect::<Vec<_>>()
+            );
+            let snap = pier::replay(&pier, None, &mut |_| {}).unwrap();
+            assert_eq!(6, snap.evt_num);
+            assert_eq!(Noun::from(kernel.clone()), Noun::from(snap.kernel));
+        }
+
+        // A runtime upgrade starts a new epoch.
+        {
+            let mut epoch = Epoch::current(&pier).unwrap();
+            epoch.vere = "0.0.0".to_string();
+            epoch.save().unwrap();
+            let snap = Snapshot {
+                evt_num: 6,
+                kernel: kernel.clone(),
+            };
+            let mut epoch = Epoch::upgrade(&pier, &snap).unwrap().unwrap();
+            assert_eq!((7, VERE_VERSION), (epoch.first, epoch.vere.as_str()));
+
+            // An epoch without events is claimed rather than rolled over.
+            epoch.vere = "0.0.0".to_string();
+            epoch.save().unwrap();
+            assert_eq!(None, Epoch::upgrade(&pier, &snap).unwrap());
+            assert_eq!(epoch.dir, Epoch::current(&pier).unwrap().dir);
+            assert_eq!(VERE_VERSION, Epoch::current(&pier).unwrap().vere);
+        }
+
+        // Prune the epochs before the latest snapshot, after which replay starts from it.
+        {
+            let pruned = Epoch::prune(&pier).unwrap();
+            assert_eq!(
+                vec![1, 5],
+                pruned.iter().map(|e| e.first).collect::<Vec<_>>()
+            );
+            assert_eq!(1, Epoch::list(&pier).unwrap().len());
+            let snap = pier::replay(&pier, None, &mut |_| {}).unwrap();
+            assert_eq!(6, snap.evt_num);
+            assert_eq!(Noun::from(kernel), Noun::from(snap.kernel));
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn c_epochs() {
+        let pier = env::temp_dir().join(format!("vere-epoch-c-{}", process::id()));
+        for (dir, vere) in [("0i0", "3.0"), ("0i5", "3.1")] {
+            let dir = Log::path(&pier).join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("epoc.txt"), "1").unwrap();
+            fs::write(dir.join("vere.txt"), vere).unwrap();
+        }
+        let epochs = Epoch::list(&pier).unwrap();
+        assert_eq!(
+            vec![(1, "3.0", None), (6, "3.1", None)],
+            epochs
+                .iter()
+                .map(|e| (e.first, e.vere.as_str(), e.kernel))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(6, Epoch::current(&pier).unwrap().first);
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn migrate() {
+        let pier = env::temp_dir().join(format!("vere-epoch-migrate-{}", process::id()));
+        let kernel = Kernel::try_from(Noun::from((Noun::from(0), Noun::from(1)))).unwrap();
+        {
+            let mut log = FileLog::new(&Log::path(&pier)).unwrap();
+            for num in 1..=2 {
+                log.append(Event {
+                    num,
+                    mug: 0,
+                    date: time::now(),
+                    ovum: Noun::from(num),
+                })
+                .unwrap();
+            }
+            log.commit().unwrap();
+        }
+        assert!(Log::is_flat(&pier));
+
+        // A pier that predates epochs has its log moved into its first epoch on rollover.
+        let snap = Snapshot { evt_num: 2, kernel };
+        let epoch = Epoch::rollover(&pier, &snap).unwrap();
+        assert_eq!(3, epoch.first);
+        assert!(!Log::is_flat(&pier));
+        let epochs = Epoch::list(&pier).unwrap();
+        assert_eq!(
+            vec![1, 3],
+            epochs.iter().map(|e| e.first).collect::<Vec<_>>()
+        );
+        assert_eq!((1, 2), {
+            let log = epochs[0].log().unwrap();
+            (log.first(), log.last())
+        });
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    error::Error,
+    event_log::{Event, EvtLog},
+};
+use nock::{
+    atom::Atom,
+    noun::Noun,
+    serdes::{Cue, Jam},
+};
+use std::io::{ErrorKind, Read, Write};
+
+/// Bytes that open every export.
+const MAGIC: &[u8; 8] = b"urbevts\0";
+
+/// Version of the export format.
+const VERSION: u64 = 1;
+
+/// Number of events read from the log at a time.
+const BATCH: usize = 1000;
+
+/// Description of the events in an export, which is its first frame, jammed as
+/// `[%export version who fake first last kernel vere]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    /// Ship the events belong to.
+    pub who: Atom,
+    /// Whether the ship is a fake ship, which never touches the live network.
+    pub fake: bool,
+    /// Number of the first exported event.
+    pub first: u64,
+    /// Number of the last exported event.
+    pub last: u64,
+    /// Mug of the kernel as of the event before the first exported event, or 0 if the export
+    /// starts with the lifecycle events.
+    pub kernel: u32,
+    /// Version of the runtime that made the export.
+    pub vere: String,
+}
+
+/// Header from Noun.
+impl TryFrom<Noun> for Header {
+    type Error = Error;
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let bad = |msg: &str| Error::BadEventLog(format!("export header: {}", msg));
+        let mut fields = noun
+            .into_tuple(8)
+            .map_err(|_| bad("expected [%export version who fake first last kernel vere]"))?
+            .into_iter();
+        let mut next = || match fields.next().unwrap() {
+            Noun::Atom(atom) => Ok(atom),
+            Noun::Cell(_) => Err(bad("unexpected cell")),
+        };
+        let num = |atom: Atom| match atom {
+            Atom::Direct(num) => Ok(num),
+            Atom::Indirect(_) => Err(bad("number is too big")),
+        };
+        if Atom::from("export") != next()? {
+            return Err(bad("missing %export tag"));
+        }
+        let version = num(next()?)?;
+        if VERSION != version {
+            return Err(bad(&format!("unknown version {}", version)));
+        }
+        let who = next()?;
+        let fake = match num(next()?)? {
+            0 => true,
+            1 => false,
+            _ => return Err(bad("fake isn't a loobean")),
+        };
+        let (first, last) = (num(next()?)?, num(next()?)?);
+        if 0 == first || last + 1 < first {
+            return Err(bad(&format!("bad range {}..={}", first, last)));
+        }
+        let kernel = u32::try_from(num(next()?)?).map_err(|_| bad("kernel mug is too big"))?;
+        let vere =
+            String::from_utf8(next()?.to_bytes()).map_err(|_| bad("runtime version isn't text"))?;
+        Ok(Self {
+            who,
+            fake,
+            first,
+            last,
+            kernel,
+            vere,
+        })
+    }
+}
+
+/// Noun from Header.
+impl From<Header> for Noun {
+    fn from(header: Header) -> Self {
+        Noun::from_tuple(vec![
+            Noun::from(Atom::from("export")),
+            Noun::from(VERSION),
+            Noun::from(header.who),
+            Noun::from(u64::from(!header.fake)),
+            Noun::from(header.first),
+            Noun::from(header.last),
+            Noun::from(u64::from(header.kernel)),
+            Noun::from(Atom::from(header.vere.as_str())),
+        ])
+    }
+}
+
+/// Writes an export: [`MAGIC`] followed by frames of a little-endian 64-bit length and a jam,
+/// the first of which is the [`Header`] and the rest of which are the events it describes,
+/// each jammed as `[num mug date ovum]`.
+pub struct Writer<W: Write> {
+    out: W,
+    header: Header,
+    /// Number of the next event to write.
+    next: u64,
+}
+
+impl<W: Write> Writer<W> {
+    /// Start an export by writing its header.
+    pub fn new(mut out: W, header: Header) -> Result<Self, Error> {
+        out.write_all(MAGIC)?;
+        write_frame(&mut out, &Noun::from(header.clone()))?;
+        Ok(Self {
+            out,
+            next: header.first,
+            header,
+        })
+    }
+
+    /// Write the next event.
+    pub fn write(&mut self, evt: &Event) -> Result<(), Error> {
+        if evt.num != self.next || evt.num > self.header.last {
+            return Err(Error::BadEventLog(format!(
+                "can't export event {} as event {} of {}..={}",
+                evt.num, self.next, self.header.first, self.header.last
+            )));
+        }
+        write_frame(
+            &mut self.out,
+            &Noun::from((Noun::from(evt.num), evt.to_record())),
+        )?;
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Write the events of `log` that come next, stopping at the end of the log or of the
+    /// header's range.
+    pub fn write_log<L: EvtLog<Evt = Event>>(&mut self, log: &L) -> Result<(), Error> {
+        let last = self.header.last.min(log.last());
+        while self.next <= last {
+            let count = usize::try_from(last - self.next + 1).map_or(BATCH, |left| left.min(BATCH));
+            let evts = log.read(self.next, count)?;
+            if evts.is_empty() {
+                break;
+            }
+            for evt in &evts {
+                self.write(evt)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish the export once every event in its header's range has been written.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.next != self.header.last + 1 {
+            return Err(Error::BadEventLog(format!(
+                "export of events {}..={} stopped before event {}",
+                self.header.first, self.header.last, self.next
+            )));
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads an export written by [`Writer`], producing its events in order.
+pub struct Reader<R: Read> {
+    inp: R,
+    header: Header,
+    /// Number of the next event to read.
+    next: u64,
+}
+
+impl<R: Read> Reader<R> {
+    /// Start reading an export by reading its header.
+    pub fn new(mut inp: R) -> Result<Self, Error> {
+        let mut magic = [0; MAGIC.len()];
+        inp.read_exact(&mut magic)
+            .map_err(|_| Error::BadEventLog("not an export".to_string()))?;
+        if *MAGIC != magic {
+            return Err(Error::BadEventLog("not an export".to_string()));
+        }
+        let header = match read_frame(&mut inp)? {
+            Some(noun) => Header::try_from(noun)?,
+            None => return Err(Error::BadEventLog("export has no header".to_string())),
+        };
+        Ok(Self {
+            inp,
+            next: header.first,
+            header,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Read the next event and check it against the header's range.
+    fn read(&mut self) -> Result<Option<Event>, Error> {
+        let frame = read_frame(&mut self.inp)?;
+        let bad = |msg: String| Err(Error::BadEventLog(format!("export: {}", msg)));
+        let noun = match frame {
+            Some(_) if self.next > self.header.last => {
+                return bad(format!("event after event {}", self.header.last))
+            }
+            Some(noun) => noun,
+            None if self.next > self.header.last => return Ok(None),
+            None => return bad(format!("ends before event {}", self.next)),
+        };
+        let (num, record) = match noun {
+            Noun::Cell(cell) => (*cell.head, *cell.tail),
+            Noun::Atom(_) => return bad(format!("event {} isn't a cell", self.next)),
+        };
+        if Noun::from(self.next) != num {
+            return bad(format!("expected event {} but found {}", self.next, num));
+        }
+        let evt = Event::from_record(self.next, record)?;
+        self.next += 1;
+        Ok(Some(evt))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// Write a frame of a noun's jam preceded by its length.
+fn write_frame<W: Write>(out: &mut W, noun: &Noun) -> Result<(), Error> {
+    let jam = noun.jam();
+    out.write_all(&(jam.len() as u64).to_le_bytes())?;
+    out.write_all(&jam)?;
+    Ok(())
+}
+
+/// Read a frame written by [`write_frame`], or nothing at the end of the input.
+fn read_frame<R: Read>(inp: &mut R) -> Result<Option<Noun>, Error> {
+    let mut len = [0; 8];
+    match inp.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if ErrorKind::UnexpectedEof == err.kind() => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = usize::try_from(u64::from_le_bytes(len))
+        .map_err(|_| Error::BadEventLog("export frame is too long".to_string()))?;
+    let mut jam = Vec::new();
+    inp.take(len as u64).read_to_end(&mut jam)?;
+    if jam.len() != len {
+        return Err(Error::BadEventLog("export ends mid-frame".to_string()));
+    }
+    Ok(Some(Noun::cue(&jam)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+
+    fn header() -> Header {
+        Header {
+            who: Atom::from(0x100u64),
+            fake: true,
+            first: 3,
+            last: 4,
+            kernel: 0xbeef,
+            vere: "0.1.0".to_string(),
+        }
+    }
+
+    fn evt(num: u64) -> Event {
+        Event {
+            num,
+            mug: num as u32,
+            date: time::now(),
+            ovum: Noun::from((Noun::from(num), Noun::from(num))),
+        }
+    }
+
+    #[test]
+    fn write_read() {
+        let mut writer = Writer::new(Vec::new(), header()).unwrap();
+        assert!(writer.write(&evt(4)).is_err());
+        writer.write(&evt(3)).unwrap();
+        let partial = writer.out.clone();
+        writer.write(&evt(4)).unwrap();
+        assert!(writer.write(&evt(5)).is_err());
+        let bytes = writer.finish().unwrap();
+
+        // Read back the header and events.
+        {
+            let reader = Reader::new(&bytes[..]).unwrap();
+            assert_eq!(&header(), reader.header());
+            let evts: Vec<_> = reader.map(Result::unwrap).collect();
+            assert_eq!(
+                vec![evt(3).ovum, evt(4).ovum],
+                vec![evts[0].ovum.clone(), evts[1].ovum.clone()]
+            );
+            assert_eq!((3, 4), (evts[0].num, evts[1].mug));
+        }
+
+        // An export that stops early.
+        {
+            let mut reader = Reader::new(&partial[..]).unwrap();
+            assert!(reader.next().unwrap().is_ok());
+            assert!(matches!(reader.next(), Some(Err(Error::BadEventLog(_)))));
+            let mut cut = bytes.clone();
+            cut.pop();
+            assert!(Reader::new(&cut[..]).unwrap().any(|evt| evt.is_err()));
+        }
+
+        // Something that isn't an export.
+        {
+            assert!(matches!(
+                Reader::new(&bytes[1..]),
+                Err(Error::BadEventLog(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,533 @@
+use crate::{
+    error::Error,
+    event_log::{decode_first, Ack, Event, EvtLog, FIRST},
+};
+use nock::{
+    atom::Atom,
+    hash::Mug,
+    noun::Noun,
+    serdes::{Cue, Jam},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+/// Length of the header preceding each jammed record: the event number, the length of the jam
+/// in bytes and the mug of the jam.
+const HEADER_LEN: usize = 20;
+
+/// Default number of events appended between fsyncs.
+const BATCH: usize = 32;
+
+/// An event log stored as a single append-only file of records.
+///
+/// Appends are buffered and written with a single fsync once a batch fills up or the log is
+/// committed.
+pub struct FileLog {
+    path: PathBuf,
+    file: File,
+    /// Number of the event before the first event.
+    base: u64,
+    /// Byte offset of each event's record, indexed by event number - base - 1, including events
+    /// that have yet to be written.
+    offsets: Vec<u64>,
+    /// Number of the most recent durable event.
+    durable: u64,
+    /// Byte offset of the end of the last durable record.
+    len: u64,
+    /// Records that have yet to be written.
+    pending: Vec<u8>,
+    /// Number of events appended between fsyncs.
+    batch: usize,
+}
+
+/// A record's header fields.
+struct Header {
+    num: u64,
+    /// Length of the jam in bytes.
+    len: u64,
+    check: u32,
+}
+
+/// A record's header fields and jam.
+struct Record {
+    num: u64,
+    check: u32,
+    jam: Vec<u8>,
+}
+
+impl FileLog {
+    /// Get the path of the file holding the records of the log at `path`.
+    fn events_path(path: &Path) -> PathBuf {
+        path.join("events")
+    }
+
+    /// Get the path of the file holding metadata value `key` of the log at `path`.
+    fn meta_path(path: &Path, key: &str) -> PathBuf {
+        path.join("meta").join(key)
+    }
+
+    /// Set the number of events appended between fsyncs, which is at least 1.
+    pub fn set_batch(&mut self, batch: usize) {
+        self.batch = batch.max(1);
+    }
+
+    /// Parse the header at the start of `bytes`.
+    fn header(bytes: &[u8]) -> Option<Header> {
+        let field = |range: std::ops::Range<usize>| bytes.get(range);
+        Some(Header {
+            num: u64::from_le_bytes(field(0..8)?.try_into().ok()?),
+            len: u64::from_le_bytes(field(8..16)?.try_into().ok()?),
+            check: u32::from_le_bytes(field(16..HEADER_LEN)?.try_into().ok()?),
+        })
+    }
+
+    /// Read the record at byte `pos` of `file`, if it ends by byte `end`.
+    fn record(file: &File, pos: u64, end: u64) -> Result<Option<Record>, Error> {
+        let mut header = [0; HEADER_LEN];
+        if end.saturating_sub(pos) < HEADER_LEN as u64 {
+            return Ok(None);
+        }
+        file.read_exact_at(&mut header, pos)?;
+        let header = match Self::header(&header) {
+            Some(header) if end - pos - (HEADER_LEN as u64) >= header.len => header,
+            _ => return Ok(None),
+        };
+        let mut jam = vec![0; header.len as usize];
+        file.read_exact_at(&mut jam, pos + HEADER_LEN as u64)?;
+        Ok(Some(Record {
+            num: header.num,
+            check: header.check,
+            jam,
+        }))
+    }
+
+    /// Get the checksum of a record's jam.
+    fn check(jam: &[u8]) -> u32 {
+        Atom::from_bytes(jam).mug()
+    }
+}
+
+impl EvtLog for FileLog {
+    type Evt = Event;
+
+    /// Open the event log at `path`, rejecting it if any record is corrupt, out of order or
+    /// duplicated. A final record that's incomplete was torn by a crash mid-commit and so never
+    /// made durable, and is cut off.
+    fn new(path: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(path)?;
+        let events = Self::events_path(path);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&events)?;
+        let base = match fs::read(Self::meta_path(path, FIRST)) {
+            Ok(first) => decode_first(&first)? - 1,
+            Err(err) if ErrorKind::NotFound == err.kind() => 0,
+            Err(err) => return Err(err.into()),
+        };
+        let end = file.metadata()?.len();
+        let mut offsets = Vec::new();
+        let mut pos = 0;
+        while pos < end {
+            let expected = base + offsets.len() as u64 + 1;
+            let rec = match Self::record(&file, pos, end)? {
+                Some(rec) => rec,
+                None => {
+                    file.set_len(pos)?;
+                    file.sync_all()?;
+                    break;
+                }
+            };
+            if offsets.is_empty() && 0 != rec.num && rec.num <= base {
+                // Left behind by a chop that was interrupted before it rewrote the file.
+                pos += (HEADER_LEN + rec.jam.len()) as u64;
+                continue;
+            } else if rec.num < expected {
+                return Err(Error::BadEventLog(format!(
+                    "duplicate event {} at byte {}",
+                    rec.num, pos
+                )));
+            } else if rec.num > expected {
+                return Err(Error::BadEventLog(format!(
+                    "missing events {}..{} before byte {}",
+                    expected, rec.num, pos
+                )));
+            } else if rec.check != Self::check(&rec.jam) {
+                return Err(Error::BadEventLog(format!(
+                    "corrupt event {} at byte {}",
+                    rec.num, pos
+                )));
+            }
+            offsets.push(pos);
+            pos += (HEADER_LEN + rec.jam.len()) as u64;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            base,
+            durable: base + offsets.len() as u64,
+            offsets,
+            len: pos,
+            pending: Vec::new(),
+            batch: BATCH,
+        })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn first(&self) -> u64 {
+        if self.offsets.is_empty() {
+            0
+        } else {
+            self.base + 1
+        }
+    }
+
+    fn last(&self) -> u64 {
+        self.base + self.offsets.len() as u64
+    }
+
+    fn set_first(&mut self, first: u64) -> Result<(), Error> {
+        if !self.offsets.is_empty() || 0 == first {
+            return Err(Error::BadEventLog(format!(
+                "can't start a log with events {}..={} at event {}",
+                self.first(),
+                self.last(),
+                first
+            )));
+        }
+        self.set_meta(FIRST, &first.to_le_bytes())?;
+        self.base = first - 1;
+        self.durable = self.base;
+        Ok(())
+    }
+
+    fn append(&mut self, evt: Self::Evt) -> Result<Ack, Error> {
+        if evt.num != self.last() + 1 {
+            return Err(Error::BadEventLog(format!(
+                "can't append event {} after event {}",
+                evt.num,
+                self.last()
+            )));
+        }
+        let jam = evt.to_record().jam();
+        self.offsets.push(self.len + self.pending.len() as u64);
+        self.pending.extend_from_slice(&evt.num.to_le_bytes());
+        self.pending
+            .extend_from_slice(&(jam.len() as u64).to_le_bytes());
+        self.pending
+            .extend_from_slice(&Self::check(&jam).to_le_bytes());
+        self.pending.extend_from_slice(&jam);
+        if self.last() - self.durable >= self.batch as u64 {
+            self.commit()
+        } else {
+            Ok(Ack {
+                evt_num: self.durable,
+            })
+        }
+    }
+
+    fn commit(&mut self) -> Result<Ack, Error> {
+        if !self.pending.is_empty() {
+            if let Err(err) = self.file.write_all(&self.pending) {
+                // Drop whatever was written so that a retry appends the records only once.
+                let _ = self.file.set_len(self.len);
+                return Err(err.into());
+            }
+            self.file.sync_data()?;
+            self.len += self.pending.len() as u64;
+            self.pending.clear();
+            self.durable = self.last();
+        }
+        Ok(Ack {
+            evt_num: self.durable,
+        })
+    }
+
+    fn read(&self, start: u64, count: usize) -> Result<Vec<Self::Evt>, Error> {
+        let start = start.max(self.base + 1);
+        if start > self.durable {
+            return Ok(Vec::new());
+        }
+        let mut pos = self.offsets[(start - self.base - 1) as usize];
+        let mut evts = Vec::new();
+        while evts.len() < count && pos < self.len {
+            let rec = Self::record(&self.file, pos, self.len)?
+                .ok_or_else(|| Error::BadEventLog(format!("truncated record at byte {}", pos)))?;
+            if rec.check != Self::check(&rec.jam) {
+                return Err(Error::BadEventLog(format!(
+                    "corrupt event {} at byte {}",
+                    rec.num, pos
+                )));
+            }
+            evts.push(Event::from_record(rec.num, Noun::cue(&rec.jam)?)?);
+            pos += (HEADER_LEN + rec.jam.len()) as u64;
+        }
+        Ok(evts)
+    }
+
+    fn truncate(&mut self, evt_num: u64) -> Result<(), Error> {
+        if evt_num > self.last() || evt_num < self.base {
+            return Err(Error::BadEventLog(format!(
+                "can't truncate to event {} of {}..={}",
+                evt_num,
+                self.first(),
+                self.last()
+            )));
+        }
+        self.commit()?;
+        if evt_num < self.last() {
+            let idx = (evt_num - self.base) as usize;
+            let len = self.offsets[idx];
+            self.file.set_len(len)?;
+            self.file.sync_all()?;
+            self.offsets.truncate(idx);
+            self.len = len;
+            self.durable = evt_num;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the file without the chopped records, recording the new first event before
+    /// replacing the file so that an interrupted chop leaves only records to skip.
+    fn chop(&mut self, evt_num: u64) -> Result<(), Error> {
+        if evt_num > self.last() || evt_num <= self.base {
+            return Err(Error::BadEventLog(format!(
+                "can't chop to event {} of {}..={}",
+                evt_num,
+                self.first(),
+                self.last()
+            )));
+        }
+        self.commit()?;
+        let idx = (evt_num - self.base) as usize;
+        let cut = self.offsets.get(idx).copied().unwrap_or(self.len);
+        let events = Self::events_path(&self.path);
+        let tmp = events.with_extension("tmp");
+        let mut kept = File::open(&events)?;
+        kept.seek(SeekFrom::Start(cut))?;
+        let mut file = File::create(&tmp)?;
+        io::copy(&mut kept.take(self.len - cut), &mut file)?;
+        file.sync_all()?;
+        self.set_meta(FIRST, &(evt_num + 1).to_le_bytes())?;
+        fs::rename(&tmp, &events)?;
+        self.file = OpenOptions::new().read(true).append(true).open(&events)?;
+        self.offsets = self.offsets[idx..].iter().map(|off| off - cut).collect();
+        self.len -= cut;
+        self.base = evt_num;
+        Ok(())
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(Self::meta_path(&self.path, key)) {
+            Ok(val) => Ok(Some(val)),
+            Err(err) if ErrorKind::NotFound == err.kind() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn set_meta(&mut self, key: &str, val: &[u8]) -> Result<(), Error> {
+        let path = Self::meta_path(&self.path, key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(val)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+/// Write out any events that have yet to be committed.
+impl Drop for FileLog {
+    fn drop(&mut self) {
+        let _ = self.commit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+    use std::{env, process};
+
+    fn evt(num: u64) -> Event {
+        Event {
+            num,
+            mug: num as u32,
+            date: time::now(),
+            ovum: Noun::from((Noun::from(num), Noun::from(num + 1))),
+        }
+    }
+
+    #[test]
+    fn append_read() {
+        let path = env::temp_dir().join(format!("vere-file-log-{}", process::id()));
+
+        // Events become durable once a batch fills up or the log is committed.
+        {
+            let mut log = FileLog::new(&path).unwrap();
+            log.set_batch(2);
+            assert_eq!(0, log.last());
+            assert_eq!(Ack { evt_num: 0 }, log.append(evt(1)).unwrap());
+            assert!(log.read(1, 1).unwrap().is_empty());
+            assert_eq!(Ack { evt_num: 2 }, log.append(evt(2)).unwrap());
+            assert_eq!(Ack { evt_num: 2 }, log.append(evt(3)).unwrap());
+            assert_eq!(Ack { evt_num: 3 }, log.commit().unwrap());
+        }
+
+        // Read back what was appended after reopening the log.
+        {
+            let log = FileLog::new(&path).unwrap();
+            assert_eq!(3, log.last());
+            let evts = log.read(2, 5).unwrap();
+            assert_eq!(
+                vec![evt(2).ovum, evt(3).ovum],
+                vec![evts[0].ovum.clone(), evts[1].ovum.clone()]
+            );
+            assert_eq!(3, evts[1].mug);
+            assert!(time::from_date(&evts[0].date).is_some());
+            assert!(log.read(4, 1).unwrap().is_empty());
+        }
+
+        // Reject events that don't immediately follow the most recent event.
+        {
+            let mut log = FileLog::new(&path).unwrap();
+            assert!(matches!(log.append(evt(3)), Err(Error::BadEventLog(_))));
+            assert!(matches!(log.append(evt(5)), Err(Error::BadEventLog(_))));
+        }
+
+        // Truncate, after which appends resume from the truncation point.
+        {
+            let mut log = FileLog::new(&path).unwrap();
+            log.append(evt(4)).unwrap();
+            assert!(matches!(log.truncate(5), Err(Error::BadEventLog(_))));
+            log.truncate(2).unwrap();
+            assert_eq!((1, 2), (log.first(), log.last()));
+            assert!(log.read(3, 1).unwrap().is_empty());
+            log.append(evt(3)).unwrap();
+            log.commit().unwrap();
+            assert_eq!(3, FileLog::new(&path).unwrap().last());
+        }
+
+        // Chop, after which the log starts after the chop point.
+        {
+            let mut log = FileLog::new(&path).unwrap();
+            assert!(matches!(log.chop(4), Err(Error::BadEventLog(_))));
+            log.chop(1).unwrap();
+            assert!(matches!(log.chop(1), Err(Error::BadEventLog(_))));
+            assert_eq!((2, 3), (log.first(), log.last()));
+            assert_eq!(evt(2).ovum, log.read(1, 1).unwrap()[0].ovum);
+            log.append(evt(4)).unwrap();
+            log.commit().unwrap();
+            let log = FileLog::new(&path).unwrap();
+            assert_eq!((2, 4), (log.first(), log.last()));
+        }
+
+        // Skip the records left behind by an interrupted chop.
+        {
+            let mut log = FileLog::new(&path).unwrap();
+            log.set_meta(FIRST, &3u64.to_le_bytes()).unwrap();
+            let log = FileLog::new(&path).unwrap();
+            assert_eq!((3, 4), (log.first(), log.last()));
+            assert_eq!(evt(3).ovum, log.read(1, 1).unwrap()[0].ovum);
+        }
+
+        // A log can start after event 1, but only while it's empty.
+        {
+            let dir = path.join("later");
+            let mut log = FileLog::new(&dir).unwrap();
+            log.set_first(4).unwrap();
+            assert_eq!(3, log.last());
+            log.append(evt(4)).unwrap();
+            log.commit().unwrap();
+            assert!(log.set_first(2).is_err());
+            let log = FileLog::new(&dir).unwrap();
+            assert_eq!((4, 4), (log.first(), log.last()));
+            assert_eq!(evt(4).ovum, log.read(1, 1).unwrap()[0].ovum);
+        }
+
+        // Metadata survives reopening the log.
+        {
+            let mut log = FileLog::new(&path).unwrap();
+            assert_eq!(None, log.meta("who").unwrap());
+            log.set_meta("who", &[1, 2]).unwrap();
+            assert_eq!(
+                Some(vec![1, 2]),
+                FileLog::new(&path).unwrap().meta("who").unwrap()
+            );
+        }
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn reject() {
+        let path = env::temp_dir().join(format!("vere-file-log-reject-{}", process::id()));
+        let events = FileLog::events_path(&path);
+        let mut log = FileLog::new(&path).unwrap();
+        for num in 1..=3 {
+            log.append(evt(num)).unwrap();
+        }
+        drop(log);
+        let bytes = fs::read(&events).unwrap();
+        let len = |bytes: &[u8]| FileLog::header(bytes).unwrap().len as usize + HEADER_LEN;
+        let second = len(&bytes);
+        let reopen = |bytes: &[u8]| {
+            fs::write(&events, bytes).unwrap();
+            FileLog::new(&path)
+        };
+
+        // A torn trailing record is cut off, and appends resume in its place.
+        {
+            for (torn, last) in [(bytes.len() - 1, 2), (second + HEADER_LEN - 1, 1)] {
+                let mut log = reopen(&bytes[..torn]).unwrap();
+                assert_eq!(last, log.last());
+                log.append(evt(last + 1)).unwrap();
+                log.commit().unwrap();
+                let log = FileLog::new(&path).unwrap();
+                assert_eq!(last + 1, log.last());
+                assert_eq!(evt(last + 1).ovum, log.read(last + 1, 1).unwrap()[0].ovum);
+            }
+        }
+
+        // A corrupt record.
+        {
+            let mut bytes = bytes.clone();
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0x80;
+            assert!(matches!(reopen(&bytes), Err(Error::BadEventLog(_))));
+        }
+
+        // A gap, by dropping the second event.
+        {
+            let third = second + len(&bytes[second..]);
+            let mut gap = bytes[..second].to_vec();
+            gap.extend_from_slice(&bytes[third..]);
+            assert!(matches!(reopen(&gap), Err(Error::BadEventLog(_))));
+        }
+
+        // A duplicate, by repeating the first event.
+        {
+            let mut dup = bytes[..second].to_vec();
+            dup.extend_from_slice(&bytes);
+            assert!(matches!(reopen(&dup), Err(Error::BadEventLog(_))));
+        }
+
+        // The untouched log.
+        {
+            assert_eq!(3, reopen(&bytes).unwrap().last());
+        }
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
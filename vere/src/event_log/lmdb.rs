@@ -0,0 +1,486 @@
+use crate::{
+    error::Error,
+    event_log::{decode_first, Ack, Event, EvtLog, FIRST},
+};
+use lmdb::{Cursor, Database, DatabaseFlags, Environment, Transaction, WriteFlags};
+use nock::{
+    atom::Atom,
+    noun::Noun,
+    serdes::{Cue, Jam},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Maximum size of the memory map, which bounds the size of the database.
+const MAP_SIZE: usize = 1 << 40;
+
+/// Default number of events appended per write transaction.
+const BATCH: usize = 32;
+
+/// Version of the event encoding written to new logs.
+const VERSION: u32 = 1;
+
+/// An event log stored in the LMDB format of the C runtime: an `EVENTS` table of event values
+/// keyed by event number and a `META` table of pier metadata keyed by name.
+///
+/// Logs before version 1 store each event as the jam of `[mug date ovum]`; later logs store the
+/// mug as 4 little-endian bytes followed by the jam of `[date ovum]`. The version is stored as
+/// the bytes of an atom, without trailing zeros.
+pub struct LmdbLog {
+    path: PathBuf,
+    env: Environment,
+    events: Database,
+    meta: Database,
+    version: u32,
+    /// Number of the first event, which may be after 1 if earlier events were discarded.
+    first: u64,
+    /// Number of the most recently appended event.
+    last: u64,
+    /// Number of the most recent durable event.
+    durable: u64,
+    /// Encoded events that have yet to be written.
+    pending: Vec<(u64, Vec<u8>)>,
+    /// Number of events appended per write transaction.
+    batch: usize,
+}
+
+impl LmdbLog {
+    /// Set the number of events appended per write transaction, which is at least 1.
+    pub fn set_batch(&mut self, batch: usize) {
+        self.batch = batch.max(1);
+    }
+
+    /// Get the version of the log's event encoding.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Encode an event as a value of the `EVENTS` table.
+    fn encode(&self, evt: &Event) -> Vec<u8> {
+        if self.version < 1 {
+            return evt.to_record().jam();
+        }
+        let job = Noun::from((Noun::from(evt.date.clone()), evt.ovum.clone()));
+        let mut val = evt.mug.to_le_bytes().to_vec();
+        val.extend_from_slice(&job.jam());
+        val
+    }
+
+    /// Decode a value of the `EVENTS` table as event `num`.
+    fn decode(&self, num: u64, val: &[u8]) -> Result<Event, Error> {
+        if self.version < 1 {
+            return Event::from_record(num, Noun::cue(val)?);
+        }
+        if val.len() < 4 {
+            return Err(Error::BadEventLog(format!("event {} has no mug", num)));
+        }
+        let mut mug = [0; 4];
+        mug.copy_from_slice(&val[..4]);
+        let mug = Noun::from(u64::from(u32::from_le_bytes(mug)));
+        Event::from_record(num, Noun::from((mug, Noun::cue(&val[4..])?)))
+    }
+}
+
+impl EvtLog for LmdbLog {
+    type Evt = Event;
+
+    /// Open the LMDB environment at `path`, rejecting it if its event numbers aren't contiguous.
+    fn new(path: &Path) -> Result<Self, Error> {
+        fs::create_dir_all(path)?;
+        let env = Environment::new()
+            .set_max_dbs(2)
+            .set_map_size(MAP_SIZE)
+            .open(path)?;
+        let events = env.create_db(Some("EVENTS"), DatabaseFlags::INTEGER_KEY)?;
+        let meta = env.create_db(Some("META"), DatabaseFlags::empty())?;
+        let (mut first, mut last) = (0, 0);
+        {
+            let txn = env.begin_ro_txn()?;
+            let mut cursor = txn.open_ro_cursor(events)?;
+            for (key, _) in cursor.iter() {
+                let num = match <[u8; 8]>::try_from(key) {
+                    Ok(key) => u64::from_ne_bytes(key),
+                    Err(_) => {
+                        return Err(Error::BadEventLog(format!(
+                            "{}-byte key after event {}",
+                            key.len(),
+                            last
+                        )))
+                    }
+                };
+                if 0 == first {
+                    first = num;
+                } else if num != last + 1 {
+                    return Err(Error::BadEventLog(format!(
+                        "expected event {} but found event {}",
+                        last + 1,
+                        num
+                    )));
+                }
+                last = num;
+            }
+        }
+        let mut log = Self {
+            path: path.to_path_buf(),
+            env,
+            events,
+            meta,
+            version: VERSION,
+            first,
+            last,
+            durable: last,
+            pending: Vec::new(),
+            batch: BATCH,
+        };
+        match log.meta("version")? {
+            Some(val) => {
+                log.version = match Atom::from_bytes(&val) {
+                    Atom::Direct(version) => u32::try_from(version).ok(),
+                    Atom::Indirect(_) => None,
+                }
+                .ok_or_else(|| Error::BadEventLog("malformed version".to_string()))?;
+            }
+            None if 0 == last => {
+                let version = Atom::Direct(u64::from(VERSION)).to_bytes();
+                log.set_meta("version", &version)?;
+            }
+            None => log.version = 0,
+        }
+        if 0 == log.first {
+            if let Some(first) = log.meta(FIRST)? {
+                log.last = decode_first(&first)? - 1;
+                log.durable = log.last;
+            }
+        }
+        Ok(log)
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn first(&self) -> u64 {
+        self.first
+    }
+
+    fn last(&self) -> u64 {
+        self.last
+    }
+
+    fn set_first(&mut self, first: u64) -> Result<(), Error> {
+        if 0 != self.first || 0 == first {
+            return Err(Error::BadEventLog(format!(
+                "can't start a log with events {}..={} at event {}",
+                self.first, self.last, first
+            )));
+        }
+        self.set_meta(FIRST, &first.to_le_bytes())?;
+        self.last = first - 1;
+        self.durable = self.last;
+        Ok(())
+    }
+
+    fn append(&mut self, evt: Self::Evt) -> Result<Ack, Error> {
+        if evt.num != self.last + 1 {
+            return Err(Error::BadEventLog(format!(
+                "can't append event {} after event {}",
+                evt.num, self.last
+            )));
+        }
+        let val = self.encode(&evt);
+        self.pending.push((evt.num, val));
+        self.last = evt.num;
+        if 0 == self.first {
+            self.first = evt.num;
+        }
+        if self.pending.len() >= self.batch {
+            self.commit()
+        } else {
+            Ok(Ack {
+                evt_num: self.durable,
+            })
+        }
+    }
+
+    fn commit(&mut self) -> Result<Ack, Error> {
+        if !self.pending.is_empty() {
+            let mut txn = self.env.begin_rw_txn()?;
+            for (num, val) in &self.pending {
+                txn.put(
+                    self.events,
+                    &num.to_ne_bytes(),
+                    val,
+                    WriteFlags::NO_OVERWRITE | WriteFlags::APPEND,
+                )?;
+            }
+            txn.commit()?;
+            self.pending.clear();
+            self.durable = self.last;
+        }
+        Ok(Ack {
+            evt_num: self.durable,
+        })
+    }
+
+    fn read(&self, start: u64, count: usize) -> Result<Vec<Self::Evt>, Error> {
+        let txn = self.env.begin_ro_txn()?;
+        let mut evts = Vec::new();
+        let mut num = start.max(self.first);
+        while evts.len() < count && num <= self.durable {
+            let val = txn.get(self.events, &num.to_ne_bytes())?;
+            evts.push(self.decode(num, val)?);
+            num += 1;
+        }
+        Ok(evts)
+    }
+
+    /// Remove the events after `evt_num`, which can't precede the event before the first event,
+    /// even if there are no events yet.
+    fn truncate(&mut self, evt_num: u64) -> Result<(), Error> {
+        let base = match self.first {
+            0 => self.last,
+            first => first - 1,
+        };
+        if evt_num > self.last || evt_num < base {
+            return Err(Error::BadEventLog(format!(
+                "can't truncate to event {} of {}..={}",
+                evt_num, self.first, self.last
+            )));
+        }
+        self.commit()?;
+        let mut txn = self.env.begin_rw_txn()?;
+        for num in evt_num + 1..=self.last {
+            txn.del(self.events, &num.to_ne_bytes(), None)?;
+        }
+        txn.commit()?;
+        self.last = evt_num;
+        self.durable = evt_num;
+        if evt_num < self.first {
+            self.first = 0;
+        }
+        Ok(())
+    }
+
+    fn chop(&mut self, evt_num: u64) -> Result<(), Error> {
+        if 0 == self.first || evt_num < self.first || evt_num > self.last {
+            return Err(Error::BadEventLog(format!(
+                "can't chop to event {} of {}..={}",
+                evt_num, self.first, self.last
+            )));
+        }
+        self.commit()?;
+        let mut txn = self.env.begin_rw_txn()?;
+        for num in self.first..=evt_num {
+            txn.del(self.events, &num.to_ne_bytes(), None)?;
+        }
+        txn.put(
+            self.meta,
+            &FIRST,
+            &(evt_num + 1).to_le_bytes(),
+            WriteFlags::empty(),
+        )?;
+        txn.commit()?;
+        self.first = if evt_num == self.last { 0 } else { evt_num + 1 };
+        Ok(())
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let txn = self.env.begin_ro_txn()?;
+        let val = match txn.get(self.meta, &key) {
+            Ok(val) => Some(val.to_vec()),
+            Err(lmdb::Error::NotFound) => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(val)
+    }
+
+    fn set_meta(&mut self, key: &str, val: &[u8]) -> Result<(), Error> {
+        let mut txn = self.env.begin_rw_txn()?;
+        txn.put(self.meta, &key, &val, WriteFlags::empty())?;
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+/// Write out any events that have yet to be committed.
+impl Drop for LmdbLog {
+    fn drop(&mut self) {
+        let _ = self.commit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time;
+    use std::{env, process};
+
+    fn evt(num: u64) -> Event {
+        Event {
+            num,
+            mug: 0xdead_0000 + num as u32,
+            date: time::now(),
+            ovum: Noun::from((Noun::from(num), Noun::from(num + 1))),
+        }
+    }
+
+    #[test]
+    fn append_read() {
+        let path = env::temp_dir().join(format!("vere-lmdb-log-{}", process::id()));
+
+        // Events become durable once a batch fills up or the log is committed.
+        {
+            let mut log = LmdbLog::new(&path).unwrap();
+            log.set_batch(2);
+            assert_eq!(VERSION, log.version());
+            assert_eq!(Ack { evt_num: 0 }, log.append(evt(1)).unwrap());
+            assert!(log.read(1, 1).unwrap().is_empty());
+            assert_eq!(Ack { evt_num: 2 }, log.append(evt(2)).unwrap());
+            assert_eq!(Ack { evt_num: 2 }, log.append(evt(3)).unwrap());
+            assert!(matches!(log.append(evt(5)), Err(Error::BadEventLog(_))));
+            log.set_meta("who", &[1, 2, 3]).unwrap();
+        }
+
+        // Read back what was appended after reopening the log.
+        {
+            let log = LmdbLog::new(&path).unwrap();
+            assert_eq!((1, 3), (log.first(), log.last()));
+            assert_eq!(
+                vec![evt(2).ovum, evt(3).ovum],
+                vec![
+                    log.read(2, 5).unwrap()[0].ovum.clone(),
+                    log.read(3, 1).unwrap()[0].ovum.clone()
+                ]
+            );
+            assert_eq!(evt(3).mug, log.read(3, 1).unwrap()[0].mug);
+            assert_eq!(Some(vec![1, 2, 3]), log.meta("who").unwrap());
+            assert_eq!(None, log.meta("fake").unwrap());
+        }
+
+        // Truncate, after which appends resume from the truncation point.
+        {
+            let mut log = LmdbLog::new(&path).unwrap();
+            log.truncate(1).unwrap();
+            assert_eq!(1, log.last());
+            assert_eq!(1, LmdbLog::new(&path).unwrap().last());
+            log.append(evt(2)).unwrap();
+            log.append(evt(3)).unwrap();
+        }
+
+        // A log with no events that starts later can't be truncated to before its start.
+        {
+            let later = path.join("later");
+            let mut log = LmdbLog::new(&later).unwrap();
+            log.set_first(5).unwrap();
+            assert!(matches!(log.truncate(3), Err(Error::BadEventLog(_))));
+            log.truncate(4).unwrap();
+            assert_eq!(4, LmdbLog::new(&later).unwrap().last());
+        }
+
+        // Reject a log with a gap.
+        {
+            let log = LmdbLog::new(&path).unwrap();
+            let mut txn = log.env.begin_rw_txn().unwrap();
+            txn.del(log.events, &2u64.to_ne_bytes(), None).unwrap();
+            txn.commit().unwrap();
+            drop(log);
+            assert!(matches!(LmdbLog::new(&path), Err(Error::BadEventLog(_))));
+        }
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn chop() {
+        let path = env::temp_dir().join(format!("vere-lmdb-log-chop-{}", process::id()));
+        let mut log = LmdbLog::new(&path).unwrap();
+        for num in 1..=3 {
+            log.append(evt(num)).unwrap();
+        }
+
+        // Chop, after which the log starts after the chop point.
+        {
+            assert!(matches!(log.chop(4), Err(Error::BadEventLog(_))));
+            log.chop(1).unwrap();
+            assert_eq!((2, 3), (log.first(), log.last()));
+            assert_eq!(2, log.read(1, 1).unwrap()[0].num);
+            let log = LmdbLog::new(&path).unwrap();
+            assert_eq!((2, 3), (log.first(), log.last()));
+        }
+
+        // Chopping every event leaves an empty log that continues from the chop point.
+        {
+            log.chop(3).unwrap();
+            drop(log);
+            let mut log = LmdbLog::new(&path).unwrap();
+            assert_eq!((0, 3), (log.first(), log.last()));
+            log.append(evt(4)).unwrap();
+            assert_eq!((4, 4), (log.first(), log.last()));
+        }
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn version_zero() {
+        let path = env::temp_dir().join(format!("vere-lmdb-log-v0-{}", process::id()));
+
+        // Events of a log without a version are jammed [mug date ovum] records.
+        {
+            let mut log = LmdbLog::new(&path).unwrap();
+            log.version = 0;
+            log.set_meta("version", &[]).unwrap();
+            log.append(evt(1)).unwrap();
+            log.commit().unwrap();
+            let txn = log.env.begin_ro_txn().unwrap();
+            let val = txn.get(log.events, &1u64.to_ne_bytes()).unwrap();
+            assert_eq!(
+                evt(1).ovum,
+                Event::from_record(1, Noun::cue(val).unwrap()).unwrap().ovum
+            );
+        }
+
+        // They read back the same.
+        {
+            let log = LmdbLog::new(&path).unwrap();
+            assert_eq!(0, log.version());
+            assert_eq!(evt(1).mug, log.read(1, 1).unwrap()[0].mug);
+        }
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn c_version() {
+        let path = env::temp_dir().join(format!("vere-lmdb-log-c-{}", process::id()));
+
+        // The C runtime writes version 1 as the single byte 1.
+        {
+            let log = LmdbLog::new(&path).unwrap();
+            assert_eq!(Some(vec![1]), log.meta("version").unwrap());
+        }
+        {
+            let mut log = LmdbLog::new(&path).unwrap();
+            log.set_meta("version", &[1]).unwrap();
+            log.append(evt(1)).unwrap();
+            log.commit().unwrap();
+        }
+        {
+            let log = LmdbLog::new(&path).unwrap();
+            assert_eq!(1, log.version());
+            assert_eq!(evt(1).mug, log.read(1, 1).unwrap()[0].mug);
+        }
+
+        // A version too big to be one is rejected.
+        {
+            let mut log = LmdbLog::new(&path).unwrap();
+            log.set_meta("version", &[0xff; 9]).unwrap();
+            drop(log);
+            assert!(matches!(LmdbLog::new(&path), Err(Error::BadEventLog(_))));
+        }
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -1,25 +1,296 @@
-use crate::{error::Error, kernel::Kernel};
-use std::path::Path;
+pub mod epoch;
+pub mod export;
+pub mod file;
+pub mod lmdb;
+pub mod replay;
 
-trait EvtLog: Sized {
+use crate::{
+    error::{Context, Error},
+    event_log::replay::Progress,
+    snapshot::Snapshot,
+};
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+};
+
+pub trait EvtLog: Sized {
     type Evt: Evt;
 
-    fn _new(_path: &Path) -> Result<Self, Error>;
+    /// Open the event log at `path`, creating it if it doesn't exist.
+    fn new(path: &Path) -> Result<Self, Error>;
+
+    fn path(&self) -> &Path;
+
+    /// Get the number of the first event, or 0 if the log is empty.
+    fn first(&self) -> u64;
+
+    /// Get the number of the most recently appended event, which is the event before the log's
+    /// start if the log is empty.
+    fn last(&self) -> u64;
+
+    /// Start an empty log at event `first` rather than 1, as when it continues from a snapshot.
+    fn set_first(&mut self, first: u64) -> Result<(), Error>;
+
+    /// Append an event, which must immediately follow the most recently appended event.
+    ///
+    /// The event may not be durable until a later append or [`EvtLog::commit`]; the returned
+    /// acknowledgment says which events are.
+    fn append(&mut self, evt: Self::Evt) -> Result<Ack, Error>;
+
+    /// Make every appended event durable.
+    fn commit(&mut self) -> Result<Ack, Error>;
 
-    fn _path(&self) -> &Path;
+    /// Read up to `count` durable events starting at event number `start`.
+    fn read(&self, start: u64, count: usize) -> Result<Vec<Self::Evt>, Error>;
 
-    fn append(self, _evt: Self::Evt) -> Result<Self, Self>;
+    /// Replay the log onto a snapshot, or onto the kernel computed from the log's lifecycle
+    /// events if there's no snapshot, stopping after event `to` or the last durable event.
+    ///
+    /// Stops with [`Error::Diverged`] at the first event that doesn't reproduce its recorded
+    /// mug, and calls `progress` after each batch of events.
+    fn replay(
+        &self,
+        snap: Option<Snapshot>,
+        to: Option<u64>,
+        progress: &mut dyn FnMut(&Progress),
+    ) -> Result<Snapshot, Error>
+    where
+        Self: EvtLog<Evt = Event>,
+    {
+        replay::replay(self, snap, to, progress)
+    }
 
-    fn _replay(&self, _kern: Kernel) -> Result<Kernel, Error>;
+    /// Durably remove every event after event `evt_num`, which must be in the log.
+    fn truncate(&mut self, evt_num: u64) -> Result<(), Error>;
 
-    fn _truncate(&mut self, _evt: Self::Evt) -> Result<(), Error>;
+    /// Durably remove every event up to and including event `evt_num`, which must be in the
+    /// log, after which the log starts at event `evt_num + 1`.
+    fn chop(&mut self, evt_num: u64) -> Result<(), Error>;
+
+    /// Get a metadata value.
+    fn meta(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Durably set a metadata value.
+    fn set_meta(&mut self, key: &str, val: &[u8]) -> Result<(), Error>;
 }
 
-trait Evt: Ord + Sized {
+/// Metadata key of the number of the first event of a log that doesn't start at event 1.
+pub(crate) const FIRST: &str = "first";
+
+/// Metadata key of the ship a log belongs to, as in the C runtime.
+pub(crate) const WHO: &str = "who";
+
+/// Metadata key of whether the ship a log belongs to is a fake ship, as in the C runtime.
+pub(crate) const FAKE: &str = "fake";
+
+/// Decode the number of a log's first event from its metadata.
+pub(crate) fn decode_first(val: &[u8]) -> Result<u64, Error> {
+    match <[u8; 8]>::try_from(val).map(u64::from_le_bytes) {
+        Ok(first) if 0 != first => Ok(first),
+        _ => Err(Error::BadEventLog("malformed first event".to_string())),
+    }
+}
+
+pub trait Evt: Ord + Sized {
     type Id;
     type Req;
 
-    fn _id(&self) -> &Self::Id;
+    fn id(&self) -> &Self::Id;
+
+    fn request(&self) -> &Self::Req;
+}
+
+/// Acknowledgment that every event up to and including `evt_num` is durable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ack {
+    pub evt_num: u64,
+}
+
+/// An ovum, the time it was received and its position in the event log. Event numbers start
+/// at 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub num: u64,
+    /// Mug of the kernel that resulted from the event, or 0 if unknown, as for lifecycle events.
+    pub mug: u32,
+    pub date: Atom,
+    pub ovum: Noun,
+}
+
+impl Event {
+    /// Convert an event to its record, `[mug date ovum]`.
+    pub fn to_record(&self) -> Noun {
+        Noun::from_tuple(vec![
+            Noun::from(u64::from(self.mug)),
+            Noun::from(self.date.clone()),
+            self.ovum.clone(),
+        ])
+    }
+
+    /// Convert a `[mug date ovum]` record to event number `num`.
+    pub fn from_record(num: u64, record: Noun) -> Result<Self, Error> {
+        let bad = || Error::BadEventLog(format!("event {} isn't [mug date ovum]", num));
+        let mut fields = record.into_tuple(3).map_err(|_| bad())?.into_iter();
+        let mug = match fields.next() {
+            Some(Noun::Atom(Atom::Direct(mug))) => u32::try_from(mug).map_err(|_| bad())?,
+            _ => return Err(bad()),
+        };
+        let date = match fields.next() {
+            Some(Noun::Atom(date)) => date,
+            _ => return Err(bad()),
+        };
+        Ok(Self {
+            num,
+            mug,
+            date,
+            ovum: fields.next().ok_or_else(bad)?,
+        })
+    }
+}
+
+impl Evt for Event {
+    type Id = u64;
+    type Req = Noun;
+
+    fn id(&self) -> &Self::Id {
+        &self.num
+    }
+
+    fn request(&self) -> &Self::Req {
+        &self.ovum
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.num.cmp(&other.num)
+    }
+}
+
+/// An event log in whichever format a pier uses.
+pub enum Log {
+    File(file::FileLog),
+    Lmdb(lmdb::LmdbLog),
+}
+
+impl Log {
+    /// Get the path of a pier's event log.
+    pub fn path(pier: &Path) -> PathBuf {
+        pier.join(".urb").join("log")
+    }
+
+    /// Check whether a pier predates epochs and keeps a single log directly in `.urb/log`.
+    pub fn is_flat(pier: &Path) -> bool {
+        let path = Self::path(pier);
+        path.join("data.mdb").exists() || path.join("events").exists()
+    }
+
+    /// Open a pier's event log, which is the log of its current epoch unless the pier predates
+    /// epochs and keeps a single LMDB or file log directly in `.urb/log`.
+    pub fn open(pier: &Path) -> Result<Self, Error> {
+        if Self::is_flat(pier) {
+            Self::new(&Self::path(pier))
+        } else {
+            epoch::Epoch::current(pier)?.log()
+        }
+    }
+}
+
+impl EvtLog for Log {
+    type Evt = Event;
+
+    fn new(path: &Path) -> Result<Self, Error> {
+        if path.join("data.mdb").exists() {
+            lmdb::LmdbLog::new(path).map(Log::Lmdb).at_path(path)
+        } else {
+            file::FileLog::new(path).map(Log::File).at_path(path)
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Log::File(log) => log.path(),
+            Log::Lmdb(log) => log.path(),
+        }
+    }
+
+    fn first(&self) -> u64 {
+        match self {
+            Log::File(log) => log.first(),
+            Log::Lmdb(log) => log.first(),
+        }
+    }
+
+    fn last(&self) -> u64 {
+        match self {
+            Log::File(log) => log.last(),
+            Log::Lmdb(log) => log.last(),
+        }
+    }
+
+    fn set_first(&mut self, first: u64) -> Result<(), Error> {
+        match self {
+            Log::File(log) => log.set_first(first),
+            Log::Lmdb(log) => log.set_first(first),
+        }
+    }
+
+    fn append(&mut self, evt: Self::Evt) -> Result<Ack, Error> {
+        match self {
+            Log::File(log) => log.append(evt),
+            Log::Lmdb(log) => log.append(evt),
+        }
+    }
+
+    fn commit(&mut self) -> Result<Ack, Error> {
+        match self {
+            Log::File(log) => log.commit(),
+            Log::Lmdb(log) => log.commit(),
+        }
+    }
+
+    fn read(&self, start: u64, count: usize) -> Result<Vec<Self::Evt>, Error> {
+        match self {
+            Log::File(log) => log.read(start, count),
+            Log::Lmdb(log) => log.read(start, count),
+        }
+    }
+
+    fn truncate(&mut self, evt_num: u64) -> Result<(), Error> {
+        match self {
+            Log::File(log) => log.truncate(evt_num),
+            Log::Lmdb(log) => log.truncate(evt_num),
+        }
+    }
+
+    fn chop(&mut self, evt_num: u64) -> Result<(), Error> {
+        match self {
+            Log::File(log) => log.chop(evt_num),
+            Log::Lmdb(log) => log.chop(evt_num),
+        }
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            Log::File(log) => log.meta(key),
+            Log::Lmdb(log) => log.meta(key),
+        }
+    }
 
-    fn _request(&self) -> &Self::Req;
+    fn set_meta(&mut self, key: &str, val: &[u8]) -> Result<(), Error> {
+        match self {
+            Log::File(log) => log.set_meta(key, val),
+            Log::Lmdb(log) => log.set_meta(key, val),
+        }
+    }
 }
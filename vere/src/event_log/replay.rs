@@ -0,0 +1,291 @@
+use crate::{
+    error::Error,
+    event_log::{Event, EvtLog},
+    kernel::Kernel,
+    snapshot::Snapshot,
+};
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Number of events read from the log at a time.
+const BATCH: usize = 1000;
+
+/// How far a replay has gotten.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    /// Number of the event the replay started after.
+    pub start: u64,
+    /// Number of the most recently replayed event.
+    pub evt_num: u64,
+    /// Number of the event the replay will stop at.
+    pub end: u64,
+    /// Time spent replaying so far.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Get the number of events replayed per second.
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if 0.0 == secs {
+            0.0
+        } else {
+            (self.evt_num - self.start) as f64 / secs
+        }
+    }
+
+    /// Estimate the time until the replay is done, if any events have been replayed.
+    pub fn eta(&self) -> Option<Duration> {
+        let rate = self.rate();
+        if 0.0 == rate {
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                (self.end - self.evt_num) as f64 / rate,
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "replayed {}/{} ({:.0} events/s",
+            self.evt_num,
+            self.end,
+            self.rate()
+        )?;
+        if let Some(eta) = self.eta() {
+            write!(f, ", eta {}s", eta.as_secs())?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The first event whose replay didn't reproduce the kernel recorded in the log.
+#[derive(Debug)]
+pub struct Divergence {
+    pub evt_num: u64,
+    /// Mug of the kernel recorded in the log.
+    pub recorded: u32,
+    /// Mug of the kernel computed by replay, or the reason the event crashed.
+    pub computed: Result<u32, String>,
+    pub date: Atom,
+    pub ovum: Noun,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "event {} diverged: recorded mug {:#x} but ",
+            self.evt_num, self.recorded
+        )?;
+        match &self.computed {
+            Ok(mug) => write!(f, "computed mug {:#x}", mug)?,
+            Err(crash) => write!(f, "crashed: {}", crash)?,
+        }
+        let wire = match &self.ovum {
+            Noun::Cell(c) => &*c.head,
+            atom => atom,
+        };
+        write!(f, " (date {:?}, wire {})", self.date.limbs(), wire)
+    }
+}
+
+/// Replay `log` onto `snap`, or onto the kernel computed from the log's lifecycle events if
+/// there's no snapshot, stopping after event `to` or the last durable event.
+///
+/// Lifecycle events are the log's leading events with a mug of 0. Every other event with a
+/// nonzero mug is checked against the kernel it produces.
+pub(super) fn replay<L: EvtLog<Evt = Event>>(
+    log: &L,
+    snap: Option<Snapshot>,
+    to: Option<u64>,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<Snapshot, Error> {
+    let end = to.unwrap_or(u64::MAX).min(log.last());
+    match (&snap, log.first()) {
+        (None, first) if first > 1 => {
+            return Err(Error::BadEventLog(format!(
+                "history starts at event {}, so replay needs a snapshot",
+                first
+            )))
+        }
+        (Some(snap), first) if first > snap.evt_num + 1 => {
+            return Err(Error::BadEventLog(format!(
+                "history starts at event {}, after the snapshot of event {}",
+                first, snap.evt_num
+            )))
+        }
+        _ => {}
+    }
+    let Snapshot {
+        mut evt_num,
+        mut kernel,
+    } = match snap {
+        Some(snap) => snap,
+        None => boot(log)?,
+    };
+    let start = evt_num;
+    let began = Instant::now();
+    while evt_num < end {
+        let count = usize::try_from(end - evt_num).map_or(BATCH, |left| left.min(BATCH));
+        let evts = log.read(evt_num + 1, count)?;
+        if evts.is_empty() {
+            break;
+        }
+        for evt in evts {
+            let Event {
+                num,
+                mug,
+                date,
+                ovum,
+            } = evt;
+            kernel = match kernel.poke(date.clone(), ovum.clone()) {
+                Ok((_, next)) if 0 == mug || next.mug() == mug => next,
+                res => {
+                    return Err(Error::Diverged(Box::new(Divergence {
+                        evt_num: num,
+                        recorded: mug,
+                        computed: res
+                            .map(|(_, next)| next.mug())
+                            .map_err(|err| err.to_string()),
+                        date,
+                        ovum,
+                    })))
+                }
+            };
+            evt_num = num;
+        }
+        kernel = kernel.relieve();
+        progress(&Progress {
+            start,
+            evt_num,
+            end,
+            elapsed: began.elapsed(),
+        });
+    }
+    Ok(Snapshot { evt_num, kernel })
+}
+
+/// Compute the kernel from the log's lifecycle events.
+fn boot<L: EvtLog<Evt = Event>>(log: &L) -> Result<Snapshot, Error> {
+    let mut boot = Vec::new();
+    loop {
+        let evts = log.read(boot.len() as u64 + 1, BATCH)?;
+        let done = evts.len() < BATCH || evts.iter().any(|evt| 0 != evt.mug);
+        boot.extend(evts.into_iter().take_while(|evt| 0 == evt.mug));
+        if done {
+            break;
+        }
+    }
+    if boot.is_empty() {
+        return Err(Error::BadEventLog("no lifecycle events".to_string()));
+    }
+    Ok(Snapshot {
+        evt_num: boot.len() as u64,
+        kernel: Kernel::lifecycle(boot.into_iter().map(|evt| evt.ovum).collect())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event_log::file::FileLog,
+        test_util::{arvo, pill},
+        time,
+    };
+    use nock::serdes::Jam;
+    use std::{env, fs, process};
+
+    #[test]
+    fn replay() {
+        let dir = env::temp_dir().join(format!("vere-replay-{}", process::id()));
+        let path = dir.join("toy.pill");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, Noun::from(pill(arvo(Noun::from(0)))).jam()).unwrap();
+        let ovum = |n: u64| Noun::from((Noun::from(n), Noun::from(n)));
+
+        // Boot and apply three events, recording the resulting mugs.
+        let mut log = FileLog::new(&dir.join("log")).unwrap();
+        let (mut kernel, _) = Kernel::new(&path, &mut log).unwrap();
+        for num in 3..=5 {
+            let date = time::now();
+            kernel = kernel.poke(date.clone(), ovum(num)).unwrap().1;
+            log.append(Event {
+                num,
+                mug: kernel.mug(),
+                date,
+                ovum: ovum(num),
+            })
+            .unwrap();
+        }
+        log.commit().unwrap();
+
+        // Replay everything from the lifecycle events, reporting progress.
+        {
+            let mut reports = Vec::new();
+            let snap = log
+                .replay(None, None, &mut |p: &Progress| reports.push(*p))
+                .unwrap();
+            assert_eq!(5, snap.evt_num);
+            assert_eq!(Noun::from(kernel.clone()), Noun::from(snap.kernel));
+            assert_eq!(1, reports.len());
+            assert_eq!(
+                (2, 5, 5),
+                (reports[0].start, reports[0].evt_num, reports[0].end)
+            );
+        }
+
+        // Replay up to an event and then the rest from a snapshot.
+        {
+            let snap = log.replay(None, Some(3), &mut |_| {}).unwrap();
+            assert_eq!(3, snap.evt_num);
+            assert_eq!(arvo(ovum(3)), Noun::from(snap.kernel.clone()));
+            let snap = log.replay(Some(snap), None, &mut |_| {}).unwrap();
+            assert_eq!(5, snap.evt_num);
+            assert_eq!(arvo(ovum(5)), Noun::from(snap.kernel));
+        }
+
+        // Stop at an event whose recorded mug doesn't match.
+        {
+            log.append(Event {
+                num: 6,
+                mug: kernel.mug() ^ 1,
+                date: time::now(),
+                ovum: ovum(6),
+            })
+            .unwrap();
+            log.commit().unwrap();
+            match log.replay(None, None, &mut |_| {}) {
+                Err(Error::Diverged(div)) => {
+                    assert_eq!(6, div.evt_num);
+                    assert!(div.computed.is_ok());
+                    assert!(format!("{}", div).starts_with("event 6 diverged"));
+                }
+                _ => panic!("replay didn't diverge"),
+            }
+            log.truncate(5).unwrap();
+        }
+
+        // Once chopped, the log can only be replayed from a snapshot.
+        {
+            let snap = log.replay(None, Some(4), &mut |_| {}).unwrap();
+            log.chop(3).unwrap();
+            assert!(matches!(
+                log.replay(None, None, &mut |_| {}),
+                Err(Error::BadEventLog(_))
+            ));
+            let snap = log.replay(Some(snap), None, &mut |_| {}).unwrap();
+            assert_eq!(arvo(ovum(5)), Noun::from(snap.kernel));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
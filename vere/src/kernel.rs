@@ -1,21 +1,295 @@
-use nock::{cell::Cell, noun::Noun};
-use std::path::Path;
+use crate::{
+    error::Error,
+    event_log::{Event, EvtLog},
+    pill::Pill,
+    time,
+};
+use loom::{
+    mark::{Mark, Marker},
+    Loom,
+};
+use nock::{atom::Atom, cell::Cell, hash::Mug, interpreters::Tar, noun::Noun};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
 
+/// Axis of Arvo's `+peek` arm.
+const PEEK_AXIS: u64 = 22;
+
+/// Axis of Arvo's `+poke` arm.
+const POKE_AXIS: u64 = 47;
+
+#[derive(Clone)]
 pub struct Kernel(Cell);
 
 impl Kernel {
-    fn _new(pill: Option<&Path>) -> Self {
-        match pill {
-            Some(_) => {
-                unimplemented!("open from local file")
+    /// Boot a kernel from a pill, recording the pill's lifecycle events as the first events of
+    /// an empty event log.
+    ///
+    /// Returns the kernel along with the pill's remaining events, which have yet to be applied.
+    pub fn new<L: EvtLog<Evt = Event>>(
+        pill: &Path,
+        log: &mut L,
+    ) -> Result<(Self, Vec<Noun>), Error> {
+        let (boot, ova) = Pill::load(pill)?.events();
+        if 0 != log.last() {
+            return Err(Error::BadEventLog(format!(
+                "can't boot into a log with {} events",
+                log.last()
+            )));
+        }
+        let kernel = Self::lifecycle(boot.clone())?;
+        for (num, ovum) in (1..).zip(boot) {
+            log.append(Event {
+                num,
+                mug: 0,
+                date: time::now(),
+                ovum,
+            })?;
+        }
+        log.commit()?;
+        Ok((kernel, ova))
+    }
+
+    /// Compute a kernel by running the lifecycle formula `[2 [0 3] [0 2]]` over a list of
+    /// lifecycle events: the first event is a formula run against the rest of the events.
+    ///
+    /// The kernel is at axis 7 of the result.
+    pub fn lifecycle(boot: Vec<Noun>) -> Result<Self, Error> {
+        let formula = Noun::from_tuple(vec![
+            Noun::from(2),
+            Noun::from((Noun::from(0), Noun::from(3))),
+            Noun::from((Noun::from(0), Noun::from(2))),
+        ]);
+        let res = Cell {
+            head: Box::new(Noun::from_list(boot)),
+            tail: Box::new(formula),
+        }
+        .tar()?;
+        match res {
+            Noun::Cell(Cell { tail, .. }) => match *tail {
+                Noun::Cell(Cell { tail, .. }) => Self::try_from(*tail).map_err(|_| {
+                    Error::BadPill("lifecycle produced an atom as its kernel".to_string())
+                }),
+                Noun::Atom(_) => Err(Error::BadPill("lifecycle produced no kernel".to_string())),
+            },
+            Noun::Atom(_) => Err(Error::BadPill("lifecycle produced no kernel".to_string())),
+        }
+    }
+
+    /// Apply an ovum to the kernel by slamming Arvo's `+poke` gate with `[now ovum]`, producing
+    /// effects and the next kernel. The kernel is unchanged if the poke crashes.
+    pub fn poke(&self, now: Atom, ovum: Noun) -> Result<(Noun, Self), Error> {
+        let crash = |msg: &str| Error::Crash {
+            arm: "poke",
+            msg: msg.to_string(),
+            source: None,
+        };
+        match self.slam("poke", POKE_AXIS, Noun::from((Noun::from(now), ovum)))? {
+            Noun::Cell(Cell { head, tail }) => {
+                let kernel =
+                    Self::try_from(*tail).map_err(|_| crash("produced an atom as its kernel"))?;
+                Ok((*head, kernel))
             }
-            None => {
-                unimplemented!("download from bootstrap.urbit.org")
+            Noun::Atom(_) => Err(crash("produced an atom instead of [effects kernel]")),
+        }
+    }
+
+    /// Read from the kernel by slamming Arvo's `+peek` gate with `[now path]`.
+    ///
+    /// Produces `None` if the path is blocked, `Some(None)` if it's unavailable and
+    /// `Some(Some(val))` otherwise.
+    pub fn peek(&self, now: Atom, path: Noun) -> Result<Option<Option<Noun>>, Error> {
+        let crash = || Error::Crash {
+            arm: "peek",
+            msg: "produced a noun other than a (unit (unit))".to_string(),
+            source: None,
+        };
+        let unit = |noun: Noun| match noun {
+            Noun::Atom(Atom::Direct(0)) => Ok(None),
+            Noun::Cell(Cell { head, tail }) if Noun::from(0) == *head => Ok(Some(*tail)),
+            _ => Err(crash()),
+        };
+        match unit(self.slam("peek", PEEK_AXIS, Noun::from((Noun::from(now), path)))?)? {
+            Some(res) => Ok(Some(unit(res)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Slam the gate produced by the arm at `axis` with `sam`, i.e. compute
+    /// `*[kernel 9 2 10 [6 1 sam] 9 axis 0 1]`, reporting any crash as coming from `arm`.
+    fn slam(&self, arm: &'static str, axis: u64, sam: Noun) -> Result<Noun, Error> {
+        let formula = Noun::from_tuple(vec![
+            Noun::from(9),
+            Noun::from(2),
+            Noun::from(10),
+            Noun::from_tuple(vec![Noun::from(6), Noun::from(1), sam]),
+            Noun::from(9),
+            Noun::from(axis),
+            Noun::from(0),
+            Noun::from(1),
+        ]);
+        let subject = Noun::Cell(self.0.clone());
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            Cell {
+                head: Box::new(subject),
+                tail: Box::new(formula),
             }
+            .tar()
+        }))
+        .map_err(|_| Error::Crash {
+            arm,
+            msg: "interpreter panicked".to_string(),
+            source: None,
+        })?
+        .map_err(|err| Error::Crash {
+            arm,
+            msg: err.to_string(),
+            source: Some(err),
+        })
+    }
+
+    /// Get the kernel's mug.
+    pub fn mug(&self) -> u32 {
+        self.0.mug()
+    }
+
+    /// Compact the kernel's loom allocations.
+    pub fn pack(self) -> Self {
+        match Noun::Cell(self.0).pack() {
+            Noun::Cell(c) => Self(c),
+            Noun::Atom(_) => unreachable!(),
+        }
+    }
+
+    /// Pack the kernel if the loom is under pressure, which must happen before the loom's cap is
+    /// reached. Packing frees little if the kernel is already compact, so the loom isn't under
+    /// pressure again until it has grown further.
+    pub fn relieve(self) -> Self {
+        if !Loom::pressure() {
+            return self;
+        }
+        let kernel = self.pack();
+        Loom::relieved();
+        kernel
+    }
+}
+
+/// Kernel from Noun.
+impl TryFrom<Noun> for Kernel {
+    type Error = ();
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        match noun {
+            Noun::Cell(c) => Ok(Self(c)),
+            Noun::Atom(_) => Err(()),
+        }
+    }
+}
+
+/// Noun from Kernel.
+impl From<Kernel> for Noun {
+    fn from(kernel: Kernel) -> Self {
+        Noun::Cell(kernel.0)
+    }
+}
+
+impl AsRef<Cell> for Kernel {
+    fn as_ref(&self) -> &Cell {
+        &self.0
+    }
+}
+
+impl Mark for Kernel {
+    fn mark(&self, marker: &mut Marker) {
+        self.0.mark(marker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event_log::file::FileLog,
+        test_util::{arvo, pill},
+    };
+    use nock::serdes::Jam;
+    use std::{env, fs, process};
+
+    #[test]
+    fn boot() {
+        let dir = env::temp_dir().join(format!("vere-kernel-boot-{}", process::id()));
+        let path = dir.join("toy.pill");
+        let core = Noun::from((Noun::from(42), Noun::from(43)));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Boot from a pill, recording the lifecycle events in the log.
+        {
+            let pill = pill(core.clone());
+            let boot = pill.boot.clone();
+            fs::write(&path, Noun::from(pill).jam()).unwrap();
+            let mut log = FileLog::new(&dir.join("log")).unwrap();
+            let (kernel, ova) = Kernel::new(&path, &mut log).unwrap();
+            assert_eq!(core, Noun::from(kernel));
+            assert_eq!(vec![Noun::from(7), Noun::from(8)], ova);
+            assert_eq!(2, log.last());
+            let evts = log.read(1, 2).unwrap();
+            assert_eq!(
+                boot,
+                evts.into_iter().map(|evt| evt.ovum).collect::<Vec<_>>()
+            );
         }
+
+        // Refuse to boot into a log that already has events.
+        {
+            let mut log = FileLog::new(&dir.join("log")).unwrap();
+            assert!(matches!(
+                Kernel::new(&path, &mut log),
+                Err(Error::BadEventLog(_))
+            ));
+        }
+
+        // Reject a lifecycle that doesn't produce a kernel.
+        {
+            let pill = pill(Noun::from(42));
+            fs::write(&path, Noun::from(pill).jam()).unwrap();
+            let mut log = FileLog::new(&dir.join("empty")).unwrap();
+            assert!(matches!(
+                Kernel::new(&path, &mut log),
+                Err(Error::BadPill(_))
+            ));
+            assert_eq!(0, log.last());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 
-    pub fn evaluate(self, req: Noun) -> (Noun, Self) {
-        unimplemented!("{}", req)
+    #[test]
+    fn poke_peek() {
+        let kernel = Kernel::try_from(arvo(Noun::from(0))).unwrap();
+        let ovum = Noun::from((Noun::from(1), Noun::from(2)));
+        let path = Noun::from_list(vec![Noun::from(Atom::from("state"))]);
+
+        // Poke the kernel and then observe its new state.
+        {
+            let (effects, kernel) = kernel.poke(time::now(), ovum.clone()).unwrap();
+            assert_eq!(Ok(vec![ovum.clone()]), effects.into_list());
+            assert_eq!(arvo(ovum.clone()), Noun::from(kernel.clone()));
+            assert_eq!(Some(Some(ovum)), kernel.peek(time::now(), path).unwrap());
+        }
+
+        // A kernel without Arvo's arms crashes rather than panicking.
+        {
+            let kernel = Kernel::try_from(Noun::from((Noun::from(0), Noun::from(1)))).unwrap();
+            assert!(matches!(
+                kernel.poke(time::now(), Noun::from(0)),
+                Err(Error::Crash { arm: "poke", .. })
+            ));
+            assert!(matches!(
+                kernel.peek(time::now(), Noun::from(0)),
+                Err(Error::Crash { arm: "peek", .. })
+            ));
+        }
     }
 }
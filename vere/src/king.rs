@@ -0,0 +1,595 @@
+//! The king, which runs a pier's I/O and event log and leaves Nock evaluation to a serf in a
+//! separate process, so that a crash, stack overflow or exhausted loom kills only the serf. The
+//! king can then start a new serf and replay the log into it.
+
+use crate::{
+    driver::{Ask, Drivers, Effect, Status},
+    error::{Context, Error},
+    event_log::{
+        epoch::{Epoch, VERE_VERSION},
+        replay::{Divergence, Progress},
+        Event, EvtLog, Log,
+    },
+    newt,
+    pier::{Identity, Lock},
+    serf::{Job, Live, Plea, Writ, PROTOCOL},
+    snapshot::Snapshot,
+    time,
+};
+use loom::mass::Mass;
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    time::Instant,
+};
+
+/// Number of events sent to the serf in each `%play`.
+const BATCH: usize = 1000;
+
+/// The king's end of the pipe to a serf, which tracks the event the serf's kernel is at.
+struct Lord<R: Read, W: Write> {
+    inp: R,
+    out: W,
+    evt_num: u64,
+    mug: u32,
+}
+
+impl<R: Read, W: Write> Lord<R, W> {
+    /// Wait for a serf to announce itself.
+    fn new(mut inp: R, out: W) -> Result<Self, Error> {
+        match recv(&mut inp)? {
+            Plea::Ripe {
+                pro: PROTOCOL,
+                eve,
+                mug,
+            } => Ok(Self {
+                inp,
+                out,
+                evt_num: eve,
+                mug,
+            }),
+            Plea::Ripe { pro, .. } => Err(Error::Ipc(format!(
+                "serf speaks protocol {} instead of {}",
+                pro, PROTOCOL
+            ))),
+            plea => Err(unexpected(&plea)),
+        }
+    }
+
+    /// Send a writ without waiting for an answer.
+    fn send(&mut self, writ: Writ) -> Result<(), Error> {
+        newt::write(&mut self.out, &Noun::from(writ))
+    }
+
+    /// Send a writ and wait for the plea that answers it.
+    fn ask(&mut self, writ: Writ) -> Result<Plea, Error> {
+        self.send(writ)?;
+        recv(&mut self.inp)
+    }
+
+    /// Replay logged events, which must follow the serf's most recent event. The serf's kernel
+    /// is checked against the mug recorded for the last event.
+    fn play(&mut self, evts: Vec<Event>) -> Result<(), Error> {
+        let eve = match evts.first() {
+            Some(evt) => evt.num,
+            None => return Ok(()),
+        };
+        let jobs = evts
+            .iter()
+            .map(|evt| Job {
+                date: evt.date.clone(),
+                ovum: evt.ovum.clone(),
+            })
+            .collect();
+        let (evt, computed) = match self.ask(Writ::Play { eve, jobs })? {
+            Plea::Played { mug } => {
+                let last = evts.last().unwrap();
+                self.evt_num = last.num;
+                self.mug = mug;
+                if 0 == last.mug || mug == last.mug {
+                    return Ok(());
+                }
+                (evts.into_iter().last().unwrap(), Ok(mug))
+            }
+            Plea::PlayBail { eve, mug, why } => {
+                self.evt_num = eve;
+                self.mug = mug;
+                match evts.into_iter().find(|evt| evt.num == eve + 1) {
+                    Some(evt) => (evt, Err(why)),
+                    None => return Err(Error::Ipc(format!("serf bailed after event {}", eve))),
+                }
+            }
+            plea => return Err(unexpected(&plea)),
+        };
+        Err(Error::Diverged(Box::new(Divergence {
+            evt_num: evt.num,
+            recorded: evt.mug,
+            computed,
+            date: evt.date,
+            ovum: evt.ovum,
+        })))
+    }
+
+    /// Apply a new event, producing the kernel's mug and the event's effects.
+    fn work(&mut self, job: Job) -> Result<(u32, Noun), Error> {
+        match self.ask(Writ::Work(job))? {
+            Plea::Done { eve, mug, fec } if eve == self.evt_num + 1 => {
+                self.evt_num = eve;
+                self.mug = mug;
+                Ok((mug, fec))
+            }
+            Plea::WorkBail(why) => Err(Error::Crash {
+                arm: "poke",
+                msg: why,
+                source: None,
+            }),
+            plea => Err(unexpected(&plea)),
+        }
+    }
+
+    /// Read a path from the kernel.
+    fn peek(&mut self, now: Atom, path: Noun) -> Result<Option<Option<Noun>>, Error> {
+        match self.ask(Writ::Peek { now, path })? {
+            Plea::Peek(dat) => Ok(dat),
+            plea => Err(unexpected(&plea)),
+        }
+    }
+
+    /// Make a maintenance request, which the serf answers unless it's asked to exit.
+    fn live(&mut self, live: Live) -> Result<(), Error> {
+        if let Live::Exit(_) = live {
+            return self.send(Writ::Live(live));
+        }
+        match self.ask(Writ::Live(live))? {
+            Plea::Live => Ok(()),
+            plea => Err(unexpected(&plea)),
+        }
+    }
+
+    /// Get the loom's usage.
+    fn mass(&mut self) -> Result<Mass, Error> {
+        match self.ask(Writ::Live(Live::Mass))? {
+            Plea::Mass(mass) => Ok(mass),
+            plea => Err(unexpected(&plea)),
+        }
+    }
+}
+
+/// Read a plea, failing if the serf has exited.
+fn recv<R: Read>(inp: &mut R) -> Result<Plea, Error> {
+    match newt::read(inp)? {
+        Some(noun) => Plea::try_from(noun),
+        None => Err(Error::Ipc("serf exited".to_string())),
+    }
+}
+
+/// Report a plea that doesn't answer the writ it followed.
+fn unexpected(plea: &Plea) -> Error {
+    let tag = match plea {
+        Plea::Ripe { .. } => "%ripe",
+        Plea::Live => "%live",
+        Plea::Mass(_) => "%mass",
+        Plea::Peek(_) => "%peek",
+        Plea::Played { .. } => "%play %done",
+        Plea::PlayBail { .. } => "%play %bail",
+        Plea::Done { .. } => "%work %done",
+        Plea::WorkBail(_) => "%work %bail",
+    };
+    Error::Ipc(format!("unexpected {} plea", tag))
+}
+
+/// Replay the events of `logs`, which are a pier's logs in order, into a serf, from the event
+/// after the serf's most recent event to the last event. A serf without a kernel is first
+/// sent the lifecycle events.
+fn replay<R: Read, W: Write>(
+    lord: &mut Lord<R, W>,
+    logs: &[&Log],
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<(), Error> {
+    let end = logs.last().map_or(0, |log| log.last());
+    let start = lord.evt_num;
+    let began = Instant::now();
+    if 0 == lord.evt_num && end > 0 {
+        lord.play(lifecycle(logs[0])?)?;
+    }
+    for log in logs {
+        while lord.evt_num < log.last() {
+            if lord.evt_num + 1 < log.first() {
+                return Err(Error::BadEventLog(format!(
+                    "history starts at event {}, after event {}",
+                    log.first(),
+                    lord.evt_num
+                )));
+            }
+            let evts = log.read(lord.evt_num + 1, BATCH)?;
+            if evts.is_empty() {
+                break;
+            }
+            lord.play(evts)?;
+            progress(&Progress {
+                start,
+                evt_num: lord.evt_num,
+                end,
+                elapsed: began.elapsed(),
+            });
+        }
+    }
+    if lord.evt_num != end {
+        return Err(Error::BadEventLog(format!(
+            "replay stopped at event {} of {}",
+            lord.evt_num, end
+        )));
+    }
+    Ok(())
+}
+
+/// Read a log's lifecycle events, which are its leading events with a mug of 0.
+fn lifecycle(log: &Log) -> Result<Vec<Event>, Error> {
+    if 1 != log.first() {
+        return Err(Error::BadEventLog(format!(
+            "history starts at event {}, so replay needs a snapshot",
+            log.first()
+        )));
+    }
+    let mut boot = Vec::new();
+    loop {
+        let evts = log.read(boot.len() as u64 + 1, BATCH)?;
+        let done = evts.len() < BATCH || evts.iter().any(|evt| 0 != evt.mug);
+        boot.extend(evts.into_iter().take_while(|evt| 0 == evt.mug));
+        if done {
+            break;
+        }
+    }
+    if boot.is_empty() {
+        return Err(Error::BadEventLog("no lifecycle events".to_string()));
+    }
+    Ok(boot)
+}
+
+/// Start a serf for a pier from the executable at `serf`.
+fn spawn(
+    serf: &Path,
+    pier: &Path,
+    loom: Option<u64>,
+) -> Result<(Child, Lord<BufReader<ChildStdout>, ChildStdin>), Error> {
+    let mut cmd = Command::new(serf);
+    cmd.arg(pier);
+    if let Some(loom) = loom {
+        cmd.arg("--loom").arg(loom.to_string());
+    }
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .at_path(serf)?;
+    let inp = BufReader::new(child.stdout.take().unwrap());
+    let out = child.stdin.take().unwrap();
+    match Lord::new(inp, out) {
+        Ok(lord) => Ok((child, lord)),
+        Err(err) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(err)
+        }
+    }
+}
+
+/// A pier booted with its kernel in a serf: the log new events go to and the serf that
+/// computes them. The pier is locked for as long as it's booted.
+pub struct King {
+    path: PathBuf,
+    /// Executable the serf is run from.
+    serf: PathBuf,
+    /// Loom size the serf is run with, if it overrides the pier's configuration.
+    loom: Option<u64>,
+    log: Log,
+    child: Child,
+    lord: Lord<BufReader<ChildStdout>, ChildStdin>,
+    /// When the pier was booted.
+    booted: Instant,
+    _lock: Lock,
+}
+
+impl King {
+    /// Boot a pier by starting a serf from the executable at `serf`, which loads the pier's
+    /// latest snapshot, and replaying the rest of the pier's log into it, reporting progress.
+    /// If the pier's log is organized into epochs and its current epoch was created by a
+    /// different runtime version, a new epoch is started.
+    pub fn boot(
+        path: &Path,
+        serf: &Path,
+        loom: Option<u64>,
+        progress: &mut dyn FnMut(&Progress),
+    ) -> Result<Self, Error> {
+        let booted = Instant::now();
+        let lock = Lock::acquire(path)?;
+        let epochs = Epoch::list(path)?;
+        if epochs.is_empty() && !Log::is_flat(path) {
+            return Err(Error::BadEventLog(format!(
+                "{} has no event log",
+                path.display()
+            )));
+        }
+        let (child, lord) = spawn(serf, path, loom)?;
+        let mut king = Self {
+            path: path.to_path_buf(),
+            serf: serf.to_path_buf(),
+            loom,
+            log: Log::open(path)?,
+            child,
+            lord,
+            booted,
+            _lock: lock,
+        };
+        king.replay(progress)?;
+        if epochs
+            .last()
+            .is_some_and(|epoch| VERE_VERSION != epoch.vere)
+        {
+            king.snapshot()?;
+            if Epoch::upgrade(path, &Snapshot::load(path)?)?.is_some() {
+                king.log = Log::open(path)?;
+            }
+        }
+        Ok(king)
+    }
+
+    /// Replay the pier's events that the serf doesn't have yet: those of the pier's earlier
+    /// epochs, which are opened only for the replay, and then those of the current log.
+    fn replay(&mut self, progress: &mut dyn FnMut(&Progress)) -> Result<(), Error> {
+        let epochs = Epoch::list(&self.path)?;
+        let earlier = epochs[..epochs.len().saturating_sub(1)]
+            .iter()
+            .map(Epoch::log)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut logs: Vec<_> = earlier.iter().collect();
+        logs.push(&self.log);
+        replay(&mut self.lord, &logs, progress)
+    }
+
+    /// Replace the serf with a new one, which starts from the pier's latest snapshot, and
+    /// replay the log into it.
+    pub fn restart(&mut self) -> Result<(), Error> {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.log.commit()?;
+        let (child, lord) = spawn(&self.serf, &self.path, self.loom)?;
+        self.child = child;
+        self.lord = lord;
+        self.replay(&mut |_| {})
+    }
+
+    /// Restart the serf after a request to it fails for any reason other than the kernel
+    /// crashing, which the serf survives. Fails only if the restart does, and otherwise
+    /// produces the request's result.
+    fn recover<T>(&mut self, res: Result<T, Error>) -> Result<Result<T, Error>, Error> {
+        match res {
+            Err(err) if !matches!(err, Error::Crash { .. }) => {
+                self.restart()?;
+                Ok(Err(err))
+            }
+            res => Ok(res),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the number of the most recent event.
+    pub fn evt_num(&self) -> u64 {
+        self.lord.evt_num
+    }
+
+    /// Get the mug of the kernel as of the most recent event.
+    pub fn mug(&self) -> u32 {
+        self.lord.mug
+    }
+
+    /// Get the pier's identity, if its log records one.
+    pub fn identity(&self) -> Result<Option<Identity>, Error> {
+        Identity::load(&self.log)
+    }
+
+    /// Get the ID of the serf's process.
+    pub fn serf_id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Get the ship's state, asking the serf for the loom's usage. If the serf dies, it's
+    /// restarted before the failure is reported.
+    pub fn status(&mut self) -> Result<Status, Error> {
+        let res = self.lord.mass();
+        let mass = self.recover(res)??;
+        Ok(Status {
+            evt_num: self.lord.evt_num,
+            mug: self.lord.mug,
+            mass,
+            uptime: self.booted.elapsed(),
+        })
+    }
+
+    /// Apply an ovum and append it to the log, producing the kernel's effects. An ovum that
+    /// crashes the kernel is neither applied nor logged. If the serf dies, it's restarted
+    /// before the failure is reported, and the ovum can be retried.
+    pub fn poke(&mut self, ovum: Noun) -> Result<Noun, Error> {
+        self.apply(ovum)?
+    }
+
+    /// Apply an ovum and append it to the log, committing the log if the append left the event
+    /// buffered so that its effects are only produced once it's durable. Fails if the serf
+    /// can't be restarted or the event can't be logged, and otherwise produces the kernel's
+    /// effects or the reason the event was dropped. If the event can't be logged, the serf,
+    /// which has already applied it, is restarted so that it's back in step with the log.
+    fn apply(&mut self, ovum: Noun) -> Result<Result<Noun, Error>, Error> {
+        let date = time::now();
+        let num = self.lord.evt_num + 1;
+        let res = self.lord.work(Job {
+            date: date.clone(),
+            ovum: ovum.clone(),
+        });
+        let (mug, fec) = match self.recover(res)?.at_event(num) {
+            Ok(done) => done,
+            Err(err) => return Ok(Err(err)),
+        };
+        let logged = self
+            .log
+            .append(Event {
+                num,
+                mug,
+                date,
+                ovum,
+            })
+            .and_then(|ack| {
+                if ack.evt_num < num {
+                    self.log.commit()
+                } else {
+                    Ok(ack)
+                }
+            });
+        if let Err(err) = logged {
+            self.restart()?;
+            return Err(err);
+        }
+        Ok(Ok(fec))
+    }
+
+    /// Process the events the drivers produce until no more can arrive, applying each and
+    /// handing its effects to the drivers, and answer the requests made through injectors
+    /// between events. An event that's dropped because it crashes the kernel or the serf dies
+    /// is logged and reported to the driver its wire names, and a driver that fails to carry
+    /// out an effect is logged.
+    pub fn run(&mut self, drivers: &mut Drivers) -> Result<(), Error> {
+        while let Some(ovum) = drivers.wait_serving(&mut |ask| self.answer(ask)) {
+            let wire = match &ovum {
+                Noun::Cell(cell) => Effect::parse_wire(cell.head.as_ref().clone()),
+                Noun::Atom(_) => None,
+            };
+            let effects = match self.apply(ovum)?.and_then(Effect::parse_list) {
+                Ok(effects) => effects,
+                Err(err) => {
+                    eprintln!("vere: dropped {}", err);
+                    if let Some(wire) = wire {
+                        drivers.dropped(&wire, &err);
+                    }
+                    continue;
+                }
+            };
+            if let Err(err) = drivers.dispatch(effects) {
+                eprintln!("vere: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer a request made through an injector.
+    fn answer(&mut self, ask: Ask) {
+        let fail = |err: Error| err.to_string();
+        match ask {
+            Ask::Peek { path, reply } => {
+                let _ = reply.send(self.peek(path).map_err(fail));
+            }
+            Ask::Status { reply } => {
+                let _ = reply.send(self.status().map_err(fail));
+            }
+            Ask::Snapshot { reply } => {
+                let _ = reply.send(self.snapshot().map_err(fail));
+            }
+            Ask::Pack { reply } => {
+                let _ = reply.send(self.pack().map_err(fail));
+            }
+        }
+    }
+
+    /// Read a path in the kernel's namespace. If the serf dies, it's restarted before the
+    /// failure is reported.
+    pub fn peek(&mut self, path: Noun) -> Result<Option<Option<Noun>>, Error> {
+        let res = self.lord.peek(time::now(), path);
+        self.recover(res)?
+    }
+
+    /// Make every event durable and have the serf save a snapshot of the kernel as of the most
+    /// recent event.
+    pub fn snapshot(&mut self) -> Result<(), Error> {
+        self.log.commit()?;
+        let evt_num = self.lord.evt_num;
+        self.lord.live(Live::Save(evt_num))
+    }
+
+    /// Have the serf compact its kernel's loom allocations.
+    pub fn pack(&mut self) -> Result<(), Error> {
+        self.lord.live(Live::Pack)
+    }
+}
+
+impl Drop for King {
+    fn drop(&mut self) {
+        let _ = self.lord.live(Live::Exit(0));
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event_log::file::FileLog,
+        kernel::Kernel,
+        serf::Serf,
+        test_util::{poke, toy_pill},
+    };
+    use std::{env, fs, io, process, thread};
+
+    #[test]
+    fn replay_work() {
+        let pier = env::temp_dir().join(format!("vere-king-{}", process::id()));
+        let pill = toy_pill(&pier);
+        let log_path = Log::path(&pier);
+        {
+            let mut log = FileLog::new(&log_path).unwrap();
+            let (kernel, _) = Kernel::new(&pill, &mut log).unwrap();
+            poke(&mut log, kernel, 3..=4);
+        }
+        let log = Log::new(&log_path).unwrap();
+
+        // Drive a serf running on a thread.
+        let (serf_inp, king_out) = io::pipe().unwrap();
+        let (king_inp, serf_out) = io::pipe().unwrap();
+        let serf = {
+            let pier = pier.clone();
+            thread::spawn(move || Serf::load(&pier).unwrap().serve(serf_inp, serf_out))
+        };
+        let mut lord = Lord::new(king_inp, king_out).unwrap();
+        assert_eq!(0, lord.evt_num);
+
+        // Replay the lifecycle events and then the rest of the log.
+        let mut batches = 0;
+        replay(&mut lord, &[&log], &mut |_| batches += 1).unwrap();
+        assert_eq!((4, 1), (lord.evt_num, batches));
+        assert_eq!(log.read(4, 1).unwrap()[0].mug, lord.mug);
+
+        // Apply a new event and observe it.
+        let ovum = Noun::from((Noun::from(5), Noun::from(5)));
+        let (mug, fec) = lord
+            .work(Job {
+                date: time::now(),
+                ovum: ovum.clone(),
+            })
+            .unwrap();
+        assert_eq!((5, mug), (lord.evt_num, lord.mug));
+        assert_eq!(Ok(vec![ovum.clone()]), fec.into_list());
+        let path = Noun::from_list(vec![Noun::from(Atom::from("state"))]);
+        assert_eq!(Some(Some(ovum)), lord.peek(time::now(), path).unwrap());
+        assert!(lord.mass().unwrap().live.bytes > 0);
+
+        // Replaying events the serf already has is a protocol violation, after which the serf
+        // stops.
+        let evts = log.read(3, 1).unwrap();
+        assert!(matches!(lord.play(evts), Err(Error::Ipc(_))));
+        assert!(matches!(serf.join().unwrap(), Err(Error::Ipc(_))));
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
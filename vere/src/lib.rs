@@ -1,3 +1,17 @@
-mod error;
-mod kernel;
-mod state;
+pub mod config;
+pub mod driver;
+pub mod error;
+pub mod event_log;
+pub mod kernel;
+pub mod king;
+pub mod newt;
+pub mod noun;
+pub mod pier;
+pub mod pill;
+pub mod scry;
+pub mod serf;
+pub mod snapshot;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod time;
+pub mod verify;
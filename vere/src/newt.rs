@@ -0,0 +1,91 @@
+use crate::error::Error;
+use nock::{
+    noun::Noun,
+    serdes::{Cue, Jam},
+};
+use std::io::{ErrorKind, Read, Write};
+
+/// Version byte that opens every frame.
+const VERSION: u8 = 0;
+
+/// Write a noun as a newt frame, which is how the runtime's processes talk to each other: a
+/// version byte of 0, the little-endian 64-bit length of the noun's jam, and the jam. The frame
+/// is flushed once written.
+pub fn write<W: Write>(out: &mut W, noun: &Noun) -> Result<(), Error> {
+    let jam = noun.jam();
+    let mut frame = Vec::with_capacity(9 + jam.len());
+    frame.push(VERSION);
+    frame.extend_from_slice(&(jam.len() as u64).to_le_bytes());
+    frame.extend_from_slice(&jam);
+    out.write_all(&frame)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Read a newt frame written by [`write`], or nothing if the input ends before the frame starts.
+pub fn read<R: Read>(inp: &mut R) -> Result<Option<Noun>, Error> {
+    let mut version = [0; 1];
+    match inp.read_exact(&mut version) {
+        Ok(()) => {}
+        Err(err) if ErrorKind::UnexpectedEof == err.kind() => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    if VERSION != version[0] {
+        return Err(Error::Ipc(format!("unknown newt version {}", version[0])));
+    }
+    let mut len = [0; 8];
+    inp.read_exact(&mut len).map_err(truncated)?;
+    let len = u64::from_le_bytes(len);
+    let mut jam = Vec::new();
+    inp.take(len).read_to_end(&mut jam)?;
+    if jam.len() as u64 != len {
+        return Err(Error::Ipc("newt frame ends early".to_string()));
+    }
+    Ok(Some(Noun::cue(&jam)?))
+}
+
+/// Report a frame cut off in its header.
+fn truncated(err: std::io::Error) -> Error {
+    if ErrorKind::UnexpectedEof == err.kind() {
+        Error::Ipc("newt frame ends early".to_string())
+    } else {
+        err.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read() {
+        let nouns = [
+            Noun::from(0),
+            Noun::from((Noun::from(1), Noun::from((Noun::from(2), Noun::from(3))))),
+        ];
+        let mut bytes = Vec::new();
+        for noun in &nouns {
+            write(&mut bytes, noun).unwrap();
+        }
+        assert_eq!(0, bytes[0]);
+
+        // Read back each frame, then nothing.
+        {
+            let mut inp = &bytes[..];
+            assert_eq!(Some(nouns[0].clone()), read(&mut inp).unwrap());
+            assert_eq!(Some(nouns[1].clone()), read(&mut inp).unwrap());
+            assert_eq!(None, read(&mut inp).unwrap());
+        }
+
+        // Reject a frame that's cut off or has an unknown version.
+        {
+            let end = 9 + nouns[0].jam().len();
+            for cut in [5, end - 1] {
+                let mut inp = &bytes[..cut];
+                assert!(matches!(read(&mut inp), Err(Error::Ipc(_))));
+            }
+            let mut inp = &[1, 0, 0, 0, 0, 0, 0, 0, 0][..];
+            assert!(matches!(read(&mut inp), Err(Error::Ipc(_))));
+        }
+    }
+}
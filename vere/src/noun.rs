@@ -0,0 +1,92 @@
+//! Making and parsing the common Hoon molds that the runtime and the kernel exchange.
+
+use nock::{atom::Atom, noun::Noun};
+
+/// Make `[%tag noun]`.
+pub fn tagged(tag: &str, noun: Noun) -> Noun {
+    Noun::from((cord(tag), noun))
+}
+
+/// Make a cord.
+pub fn cord(text: &str) -> Noun {
+    Noun::from(Atom::from(text))
+}
+
+/// Parse a cord.
+pub fn text(noun: &Noun) -> Option<String> {
+    match noun {
+        Noun::Atom(atom) => String::from_utf8(atom.to_bytes()).ok(),
+        Noun::Cell(_) => None,
+    }
+}
+
+/// Make a loobean.
+pub fn flag(yes: bool) -> Noun {
+    Noun::from(if yes { 0 } else { 1 })
+}
+
+/// Parse a loobean, which is 0 for yes and 1 for no.
+pub fn parse_flag(noun: &Noun) -> Option<bool> {
+    match noun {
+        Noun::Atom(Atom::Direct(0)) => Some(true),
+        Noun::Atom(Atom::Direct(1)) => Some(false),
+        _ => None,
+    }
+}
+
+/// Make a `(unit octs)`, which is `~` for no bytes.
+pub fn octs(bytes: &[u8]) -> Noun {
+    if bytes.is_empty() {
+        return Noun::from(0);
+    }
+    Noun::from((
+        Noun::from(0),
+        Noun::from((
+            Noun::from(bytes.len() as u64),
+            Noun::from(Atom::from_bytes(bytes)),
+        )),
+    ))
+}
+
+/// Parse a `(unit octs)`, an atom's bytes padded with zeros to the length it's given with.
+pub fn parse_octs(noun: Noun) -> Option<Vec<u8>> {
+    match noun {
+        Noun::Atom(Atom::Direct(0)) => Some(Vec::new()),
+        Noun::Cell(cell) if Noun::from(0) == *cell.head => {
+            let mut octs = cell.tail.into_tuple(2).ok()?.into_iter();
+            let len = match octs.next()? {
+                Noun::Atom(Atom::Direct(len)) => usize::try_from(len).ok()?,
+                _ => return None,
+            };
+            let mut bytes = match octs.next()? {
+                Noun::Atom(atom) => atom.to_bytes(),
+                Noun::Cell(_) => return None,
+            };
+            bytes.resize(len, 0);
+            Some(bytes)
+        }
+        _ => None,
+    }
+}
+
+/// Make a `(list [key=@t value=@t])`.
+pub fn header_list(headers: &[(String, String)]) -> Noun {
+    Noun::from_list(
+        headers
+            .iter()
+            .map(|(key, val)| Noun::from((cord(key), cord(val))))
+            .collect(),
+    )
+}
+
+/// Parse a `(list [key=@t value=@t])`.
+pub fn parse_header_list(noun: Noun) -> Option<Vec<(String, String)>> {
+    noun.into_list()
+        .ok()?
+        .into_iter()
+        .map(|header| match header {
+            Noun::Cell(cell) => Some((text(&cell.head)?, text(&cell.tail)?)),
+            Noun::Atom(_) => None,
+        })
+        .collect()
+}
@@ -0,0 +1,949 @@
+use crate::{
+    config::Config,
+    error::{Context, Error},
+    event_log::{
+        epoch::{Epoch, VERE_VERSION},
+        export::{Header, Reader, Writer},
+        replay::Progress,
+        Event, EvtLog, Log, FAKE, WHO,
+    },
+    kernel::Kernel,
+    snapshot::Snapshot,
+    time,
+};
+use nock::{atom::Atom, noun::Noun, serdes::Jam};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A pier that's locked against being run by any other process.
+///
+/// The lock is an exclusive `flock(2)` on a file holding the ID of the process that holds it,
+/// so it's released by the kernel if that process dies. The file is removed on drop.
+pub struct Lock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl Lock {
+    /// Get the path of a pier's lock.
+    pub fn path(pier: &Path) -> PathBuf {
+        pier.join(".vere.lock")
+    }
+
+    /// Lock a pier, failing with [`Error::PierLive`] if a live process already holds the lock.
+    /// A lock file left behind by a process that's no longer running is taken over.
+    pub fn acquire(pier: &Path) -> Result<Self, Error> {
+        let path = Self::path(pier);
+        fs::create_dir_all(pier)?;
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+            if 0 != unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } {
+                let err = io::Error::last_os_error();
+                if ErrorKind::WouldBlock != err.kind() {
+                    return Err(err.into());
+                }
+                let mut pid = String::new();
+                file.read_to_string(&mut pid)?;
+                return Err(Error::PierLive(pid.trim().parse().unwrap_or(0)));
+            }
+            // The holder we waited on may have removed the file we opened before we locked
+            // it, in which case the lock is on a file no one else will see.
+            match fs::metadata(&path) {
+                Ok(meta) if meta.ino() == file.metadata()?.ino() => {}
+                Ok(_) => continue,
+                Err(err) if ErrorKind::NotFound == err.kind() => continue,
+                Err(err) => return Err(err.into()),
+            }
+            file.set_len(0)?;
+            file.write_all(process::id().to_string().as_bytes())?;
+            file.sync_all()?;
+            return Ok(Self { path, _file: file });
+        }
+    }
+
+    /// Get the ID of the live process holding a pier's lock, if any, probing the lock itself so
+    /// that a lock file left behind by a dead process isn't mistaken for a live one.
+    pub fn holder(pier: &Path) -> Result<Option<u32>, Error> {
+        let mut file = match File::open(Self::path(pier)) {
+            Ok(file) => file,
+            Err(err) if ErrorKind::NotFound == err.kind() => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        if 0 == unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } {
+            return Ok(None);
+        }
+        let err = io::Error::last_os_error();
+        if ErrorKind::WouldBlock != err.kind() {
+            return Err(err.into());
+        }
+        let mut pid = String::new();
+        file.read_to_string(&mut pid)?;
+        Ok(Some(pid.trim().parse().unwrap_or(0)))
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The ship a pier belongs to, recorded in its event log's metadata as in the C runtime: `who`
+/// as the bytes of an atom and `fake` as those of a loobean, so a fake ship's is empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub who: Atom,
+    /// Whether the ship is a fake ship, which never touches the live network.
+    pub fake: bool,
+}
+
+impl Identity {
+    /// Load the identity recorded in a log's metadata, if any.
+    pub fn load<L: EvtLog>(log: &L) -> Result<Option<Self>, Error> {
+        let who = match log.meta(WHO)? {
+            Some(who) => Atom::from_bytes(&who),
+            None => return Ok(None),
+        };
+        let fake = match log.meta(FAKE)?.as_deref() {
+            Some([] | [0]) => true,
+            None | Some([1]) => false,
+            Some(_) => return Err(Error::BadEventLog("malformed fake".to_string())),
+        };
+        Ok(Some(Self { who, fake }))
+    }
+
+    /// Record the identity in a log's metadata.
+    pub fn save<L: EvtLog>(&self, log: &mut L) -> Result<(), Error> {
+        log.set_meta(WHO, &self.who.to_bytes())?;
+        let fake = Atom::Direct(u64::from(!self.fake)).to_bytes();
+        log.set_meta(FAKE, &fake)
+    }
+}
+
+/// A booted pier: its kernel as of its most recent event and the log new events go to. The
+/// pier is locked for as long as it's booted.
+pub struct Pier {
+    path: PathBuf,
+    log: Log,
+    kernel: Kernel,
+    evt_num: u64,
+    _lock: Lock,
+}
+
+impl Pier {
+    /// Create a pier belonging to `ident` from a pill, computing its kernel from the pill's
+    /// lifecycle events and then applying the pill's remaining events. The pier must not
+    /// already have an event log, and its configuration is saved only once that's checked. If
+    /// creation fails, the partial log is removed.
+    pub fn create(
+        path: &Path,
+        pill: &Path,
+        ident: &Identity,
+        conf: &Config,
+    ) -> Result<Self, Error> {
+        let lock = Lock::acquire(path)?;
+        if Log::path(path).exists() {
+            return Err(Error::BadEventLog(format!(
+                "{} already has an event log",
+                path.display()
+            )));
+        }
+        let res = Self::boot_pill(path, pill, ident, conf, lock);
+        if res.is_err() {
+            let _ = fs::remove_dir_all(Log::path(path));
+        }
+        res
+    }
+
+    /// Run a pill into a new pier's log.
+    fn boot_pill(
+        path: &Path,
+        pill: &Path,
+        ident: &Identity,
+        conf: &Config,
+        lock: Lock,
+    ) -> Result<Self, Error> {
+        conf.save(path)?;
+        let mut log = Log::open(path)?;
+        ident.save(&mut log)?;
+        let (kernel, ova) = Kernel::new(pill, &mut log)?;
+        let mut pier = Self {
+            path: path.to_path_buf(),
+            evt_num: log.last(),
+            log,
+            kernel,
+            _lock: lock,
+        };
+        for ovum in ova {
+            pier.poke(ovum)?;
+        }
+        pier.snapshot()?;
+        Ok(pier)
+    }
+
+    /// Boot a pier by replaying its event log from its latest snapshot, reporting progress.
+    /// If the pier's log is organized into epochs and its current epoch was created by a
+    /// different runtime version, a new epoch is started.
+    pub fn boot(path: &Path, progress: &mut dyn FnMut(&Progress)) -> Result<Self, Error> {
+        let lock = Lock::acquire(path)?;
+        let snap = replay(path, None, progress)?;
+        let mut log = Log::open(path)?;
+        if log.last() != snap.evt_num {
+            return Err(Error::BadEventLog(format!(
+                "replay stopped at event {} of {}",
+                snap.evt_num,
+                log.last()
+            )));
+        }
+        if !Epoch::list(path)?.is_empty() && Epoch::upgrade(path, &snap)?.is_some() {
+            log = Log::open(path)?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            log,
+            kernel: snap.kernel,
+            evt_num: snap.evt_num,
+            _lock: lock,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn kernel(&self) -> &Kernel {
+        &self.kernel
+    }
+
+    /// Get the number of the most recent event.
+    pub fn evt_num(&self) -> u64 {
+        self.evt_num
+    }
+
+    /// Apply an ovum and append it to the log, producing the kernel's effects. An ovum that
+    /// crashes the kernel is neither applied nor logged.
+    pub fn poke(&mut self, ovum: Noun) -> Result<Noun, Error> {
+        let date = time::now();
+        let (effects, kernel) = self
+            .kernel
+            .poke(date.clone(), ovum.clone())
+            .at_event(self.evt_num + 1)?;
+        self.log.append(Event {
+            num: self.evt_num + 1,
+            mug: kernel.mug(),
+            date,
+            ovum,
+        })?;
+        self.kernel = kernel;
+        self.evt_num += 1;
+        Ok(effects)
+    }
+
+    /// Read a path in the kernel's namespace.
+    pub fn peek(&self, path: Noun) -> Result<Option<Option<Noun>>, Error> {
+        self.kernel.peek(time::now(), path)
+    }
+
+    /// Make every event durable and save a snapshot of the kernel as of the most recent event.
+    pub fn snapshot(&mut self) -> Result<(), Error> {
+        self.log.commit()?;
+        Snapshot {
+            evt_num: self.evt_num,
+            kernel: self.kernel.clone(),
+        }
+        .save(&self.path)
+    }
+
+    /// Make every event durable and start a new epoch of the log from a snapshot of the kernel
+    /// as of the most recent event, producing the new epoch.
+    pub fn rollover(&mut self) -> Result<Epoch, Error> {
+        self.log.commit()?;
+        let snap = Snapshot {
+            evt_num: self.evt_num,
+            kernel: self.kernel.clone(),
+        };
+        let epoch = Epoch::rollover(&self.path, &snap)?;
+        self.log = epoch.log()?;
+        Ok(epoch)
+    }
+}
+
+/// What truncating a pier's event log removes.
+#[derive(Debug, PartialEq)]
+pub struct Truncation {
+    /// Number of the most recent event before truncation.
+    pub last: u64,
+    /// Number of the most recent event after truncation.
+    pub evt_num: u64,
+    /// Event number of the snapshot invalidated by truncation, if any.
+    pub snapshot: Option<u64>,
+}
+
+/// Roll a pier back to event `evt_num` by removing every later event from its log and deleting
+/// its snapshot if the snapshot is of a later event. With `dry_run`, only report what would be
+/// removed.
+///
+/// The truncation is recorded in the log's `truncated` metadata as the jam of
+/// `[date last evt-num]`. Fails if the pier is live, before its log is opened.
+pub fn truncate(pier: &Path, evt_num: u64, dry_run: bool) -> Result<Truncation, Error> {
+    let _lock = Lock::acquire(pier)?;
+    let mut log = Log::open(pier)?;
+    if evt_num > log.last() || evt_num + 1 < log.first() {
+        return Err(Error::BadEventLog(format!(
+            "can't truncate to event {} of {}..={}",
+            evt_num,
+            log.first(),
+            log.last()
+        )));
+    }
+    let trunc = Truncation {
+        last: log.last(),
+        evt_num,
+        snapshot: Snapshot::header(pier)?
+            .map(|(snap, _)| snap)
+            .filter(|snap| *snap > evt_num),
+    };
+    if dry_run || trunc.last == evt_num {
+        return Ok(trunc);
+    }
+    if trunc.snapshot.is_some() {
+        fs::remove_file(Snapshot::path(pier))?;
+    }
+    log.truncate(evt_num)?;
+    let record = Noun::from_tuple(vec![
+        Noun::from(time::now()),
+        Noun::from(trunc.last),
+        Noun::from(evt_num),
+    ]);
+    log.set_meta("truncated", &record.jam())?;
+    Ok(trunc)
+}
+
+/// What chopping a pier's event log removes.
+#[derive(Debug, PartialEq)]
+pub struct Chop {
+    /// Number of the first event before chopping, or 0 if the log was empty.
+    pub first: u64,
+    /// Number of the pier's snapshot, which is the last event removed.
+    pub evt_num: u64,
+}
+
+impl Chop {
+    /// Check whether there are any events to remove.
+    pub fn is_empty(&self) -> bool {
+        0 == self.first || self.first > self.evt_num
+    }
+}
+
+/// Delete the epochs of a pier's event log that precede its latest epoch with a snapshot,
+/// producing the deleted epochs. Fails if the pier is live.
+pub fn prune(pier: &Path) -> Result<Vec<Epoch>, Error> {
+    let _lock = Lock::acquire(pier)?;
+    Epoch::prune(pier)
+}
+
+/// Remove the events a pier's snapshot covers from its event log, after which the log starts
+/// with the event after the snapshot and can only be replayed from that snapshot. With
+/// `dry_run`, only report what would be removed.
+///
+/// The snapshot is checked against the mug recorded for its event before anything is removed.
+/// If the pier's log is organized into epochs, earlier epochs are deleted and the epoch holding
+/// the snapshot's event starts from the snapshot. Fails if the pier is live.
+pub fn chop(pier: &Path, dry_run: bool) -> Result<Chop, Error> {
+    let _lock = Lock::acquire(pier)?;
+    let snap = Snapshot::latest(pier)?
+        .ok_or_else(|| Error::BadSnapshot("no snapshot to chop to".to_string()))?;
+    let epochs = Epoch::list(pier)?;
+    let chop = Chop {
+        first: match epochs.first() {
+            Some(epoch) => epoch.first,
+            None => Log::open(pier)?.first(),
+        },
+        evt_num: snap.evt_num,
+    };
+    if chop.is_empty() {
+        return Ok(chop);
+    }
+    let mut log = match epochs.iter().rfind(|epoch| epoch.first <= snap.evt_num) {
+        Some(epoch) => epoch.log()?,
+        None => Log::open(pier)?,
+    };
+    match log.read(snap.evt_num, 1)?.pop() {
+        Some(evt) if 0 == evt.mug || snap.kernel.mug() == evt.mug => {}
+        Some(evt) => {
+            return Err(Error::SnapshotMismatch {
+                evt_num: evt.num,
+                expected: evt.mug,
+                found: snap.kernel.mug(),
+            })
+        }
+        None => {
+            return Err(Error::BadSnapshot(format!(
+                "event {} isn't in the log",
+                snap.evt_num
+            )))
+        }
+    }
+    if dry_run {
+        return Ok(chop);
+    }
+    if epochs.is_empty() {
+        log.chop(snap.evt_num)?;
+    } else {
+        drop(log);
+        Epoch::chop(pier, &snap)?;
+    }
+    Ok(chop)
+}
+
+/// Replay a pier up to event `to`, or its last event, from the most recent snapshot at or before
+/// `to`: the pier's snapshot, the snapshot an epoch starts from, or the lifecycle events of the
+/// pier's first epoch. Replay continues across epochs.
+pub fn replay(
+    pier: &Path,
+    to: Option<u64>,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<Snapshot, Error> {
+    let to = to.unwrap_or(u64::MAX);
+    let pier_snap = Snapshot::header(pier)?
+        .map(|(evt_num, _)| evt_num)
+        .filter(|evt_num| *evt_num <= to);
+    let epochs = Epoch::list(pier)?;
+    if epochs.is_empty() && !Log::is_flat(pier) {
+        return Err(Error::BadEventLog(format!(
+            "{} has no event log",
+            pier.display()
+        )));
+    } else if epochs.is_empty() {
+        let snap = match pier_snap {
+            Some(_) => Some(Snapshot::load(pier)?),
+            None => None,
+        };
+        return Log::open(pier)?.replay(snap, Some(to), progress);
+    }
+    let start = epochs
+        .iter()
+        .rposition(|epoch| epoch.first - 1 <= to)
+        .ok_or_else(|| {
+            Error::BadEventLog(format!(
+                "can't replay to event {} when history starts at event {}",
+                to, epochs[0].first
+            ))
+        })?;
+    let mut snap = match pier_snap {
+        Some(evt_num) if evt_num + 1 >= epochs[start].first => Some(Snapshot::load(pier)?),
+        _ => epochs[start].snapshot()?,
+    };
+    for epoch in &epochs[start..] {
+        let log = epoch.log()?;
+        let evt_num = snap.as_ref().map_or(0, |snap| snap.evt_num);
+        if log.last() <= evt_num || evt_num >= to {
+            continue;
+        }
+        if snap.is_some() && epoch.first > evt_num + 1 {
+            return Err(Error::BadEventLog(format!(
+                "epoch starting at event {} doesn't follow event {}",
+                epoch.first, evt_num
+            )));
+        }
+        snap = Some(log.replay(snap, Some(to), progress)?);
+    }
+    snap.ok_or_else(|| Error::BadEventLog("no events to replay".to_string()))
+}
+
+/// Open each of a pier's event logs in order: the log of each of its epochs, or its only log if
+/// it predates epochs.
+fn logs(pier: &Path) -> Result<Vec<Log>, Error> {
+    let epochs = Epoch::list(pier)?;
+    if epochs.is_empty() {
+        Ok(vec![Log::open(pier)?])
+    } else {
+        epochs.iter().map(Epoch::log).collect()
+    }
+}
+
+/// Find the mug of a pier's kernel as of event `evt_num`: the mug recorded for the event, or
+/// the mug of a snapshot of the event.
+fn kernel_mug(pier: &Path, logs: &[Log], evt_num: u64) -> Result<u32, Error> {
+    for log in logs {
+        let evt = log.read(evt_num, 1)?.pop();
+        if let Some(evt) = evt.filter(|evt| evt.num == evt_num && 0 != evt.mug) {
+            return Ok(evt.mug);
+        }
+    }
+    let epoch = Epoch::list(pier)?
+        .into_iter()
+        .find(|epoch| epoch.first == evt_num + 1);
+    if let Some(mug) = epoch.and_then(|epoch| epoch.kernel) {
+        return Ok(mug);
+    }
+    match Snapshot::header(pier)? {
+        Some((snap, mug)) if snap == evt_num => Ok(mug),
+        _ => Err(Error::BadEventLog(format!(
+            "no recorded kernel mug for event {}",
+            evt_num
+        ))),
+    }
+}
+
+/// Export events `first..=last` of a pier, by default every event it has, to `out`.
+///
+/// The export's header records the pier's identity and the mug of the kernel as of the event
+/// before the first exported event, which must be known unless the export starts at event 1.
+pub fn export<W: Write>(
+    pier: &Path,
+    first: Option<u64>,
+    last: Option<u64>,
+    out: W,
+) -> Result<Header, Error> {
+    let ident = Identity::load(&Log::open(pier)?)?
+        .ok_or_else(|| Error::BadEventLog("pier has no identity".to_string()))?;
+    let logs = logs(pier)?;
+    let start = logs
+        .iter()
+        .map(EvtLog::first)
+        .find(|first| 0 != *first)
+        .unwrap_or(1);
+    let end = logs.last().map_or(0, EvtLog::last);
+    let (first, last) = (first.unwrap_or(start), last.unwrap_or(end));
+    if first < start || last > end || first > last {
+        return Err(Error::BadEventLog(format!(
+            "can't export events {}..={} of {}..={}",
+            first, last, start, end
+        )));
+    }
+    let header = Header {
+        who: ident.who,
+        fake: ident.fake,
+        first,
+        last,
+        kernel: if 1 == first {
+            0
+        } else {
+            kernel_mug(pier, &logs, first - 1)?
+        },
+        vere: VERE_VERSION.to_string(),
+    };
+    let mut writer = Writer::new(out, header.clone())?;
+    for log in &logs {
+        writer.write_log(log)?;
+    }
+    writer.finish()?;
+    Ok(header)
+}
+
+/// Create a pier's event log from an export, taking on the export's identity. The pier must not
+/// already have an event log.
+///
+/// An export that doesn't start at event 1 needs `snap`, which must be of the event before the
+/// export's first event and match the kernel mug in the export's header. The new log starts an
+/// epoch from it. If the import fails, the partial log is removed.
+pub fn import<R: Read>(pier: &Path, inp: R, snap: Option<Snapshot>) -> Result<Header, Error> {
+    let _lock = Lock::acquire(pier)?;
+    if Log::path(pier).exists() {
+        return Err(Error::BadEventLog(format!(
+            "{} already has an event log",
+            pier.display()
+        )));
+    }
+    let reader = Reader::new(inp)?;
+    let header = reader.header().clone();
+    let snap = match snap {
+        None if 1 == header.first => None,
+        Some(snap) if snap.evt_num + 1 == header.first && snap.kernel.mug() == header.kernel => {
+            Some(snap)
+        }
+        Some(snap) => {
+            return Err(Error::BadSnapshot(format!(
+                "snapshot of event {} with mug {:#x} doesn't precede events {}..={} with kernel \
+                 mug {:#x}",
+                snap.evt_num,
+                snap.kernel.mug(),
+                header.first,
+                header.last,
+                header.kernel
+            )))
+        }
+        None => {
+            return Err(Error::BadSnapshot(format!(
+                "importing events {}..={} needs a snapshot of event {}",
+                header.first,
+                header.last,
+                header.first - 1
+            )))
+        }
+    };
+    let res = Epoch::create(pier, snap.as_ref()).and_then(|epoch| append(&epoch, &header, reader));
+    if let Err(err) = res {
+        let _ = fs::remove_dir_all(Log::path(pier));
+        return Err(err);
+    }
+    Ok(header)
+}
+
+/// Append an export's events to a new epoch, recording the export's identity.
+fn append<R: Read>(epoch: &Epoch, header: &Header, reader: Reader<R>) -> Result<(), Error> {
+    let mut log = epoch.log()?;
+    Identity {
+        who: header.who.clone(),
+        fake: header.fake,
+    }
+    .save(&mut log)?;
+    for evt in reader {
+        log.append(evt?)?;
+    }
+    log.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event_log::file::FileLog,
+        test_util::{arvo, poke, toy_pill},
+    };
+    use nock::serdes::Cue;
+    use std::env;
+
+    #[test]
+    fn lock() {
+        let pier = env::temp_dir().join(format!("vere-pier-lock-{}", process::id()));
+
+        // A pier can't be locked twice.
+        {
+            let lock = Lock::acquire(&pier).unwrap();
+            assert_eq!(Some(process::id()), Lock::holder(&pier).unwrap());
+            assert!(matches!(Lock::acquire(&pier), Err(Error::PierLive(_))));
+            drop(lock);
+            assert_eq!(None, Lock::holder(&pier).unwrap());
+        }
+
+        // A stale lock is taken over, even if its process ID has been reused.
+        {
+            fs::write(Lock::path(&pier), "not a pid").unwrap();
+            assert!(Lock::acquire(&pier).is_ok());
+            fs::write(Lock::path(&pier), process::id().to_string()).unwrap();
+            assert_eq!(None, Lock::holder(&pier).unwrap());
+            assert!(Lock::acquire(&pier).is_ok());
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn identity() {
+        let pier = env::temp_dir().join(format!("vere-pier-identity-{}", process::id()));
+        let mut log = FileLog::new(&Log::path(&pier)).unwrap();
+        assert_eq!(None, Identity::load(&log).unwrap());
+
+        // Fake is a loobean of minimal bytes, as the C runtime writes it.
+        log.set_meta(WHO, &[0, 1]).unwrap();
+        let cases: [(&[u8], bool, &[u8]); 3] =
+            [(&[], true, &[]), (&[0], true, &[]), (&[1], false, &[1])];
+        for (bytes, fake, saved) in cases {
+            log.set_meta(FAKE, bytes).unwrap();
+            let ident = Identity::load(&log).unwrap().unwrap();
+            assert_eq!(
+                (Atom::from(0x100u64), fake),
+                (ident.who.clone(), ident.fake)
+            );
+            ident.save(&mut log).unwrap();
+            assert_eq!(Some(saved.to_vec()), log.meta(FAKE).unwrap());
+        }
+        log.set_meta(FAKE, &[2]).unwrap();
+        assert!(matches!(Identity::load(&log), Err(Error::BadEventLog(_))));
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn truncate() {
+        let pier = env::temp_dir().join(format!("vere-pier-truncate-{}", process::id()));
+        let mut log = FileLog::new(&Log::path(&pier)).unwrap();
+        for num in 1..=5 {
+            log.append(Event {
+                num,
+                mug: 0,
+                date: time::now(),
+                ovum: Noun::from(num),
+            })
+            .unwrap();
+        }
+        log.commit().unwrap();
+        Snapshot {
+            evt_num: 4,
+            kernel: Kernel::try_from(Noun::from((Noun::from(0), Noun::from(1)))).unwrap(),
+        }
+        .save(&pier)
+        .unwrap();
+
+        // A dry run changes nothing.
+        {
+            let trunc = super::truncate(&pier, 3, true).unwrap();
+            assert_eq!(
+                Truncation {
+                    last: 5,
+                    evt_num: 3,
+                    snapshot: Some(4)
+                },
+                trunc
+            );
+            assert_eq!(5, log.last());
+            assert!(Snapshot::path(&pier).exists());
+        }
+
+        // A pier can't be truncated while it's live.
+        {
+            let lock = Lock::acquire(&pier).unwrap();
+            assert!(matches!(
+                super::truncate(&pier, 3, false),
+                Err(Error::PierLive(_))
+            ));
+            drop(lock);
+        }
+
+        // Truncation removes later events and the snapshot, and is recorded.
+        {
+            super::truncate(&pier, 3, false).unwrap();
+            let log = Log::open(&pier).unwrap();
+            assert_eq!(3, log.last());
+            assert!(!Snapshot::path(&pier).exists());
+            let record = Noun::cue(&log.meta("truncated").unwrap().unwrap()).unwrap();
+            assert_eq!(
+                Ok(vec![Noun::from(5), Noun::from(3)]),
+                record.into_tuple(3).map(|fields| fields[1..].to_vec())
+            );
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn chop() {
+        let pier = env::temp_dir().join(format!("vere-pier-chop-{}", process::id()));
+        let path = toy_pill(&pier);
+        let mut log = FileLog::new(&Log::path(&pier)).unwrap();
+        let (kernel, _) = Kernel::new(&path, &mut log).unwrap();
+        let third = poke(&mut log, kernel, 3..=3);
+        let fourth = poke(&mut log, third.clone(), 4..=5);
+        drop(log);
+
+        // A snapshot that doesn't match its event's recorded mug isn't trusted.
+        {
+            Snapshot {
+                evt_num: 4,
+                kernel: third,
+            }
+            .save(&pier)
+            .unwrap();
+            assert!(matches!(
+                super::chop(&pier, false),
+                Err(Error::SnapshotMismatch { evt_num: 4, .. })
+            ));
+            fs::remove_file(Snapshot::path(&pier)).unwrap();
+        }
+
+        // A dry run changes nothing.
+        {
+            let snap = super::replay(&pier, Some(4), &mut |_| {}).unwrap();
+            snap.save(&pier).unwrap();
+            let chop = super::chop(&pier, true).unwrap();
+            assert_eq!(
+                Chop {
+                    first: 1,
+                    evt_num: 4
+                },
+                chop
+            );
+            assert_eq!(1, Log::open(&pier).unwrap().first());
+        }
+
+        // Chopping removes the events the snapshot covers, after which replay needs it.
+        {
+            super::chop(&pier, false).unwrap();
+            let log = Log::open(&pier).unwrap();
+            assert_eq!((5, 5), (log.first(), log.last()));
+            assert!(super::chop(&pier, false).unwrap().is_empty());
+            let snap = super::replay(&pier, None, &mut |_| {}).unwrap();
+            assert_eq!(5, snap.evt_num);
+            assert_eq!(Noun::from(fourth), Noun::from(snap.kernel));
+            fs::remove_file(Snapshot::path(&pier)).unwrap();
+            assert!(matches!(
+                super::replay(&pier, None, &mut |_| {}),
+                Err(Error::BadEventLog(_))
+            ));
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn chop_epochs() {
+        let pier = env::temp_dir().join(format!("vere-pier-chop-epochs-{}", process::id()));
+        let path = toy_pill(&pier);
+        let (kernel, _) =
+            Kernel::new(&path, &mut Epoch::current(&pier).unwrap().log().unwrap()).unwrap();
+        let kernel = poke(&mut Log::open(&pier).unwrap(), kernel, 3..=4);
+        Epoch::rollover(
+            &pier,
+            &Snapshot {
+                evt_num: 4,
+                kernel: kernel.clone(),
+            },
+        )
+        .unwrap();
+        let kernel = poke(&mut Log::open(&pier).unwrap(), kernel, 5..=6);
+        super::replay(&pier, Some(5), &mut |_| {})
+            .unwrap()
+            .save(&pier)
+            .unwrap();
+
+        // Earlier epochs are deleted and the epoch holding the snapshot's event starts from it.
+        super::chop(&pier, false).unwrap();
+        let epochs = Epoch::list(&pier).unwrap();
+        assert_eq!(1, epochs.len());
+        assert_eq!(6, epochs[0].first);
+        assert!(epochs[0].dir.ends_with("0i5"));
+        assert_eq!(
+            Some(5),
+            Snapshot::read_header(&epochs[0].snapshot_path())
+                .unwrap()
+                .map(|(evt_num, _)| evt_num)
+        );
+        fs::remove_file(Snapshot::path(&pier)).unwrap();
+        let snap = super::replay(&pier, None, &mut |_| {}).unwrap();
+        assert_eq!(6, snap.evt_num);
+        assert_eq!(Noun::from(kernel), Noun::from(snap.kernel));
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+
+    #[test]
+    fn export_import() {
+        let dir = env::temp_dir().join(format!("vere-pier-export-{}", process::id()));
+        let (from, to) = (dir.join("from"), dir.join("to"));
+        let path = toy_pill(&dir);
+        let mut log = Log::open(&from).unwrap();
+        let (kernel, _) = Kernel::new(&path, &mut log).unwrap();
+        let ident = Identity {
+            who: Atom::from(0x100u64),
+            fake: true,
+        };
+        assert!(super::export(&from, None, None, Vec::new()).is_err());
+        ident.save(&mut log).unwrap();
+        let kernel = poke(&mut log, kernel, 3..=5);
+        drop(log);
+
+        // Export everything and import it into a new pier, which replays to the same kernel.
+        {
+            let mut out = Vec::new();
+            let header = super::export(&from, None, None, &mut out).unwrap();
+            assert_eq!((1, 5, 0), (header.first, header.last, header.kernel));
+            super::import(&to, &out[..], None).unwrap();
+            assert_eq!(
+                Some(ident.clone()),
+                Identity::load(&Log::open(&to).unwrap()).unwrap()
+            );
+            assert!(super::import(&to, &out[..], None).is_err());
+            let snap = super::replay(&to, None, &mut |_| {}).unwrap();
+            assert_eq!(Noun::from(kernel.clone()), Noun::from(snap.kernel));
+            fs::remove_dir_all(&to).unwrap();
+        }
+
+        // Export a range, whose import needs a snapshot of the kernel it starts from.
+        {
+            let mut out = Vec::new();
+            assert!(super::export(&from, Some(4), Some(6), &mut out).is_err());
+            let header = super::export(&from, Some(4), None, &mut out).unwrap();
+            let snap = super::replay(&from, Some(3), &mut |_| {}).unwrap();
+            assert_eq!(snap.kernel.mug(), header.kernel);
+            assert!(matches!(
+                super::import(&to, &out[..], None),
+                Err(Error::BadSnapshot(_))
+            ));
+            assert!(!Log::path(&to).exists());
+            super::import(&to, &out[..], Some(snap)).unwrap();
+            let snap = super::replay(&to, None, &mut |_| {}).unwrap();
+            assert_eq!(5, snap.evt_num);
+            assert_eq!(Noun::from(kernel), Noun::from(snap.kernel));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn create_boot() {
+        let dir = env::temp_dir().join(format!("vere-pier-boot-{}", process::id()));
+        let path = toy_pill(&dir);
+        let pier = dir.join("pier");
+        let ident = Identity {
+            who: Atom::from(0u64),
+            fake: true,
+        };
+        let conf = Config {
+            high_water: 80,
+            ..Config::default()
+        };
+
+        // Create a pier, which runs the whole pill and snapshots the result.
+        {
+            let mut live = Pier::create(&pier, &path, &ident, &conf).unwrap();
+            assert_eq!(4, live.evt_num());
+            assert_eq!(
+                Some((4, live.kernel().mug())),
+                Snapshot::header(&pier).unwrap()
+            );
+            assert!(matches!(
+                Pier::boot(&pier, &mut |_| {}),
+                Err(Error::PierLive(_))
+            ));
+            live.poke(Noun::from(9)).unwrap();
+            assert_eq!(Some(Some(Noun::from(9))), live.peek(Noun::from(0)).unwrap());
+        }
+        assert!(Pier::create(&pier, &path, &ident, &Config::default()).is_err());
+        assert_eq!(conf, Config::load(&pier).unwrap());
+
+        // Boot it again from its snapshot and log.
+        {
+            let live = Pier::boot(&pier, &mut |_| {}).unwrap();
+            assert_eq!(5, live.evt_num());
+            assert_eq!(arvo(Noun::from(9)), Noun::from(live.kernel().clone()));
+            assert_eq!(
+                Some(ident.clone()),
+                Identity::load(&Log::open(&pier).unwrap()).unwrap()
+            );
+        }
+
+        // Roll it over to a new epoch and prune the epoch before it.
+        {
+            let mut live = Pier::boot(&pier, &mut |_| {}).unwrap();
+            assert!(matches!(super::prune(&pier), Err(Error::PierLive(_))));
+            assert_eq!(6, live.rollover().unwrap().first);
+            live.poke(Noun::from(10)).unwrap();
+            live.snapshot().unwrap();
+        }
+        {
+            let pruned = super::prune(&pier).unwrap();
+            assert_eq!(vec![1], pruned.iter().map(|e| e.first).collect::<Vec<_>>());
+            let live = Pier::boot(&pier, &mut |_| {}).unwrap();
+            assert_eq!(6, live.evt_num());
+            assert_eq!(
+                Some(ident),
+                Identity::load(&Log::open(&pier).unwrap()).unwrap()
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
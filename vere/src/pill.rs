@@ -0,0 +1,159 @@
+use crate::error::{Context, Error};
+use nock::{atom::Atom, noun::Noun, serdes::Cue};
+use std::{fs, path::Path};
+
+/// A boot sequence, distributed as a jammed `[%pill name boot-ova kernel-ova userspace-ova]`.
+#[derive(Debug, PartialEq)]
+pub struct Pill {
+    /// Name of the pill, e.g. %solid or %brass.
+    pub name: Atom,
+    /// Lifecycle events, which compute the initial kernel.
+    pub boot: Vec<Noun>,
+    /// Events that install the kernel's vanes.
+    pub kernel: Vec<Noun>,
+    /// Events that install userspace.
+    pub userspace: Vec<Noun>,
+}
+
+impl Pill {
+    /// Load a pill from a local file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let bytes = fs::read(path).at_path(path)?;
+        let noun = Noun::cue(&bytes).at_path(path)?;
+        Self::try_from(noun).at_path(path)
+    }
+
+    /// Split a pill into its lifecycle events and the events that follow them.
+    pub fn events(self) -> (Vec<Noun>, Vec<Noun>) {
+        let mut ova = self.kernel;
+        ova.extend(self.userspace);
+        (self.boot, ova)
+    }
+}
+
+/// Pill from Noun.
+impl TryFrom<Noun> for Pill {
+    type Error = Error;
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let bad = |msg: &str| Error::BadPill(msg.to_string());
+        let mut fields = noun
+            .into_tuple(5)
+            .map_err(|_| bad("expected [%pill name boot kernel userspace]"))?
+            .into_iter();
+        let mut next = || fields.next().unwrap();
+        if Noun::Atom(Atom::from("pill")) != next() {
+            return Err(bad("missing %pill tag"));
+        }
+        let name = match next() {
+            Noun::Atom(name) => name,
+            Noun::Cell(_) => return Err(bad("name is a cell")),
+        };
+        let boot = next()
+            .into_list()
+            .map_err(|_| bad("lifecycle events aren't a list"))?;
+        if boot.is_empty() {
+            return Err(bad("no lifecycle events"));
+        }
+        let kernel = next()
+            .into_list()
+            .map_err(|_| bad("kernel events aren't a list"))?;
+        let userspace = next()
+            .into_list()
+            .map_err(|_| bad("userspace events aren't a list"))?;
+        Ok(Self {
+            name,
+            boot,
+            kernel,
+            userspace,
+        })
+    }
+}
+
+/// Noun from Pill.
+impl From<Pill> for Noun {
+    fn from(pill: Pill) -> Self {
+        Noun::from_tuple(vec![
+            Noun::from(Atom::from("pill")),
+            Noun::from(pill.name),
+            Noun::from_list(pill.boot),
+            Noun::from_list(pill.kernel),
+            Noun::from_list(pill.userspace),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nock::serdes::Jam;
+    use std::{env, process};
+
+    fn pill() -> Pill {
+        Pill {
+            name: Atom::from("test"),
+            boot: vec![Noun::from((Noun::from(0), Noun::from(3))), Noun::from(42)],
+            kernel: vec![Noun::from((Noun::from(1), Noun::from(2)))],
+            userspace: vec![],
+        }
+    }
+
+    #[test]
+    fn load() {
+        let path = env::temp_dir().join(format!("vere-pill-{}.pill", process::id()));
+
+        // Load a well-formed pill.
+        {
+            fs::write(&path, Noun::from(pill()).jam()).unwrap();
+            let loaded = Pill::load(&path).unwrap();
+            assert_eq!(pill(), loaded);
+            let (boot, ova) = loaded.events();
+            assert_eq!(2, boot.len());
+            assert_eq!(vec![Noun::from((Noun::from(1), Noun::from(2)))], ova);
+        }
+
+        // Reject a file that isn't a jam.
+        {
+            fs::write(&path, [3]).unwrap();
+            assert!(matches!(
+                Pill::load(&path).err().unwrap().root(),
+                Error::Cue(_)
+            ));
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn malformed() {
+        // An atom.
+        {
+            assert!(matches!(
+                Pill::try_from(Noun::from(7)),
+                Err(Error::BadPill(_))
+            ));
+        }
+
+        // The wrong tag.
+        {
+            let noun = Noun::from(pill());
+            let noun = match noun {
+                Noun::Cell(c) => Noun::from((Noun::from(Atom::from("ivory")), *c.tail)),
+                Noun::Atom(_) => unreachable!(),
+            };
+            assert!(matches!(Pill::try_from(noun), Err(Error::BadPill(_))));
+        }
+
+        // Userspace events that aren't a list.
+        {
+            let noun = Noun::from_tuple(vec![
+                Noun::from(Atom::from("pill")),
+                Noun::from(Atom::from("test")),
+                Noun::from_list(vec![Noun::from(1)]),
+                Noun::from(0),
+                Noun::from(5),
+            ]);
+            assert!(matches!(Pill::try_from(noun), Err(Error::BadPill(_))));
+        }
+    }
+}
@@ -0,0 +1,418 @@
+//! The scry socket, which lets local tools read the ship's namespace over a Unix socket in the
+//! pier, `.urb/scry.sock`.
+//!
+//! A read is one line, `<format> /<care>/<ship>/<desk>/<case>/<path>`, e.g.
+//! `json /gx/~zod/base/1/sys/kelvin`, whose segments are passed to the kernel as knots. The
+//! format is `jam`, for the jam of the result, or `json`, for the result converted to JSON by
+//! its mark, which works for the `json`, `txt`, `hoon`, `ud`, `loob` and `noun` marks. Each
+//! read is answered in turn with one of:
+//!
+//! - `ok <len>`, then a newline and `<len>` bytes of the result;
+//! - `blocked`, if the path is blocked;
+//! - `none`, if the path is unavailable;
+//! - `error <message>`, if the read is malformed or fails.
+
+use crate::{
+    driver::{Drivers, Injector},
+    error::{Context, Error},
+    event_log::Log,
+    noun::text,
+};
+use nock::{atom::Atom, noun::Noun, serdes::Jam};
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    thread,
+};
+
+/// How a result is sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Jam,
+    Json,
+}
+
+/// Parse a read, producing its format and the path to peek.
+fn parse(line: &str) -> Result<(Format, Noun), String> {
+    let (format, path) = line
+        .trim()
+        .split_once(' ')
+        .ok_or_else(|| "expected <format> <path>".to_string())?;
+    let format = match format {
+        "jam" => Format::Jam,
+        "json" => Format::Json,
+        _ => return Err(format!("unknown format {}", format)),
+    };
+    let segments: Vec<_> = match path.trim().strip_prefix('/') {
+        Some(path) => path.split('/').collect(),
+        None => return Err(format!("path {} doesn't start with /", path)),
+    };
+    if segments.len() < 4 || segments.iter().any(|segment| segment.is_empty()) {
+        return Err("expected /<care>/<ship>/<desk>/<case>/<path>".to_string());
+    }
+    let path = segments
+        .into_iter()
+        .map(|segment| Noun::from(Atom::from(segment)))
+        .collect();
+    Ok((format, Noun::from_list(path)))
+}
+
+/// Write a string as a JSON string.
+fn quote(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Write an atom in decimal.
+fn decimal(out: &mut String, atom: &Atom) {
+    const BASE: u128 = 10_000_000_000_000_000_000;
+    let mut limbs = atom.limbs().to_vec();
+    let mut digits = Vec::new();
+    while limbs.iter().any(|limb| 0 != *limb) {
+        let mut rem = 0u128;
+        for limb in limbs.iter_mut().rev() {
+            let cur = rem << 64 | u128::from(*limb);
+            *limb = (cur / BASE) as u64;
+            rem = cur % BASE;
+        }
+        digits.push(rem as u64);
+    }
+    match digits.split_last() {
+        Some((top, rest)) => {
+            let _ = write!(out, "{}", top);
+            for digits in rest.iter().rev() {
+                let _ = write!(out, "{:019}", digits);
+            }
+        }
+        None => out.push('0'),
+    }
+}
+
+/// Write a `json` noun as JSON.
+fn json(out: &mut String, noun: &Noun) -> Result<(), String> {
+    let bad = || "malformed json".to_string();
+    let (tag, val) = match noun {
+        Noun::Atom(Atom::Direct(0)) => {
+            out.push_str("null");
+            return Ok(());
+        }
+        Noun::Cell(cell) => (text(&cell.head).ok_or_else(bad)?, cell.tail.as_ref()),
+        Noun::Atom(_) => return Err(bad()),
+    };
+    match (tag.as_str(), val) {
+        ("a", items) => {
+            out.push('[');
+            for (i, item) in items
+                .clone()
+                .into_list()
+                .map_err(|_| bad())?
+                .iter()
+                .enumerate()
+            {
+                if i > 0 {
+                    out.push(',');
+                }
+                json(out, item)?;
+            }
+            out.push(']');
+        }
+        ("b", flag) => match flag {
+            Noun::Atom(Atom::Direct(0)) => out.push_str("true"),
+            Noun::Atom(Atom::Direct(1)) => out.push_str("false"),
+            _ => return Err(bad()),
+        },
+        ("n", num) => out.push_str(&text(num).ok_or_else(bad)?),
+        ("s", string) => quote(out, &text(string).ok_or_else(bad)?),
+        ("o", map) => {
+            out.push('{');
+            let mut first = true;
+            let mut nodes = vec![map.clone()];
+            while let Some(node) = nodes.pop() {
+                if Noun::from(0) == node {
+                    continue;
+                }
+                let mut node = node.into_tuple(3).map_err(|_| bad())?.into_iter();
+                let (key, val) = match node.next().unwrap() {
+                    Noun::Cell(pair) => (text(&pair.head).ok_or_else(bad)?, *pair.tail),
+                    Noun::Atom(_) => return Err(bad()),
+                };
+                if !first {
+                    out.push(',');
+                }
+                first = false;
+                quote(out, &key);
+                out.push(':');
+                json(out, &val)?;
+                let (left, right) = (node.next().unwrap(), node.next().unwrap());
+                nodes.extend([right, left]);
+            }
+            out.push('}');
+        }
+        _ => return Err(bad()),
+    }
+    Ok(())
+}
+
+/// Write a noun as JSON, atoms as numbers and cells as two-element arrays.
+fn noun(out: &mut String, noun: &Noun) {
+    match noun {
+        Noun::Atom(atom) => decimal(out, atom),
+        Noun::Cell(cell) => {
+            out.push('[');
+            self::noun(out, &cell.head);
+            out.push(',');
+            self::noun(out, &cell.tail);
+            out.push(']');
+        }
+    }
+}
+
+/// Convert a peek's result to JSON by its mark. The result must be a cage, `[mark vase]`.
+pub fn to_json(res: &Noun) -> Result<String, String> {
+    let (mark, val) = match res {
+        Noun::Cell(cage) => match (text(&cage.head), cage.tail.as_ref()) {
+            (Some(mark), Noun::Cell(vase)) => (mark, vase.tail.as_ref()),
+            _ => return Err("the result isn't a cage".to_string()),
+        },
+        Noun::Atom(_) => return Err("the result isn't a cage".to_string()),
+    };
+    let mut out = String::new();
+    match mark.as_str() {
+        "json" => json(&mut out, val)?,
+        "txt" => {
+            let lines = val
+                .clone()
+                .into_list()
+                .map_err(|_| "malformed txt".to_string())?;
+            out.push('[');
+            for (i, line) in lines.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                quote(&mut out, &text(line).ok_or("malformed txt")?);
+            }
+            out.push(']');
+        }
+        "hoon" => quote(&mut out, &text(val).ok_or("malformed hoon")?),
+        "ud" => match val {
+            Noun::Atom(atom) => decimal(&mut out, atom),
+            Noun::Cell(_) => return Err("malformed ud".to_string()),
+        },
+        "loob" => match val {
+            Noun::Atom(Atom::Direct(0)) => out.push_str("true"),
+            Noun::Atom(Atom::Direct(1)) => out.push_str("false"),
+            _ => return Err("malformed loob".to_string()),
+        },
+        "noun" => noun(&mut out, val),
+        _ => return Err(format!("no JSON conversion for mark %{}", mark)),
+    }
+    Ok(out)
+}
+
+/// Answer a connection's reads in turn until it closes.
+fn serve(stream: UnixStream, injector: Injector) -> io::Result<()> {
+    let mut inp = BufReader::new(stream.try_clone()?);
+    let mut out = stream;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if 0 == inp.read_line(&mut line)? {
+            return Ok(());
+        }
+        let res = parse(&line).and_then(|(format, path)| {
+            let res = injector.peek(path)?;
+            match (format, res) {
+                (_, None) => Ok(None),
+                (_, Some(None)) => Ok(Some(None)),
+                (Format::Jam, Some(Some(res))) => Ok(Some(Some(res.jam()))),
+                (Format::Json, Some(Some(res))) => Ok(Some(Some(to_json(&res)?.into_bytes()))),
+            }
+        });
+        match res {
+            Ok(Some(Some(bytes))) => {
+                writeln!(out, "ok {}", bytes.len())?;
+                out.write_all(&bytes)?;
+            }
+            Ok(Some(None)) => writeln!(out, "none")?,
+            Ok(None) => writeln!(out, "blocked")?,
+            Err(err) => writeln!(out, "error {}", err.replace('\n', " "))?,
+        }
+        out.flush()?;
+    }
+}
+
+/// The scry socket, which is removed when it's dropped.
+pub struct Scry {
+    path: PathBuf,
+}
+
+impl Scry {
+    /// Get the path of a pier's scry socket.
+    pub fn path(pier: &Path) -> PathBuf {
+        Log::path(pier).with_file_name("scry.sock")
+    }
+
+    /// Start answering reads on a pier's scry socket, replacing any socket left behind by an
+    /// earlier run. Reads are answered by the king between events.
+    pub fn start(pier: &Path, drivers: &Drivers) -> Result<Self, Error> {
+        let path = Self::path(pier);
+        match fs::remove_file(&path) {
+            Err(err) if ErrorKind::NotFound != err.kind() => return Err(err).at_path(&path),
+            _ => {}
+        }
+        let listener = UnixListener::bind(&path).at_path(&path)?;
+        let injector = drivers.injector();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let injector = injector.clone();
+                        thread::spawn(move || serve(stream, injector));
+                    }
+                    Err(err) => eprintln!("scry: failed to accept a connection: {}", err),
+                }
+            }
+        });
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Scry {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::Ask, noun::cord};
+    use std::{env, io::Read, process};
+
+    fn cage(mark: &str, val: Noun) -> Noun {
+        Noun::from((cord(mark), Noun::from((Noun::from(0), val))))
+    }
+
+    #[test]
+    fn convert() {
+        let obj = Noun::from_tuple(vec![
+            Noun::from((cord("b"), Noun::from((cord("n"), cord("2"))))),
+            Noun::from_tuple(vec![
+                Noun::from((cord("a"), Noun::from((cord("s"), cord("x\"y"))))),
+                Noun::from(0),
+                Noun::from(0),
+            ]),
+            Noun::from(0),
+        ]);
+        let val = Noun::from((
+            cord("a"),
+            Noun::from_list(vec![
+                Noun::from((cord("o"), obj)),
+                Noun::from((cord("b"), Noun::from(1))),
+                Noun::from(0),
+            ]),
+        ));
+        assert_eq!(
+            Ok(r#"[{"b":2,"a":"x\"y"},false,null]"#.to_string()),
+            to_json(&cage("json", val))
+        );
+        assert_eq!(
+            Ok(r#"["a","b"]"#.to_string()),
+            to_json(&cage("txt", Noun::from_list(vec![cord("a"), cord("b")])))
+        );
+        let big = Atom::from_limbs(vec![0, 1]);
+        assert_eq!(
+            Ok("18446744073709551616".to_string()),
+            to_json(&cage("ud", Noun::from(big)))
+        );
+        assert_eq!(
+            Ok("[1,[2,0]]".to_string()),
+            to_json(&cage(
+                "noun",
+                Noun::from((Noun::from(1), Noun::from((Noun::from(2), Noun::from(0)))))
+            ))
+        );
+        assert!(to_json(&cage("png", Noun::from(0))).is_err());
+        assert!(to_json(&Noun::from(0)).is_err());
+    }
+
+    #[test]
+    fn socket() {
+        let pier = env::temp_dir().join(format!("vere-scry-{}", process::id()));
+        fs::create_dir_all(Log::path(&pier).parent().unwrap()).unwrap();
+        let mut drivers = Drivers::new();
+        let scry = Scry::start(&pier, &drivers).unwrap();
+        let marker = drivers.injector();
+        let path = Scry::path(&pier);
+        let client = thread::spawn(move || {
+            let mut stream = UnixStream::connect(path).unwrap();
+            write!(
+                stream,
+                "jam /cx/~zod/base/1/sys/kelvin\njson /cx/~zod/base/1/sys/kelvin\n\
+                 json /gx/~zod/base/1/blocked\nxml /cx/~zod/base/1\njson /cx/~zod\n"
+            )
+            .unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut res = Vec::new();
+            stream.read_to_end(&mut res).unwrap();
+            marker.inject(Noun::from(0));
+            res
+        });
+
+        // The king answers each read between events.
+        let answer = cage("ud", Noun::from(409));
+        let mut paths = Vec::new();
+        let served = drivers.wait_serving(&mut |ask| match ask {
+            Ask::Peek { path, reply } => {
+                let blocked = path.clone().into_list().unwrap().contains(&cord("blocked"));
+                paths.push(path);
+                let _ = reply.send(Ok(if blocked {
+                    None
+                } else {
+                    Some(Some(answer.clone()))
+                }));
+            }
+            _ => unreachable!(),
+        });
+        assert_eq!(Some(Noun::from(0)), served);
+        let kelvin = Noun::from_list(
+            ["cx", "~zod", "base", "1", "sys", "kelvin"]
+                .iter()
+                .map(|segment| cord(segment))
+                .collect(),
+        );
+        assert_eq!(kelvin, paths[0]);
+        assert_eq!(3, paths.len());
+
+        let res = client.join().unwrap();
+        let jam = answer.jam();
+        let mut expected = format!("ok {}\n", jam.len()).into_bytes();
+        expected.extend(&jam);
+        expected.extend(b"ok 3\n409blocked\n");
+        let text = String::from_utf8_lossy(&res).into_owned();
+        assert!(res.starts_with(&expected), "{}", text);
+        assert!(text.ends_with(
+            "error unknown format xml\nerror expected /<care>/<ship>/<desk>/<case>/<path>\n"
+        ));
+
+        drop(scry);
+        assert!(!Scry::path(&pier).exists());
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
@@ -0,0 +1,707 @@
+//! The serf, which is the worker process that owns a pier's kernel and loom, and the protocol
+//! the king, i.e. the process that runs the pier's I/O, uses to drive it.
+//!
+//! The king and the serf exchange nouns in [`newt`](crate::newt) frames over the serf's stdin
+//! and stdout. The king sends [`Writ`]s and the serf answers each with a [`Plea`], except for
+//! `[%live %exit code]`, after which the serf exits. The serf announces itself with
+//! `[%ripe pro eve mug]` before reading anything.
+
+use crate::{
+    error::Error,
+    event_log::epoch::Epoch,
+    kernel::Kernel,
+    newt,
+    noun::{tagged, text},
+    snapshot::Snapshot,
+};
+use loom::{
+    mark::{Mark, Root},
+    mass::Mass,
+    Stats,
+};
+use nock::{atom::Atom, noun::Noun};
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Version of the protocol, which the serf reports in `%ripe`.
+pub const PROTOCOL: u64 = 1;
+
+/// An event for the serf to apply: `[date ovum]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub date: Atom,
+    pub ovum: Noun,
+}
+
+/// A maintenance request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Live {
+    /// `[%live %save eve]`: save a snapshot of the kernel as of event `eve`.
+    Save(u64),
+    /// `[%live %pack ~]`: compact the kernel's loom allocations.
+    Pack,
+    /// `[%live %exit code]`: exit with `code` without answering.
+    Exit(u64),
+    /// `[%live %mass ~]`: report the loom's usage.
+    Mass,
+}
+
+/// A request from the king to the serf.
+///
+/// The C serf's `%peek` and `%work` carry a timeout in milliseconds, which this serf doesn't
+/// enforce; it's sent as 0 and ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Writ {
+    Live(Live),
+    /// `[%peek mil now path]`: read `path` from the kernel as of `now`.
+    Peek {
+        now: Atom,
+        path: Noun,
+    },
+    /// `[%play eve jobs]`: replay logged events starting at event `eve`. A serf without a
+    /// kernel computes it from its first `%play`, which must start at event 1 and consist of
+    /// exactly the lifecycle events.
+    Play {
+        eve: u64,
+        jobs: Vec<Job>,
+    },
+    /// `[%work mil job]`: apply a new event.
+    Work(Job),
+}
+
+/// A response from the serf to the king. The reason for a bail is a cord, where the C serf
+/// sends a list of goofs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Plea {
+    /// `[%ripe pro eve mug]`: the serf has started with its kernel as of event `eve`, which is 0
+    /// if it has no kernel yet.
+    Ripe { pro: u64, eve: u64, mug: u32 },
+    /// `[%live ~]`: the maintenance request is done.
+    Live,
+    /// `[%mass roots other live cap]`: the loom's usage, which answers `[%live %mass ~]`. Each
+    /// root is `[name allocs bytes]`, `other` and `live` are `[allocs bytes peak]`, and `cap` is 0
+    /// if unbounded.
+    Mass(Mass),
+    /// `[%peek dat]`: `None` if the path is blocked, `Some(None)` if it's unavailable.
+    Peek(Option<Option<Noun>>),
+    /// `[%play %done mug]`: every event was replayed, leaving the kernel with mug `mug`.
+    Played { mug: u32 },
+    /// `[%play %bail eve mug why]`: replay stopped after event `eve` because the next event
+    /// crashed, leaving the kernel with mug `mug`.
+    PlayBail { eve: u64, mug: u32, why: String },
+    /// `[%work %done eve mug fec]`: the event was applied as event `eve`, leaving the kernel
+    /// with mug `mug` and producing effects `fec`.
+    Done { eve: u64, mug: u32, fec: Noun },
+    /// `[%work %bail why]`: the event crashed and wasn't applied.
+    WorkBail(String),
+}
+
+/// Writ from Noun.
+impl TryFrom<Noun> for Writ {
+    type Error = Error;
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let (tag, rest) = untag(noun)?;
+        let bad = || Error::Ipc(format!("malformed %{} writ", tag));
+        match tag.as_str() {
+            "live" => {
+                let (what, val) = untag(rest)?;
+                match what.as_str() {
+                    "save" => Ok(Writ::Live(Live::Save(num(val).ok_or_else(bad)?))),
+                    "pack" => Ok(Writ::Live(Live::Pack)),
+                    "exit" => Ok(Writ::Live(Live::Exit(num(val).ok_or_else(bad)?))),
+                    "mass" => Ok(Writ::Live(Live::Mass)),
+                    _ => Err(Error::Ipc(format!("unknown %live writ %{}", what))),
+                }
+            }
+            "peek" => {
+                let mut fields = rest.into_tuple(3).map_err(|_| bad())?.into_iter().skip(1);
+                Ok(Writ::Peek {
+                    now: atom(fields.next().unwrap()).ok_or_else(bad)?,
+                    path: fields.next().unwrap(),
+                })
+            }
+            "play" => match rest {
+                Noun::Cell(cell) => Ok(Writ::Play {
+                    eve: num(*cell.head).ok_or_else(bad)?,
+                    jobs: cell
+                        .tail
+                        .into_list()
+                        .map_err(|_| bad())?
+                        .into_iter()
+                        .map(|job| Job::try_from(job).map_err(|_| bad()))
+                        .collect::<Result<_, _>>()?,
+                }),
+                Noun::Atom(_) => Err(bad()),
+            },
+            "work" => match rest {
+                Noun::Cell(cell) => Ok(Writ::Work(Job::try_from(*cell.tail).map_err(|_| bad())?)),
+                Noun::Atom(_) => Err(bad()),
+            },
+            _ => Err(Error::Ipc(format!("unknown writ %{}", tag))),
+        }
+    }
+}
+
+/// Noun from Writ.
+impl From<Writ> for Noun {
+    fn from(writ: Writ) -> Self {
+        match writ {
+            Writ::Live(Live::Save(eve)) => tagged("live", tagged("save", Noun::from(eve))),
+            Writ::Live(Live::Pack) => tagged("live", tagged("pack", Noun::from(0))),
+            Writ::Live(Live::Exit(code)) => tagged("live", tagged("exit", Noun::from(code))),
+            Writ::Live(Live::Mass) => tagged("live", tagged("mass", Noun::from(0))),
+            Writ::Peek { now, path } => tagged(
+                "peek",
+                Noun::from_tuple(vec![Noun::from(0), Noun::from(now), path]),
+            ),
+            Writ::Play { eve, jobs } => tagged(
+                "play",
+                Noun::from((
+                    Noun::from(eve),
+                    Noun::from_list(jobs.into_iter().map(Noun::from).collect()),
+                )),
+            ),
+            Writ::Work(job) => tagged("work", Noun::from((Noun::from(0), Noun::from(job)))),
+        }
+    }
+}
+
+/// Plea from Noun.
+impl TryFrom<Noun> for Plea {
+    type Error = Error;
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        let (tag, rest) = untag(noun)?;
+        let bad = || Error::Ipc(format!("malformed %{} plea", tag));
+        let fields = |rest: Noun, n| rest.into_tuple(n).map_err(|_| bad());
+        match tag.as_str() {
+            "ripe" => {
+                let mut fields = fields(rest, 3)?.into_iter();
+                Ok(Plea::Ripe {
+                    pro: num(fields.next().unwrap()).ok_or_else(bad)?,
+                    eve: num(fields.next().unwrap()).ok_or_else(bad)?,
+                    mug: mug(fields.next().unwrap()).ok_or_else(bad)?,
+                })
+            }
+            "live" => Ok(Plea::Live),
+            "mass" => {
+                let mut fields = fields(rest, 4)?.into_iter();
+                let roots = fields
+                    .next()
+                    .unwrap()
+                    .into_list()
+                    .map_err(|_| bad())?
+                    .into_iter()
+                    .map(|root| {
+                        let mut fields = root.into_tuple(3).ok()?.into_iter();
+                        Some(Root {
+                            name: text(&fields.next()?)?,
+                            allocs: num(fields.next()?)? as usize,
+                            bytes: num(fields.next()?)? as usize,
+                        })
+                    })
+                    .collect::<Option<_>>()
+                    .ok_or_else(bad)?;
+                Ok(Plea::Mass(Mass {
+                    roots,
+                    other: stats(fields.next().unwrap()).ok_or_else(bad)?,
+                    live: stats(fields.next().unwrap()).ok_or_else(bad)?,
+                    cap: match num(fields.next().unwrap()).ok_or_else(bad)? {
+                        0 => None,
+                        cap => Some(cap as usize),
+                    },
+                }))
+            }
+            "peek" => {
+                let unit = |noun: Noun| match noun {
+                    Noun::Atom(Atom::Direct(0)) => Ok(None),
+                    Noun::Cell(cell) if Noun::from(0) == *cell.head => Ok(Some(*cell.tail)),
+                    _ => Err(bad()),
+                };
+                Ok(Plea::Peek(unit(rest)?.map(unit).transpose()?))
+            }
+            "play" => {
+                let (what, rest) = untag(rest)?;
+                match what.as_str() {
+                    "done" => Ok(Plea::Played {
+                        mug: mug(rest).ok_or_else(bad)?,
+                    }),
+                    "bail" => {
+                        let mut fields = fields(rest, 3)?.into_iter();
+                        Ok(Plea::PlayBail {
+                            eve: num(fields.next().unwrap()).ok_or_else(bad)?,
+                            mug: mug(fields.next().unwrap()).ok_or_else(bad)?,
+                            why: text(&fields.next().unwrap()).ok_or_else(bad)?,
+                        })
+                    }
+                    _ => Err(bad()),
+                }
+            }
+            "work" => {
+                let (what, rest) = untag(rest)?;
+                match what.as_str() {
+                    "done" => {
+                        let mut fields = fields(rest, 3)?.into_iter();
+                        Ok(Plea::Done {
+                            eve: num(fields.next().unwrap()).ok_or_else(bad)?,
+                            mug: mug(fields.next().unwrap()).ok_or_else(bad)?,
+                            fec: fields.next().unwrap(),
+                        })
+                    }
+                    "bail" => Ok(Plea::WorkBail(text(&rest).ok_or_else(bad)?)),
+                    _ => Err(bad()),
+                }
+            }
+            _ => Err(Error::Ipc(format!("unknown plea %{}", tag))),
+        }
+    }
+}
+
+/// Noun from Plea.
+impl From<Plea> for Noun {
+    fn from(plea: Plea) -> Self {
+        let unit = |noun: Option<Noun>| match noun {
+            Some(noun) => Noun::from((Noun::from(0), noun)),
+            None => Noun::from(0),
+        };
+        match plea {
+            Plea::Ripe { pro, eve, mug } => tagged(
+                "ripe",
+                Noun::from_tuple(vec![
+                    Noun::from(pro),
+                    Noun::from(eve),
+                    Noun::from(u64::from(mug)),
+                ]),
+            ),
+            Plea::Live => tagged("live", Noun::from(0)),
+            Plea::Mass(mass) => {
+                let roots = mass
+                    .roots
+                    .into_iter()
+                    .map(|root| {
+                        Noun::from_tuple(vec![
+                            Noun::from(Atom::from(root.name.as_str())),
+                            Noun::from(root.allocs as u64),
+                            Noun::from(root.bytes as u64),
+                        ])
+                    })
+                    .collect();
+                tagged(
+                    "mass",
+                    Noun::from_tuple(vec![
+                        Noun::from_list(roots),
+                        from_stats(mass.other),
+                        from_stats(mass.live),
+                        Noun::from(mass.cap.unwrap_or(0) as u64),
+                    ]),
+                )
+            }
+            Plea::Peek(dat) => tagged("peek", unit(dat.map(unit))),
+            Plea::Played { mug } => tagged("play", tagged("done", Noun::from(u64::from(mug)))),
+            Plea::PlayBail { eve, mug, why } => tagged(
+                "play",
+                tagged(
+                    "bail",
+                    Noun::from_tuple(vec![
+                        Noun::from(eve),
+                        Noun::from(u64::from(mug)),
+                        Noun::from(Atom::from(why.as_str())),
+                    ]),
+                ),
+            ),
+            Plea::Done { eve, mug, fec } => tagged(
+                "work",
+                tagged(
+                    "done",
+                    Noun::from_tuple(vec![Noun::from(eve), Noun::from(u64::from(mug)), fec]),
+                ),
+            ),
+            Plea::WorkBail(why) => {
+                tagged("work", tagged("bail", Noun::from(Atom::from(why.as_str()))))
+            }
+        }
+    }
+}
+
+/// Job from Noun.
+impl TryFrom<Noun> for Job {
+    type Error = Noun;
+
+    fn try_from(noun: Noun) -> Result<Self, Self::Error> {
+        match noun {
+            Noun::Cell(cell) => match *cell.head {
+                Noun::Atom(date) => Ok(Self {
+                    date,
+                    ovum: *cell.tail,
+                }),
+                head => Err(Noun::from((head, *cell.tail))),
+            },
+            atom => Err(atom),
+        }
+    }
+}
+
+/// Noun from Job.
+impl From<Job> for Noun {
+    fn from(job: Job) -> Self {
+        Noun::from((Noun::from(job.date), job.ovum))
+    }
+}
+
+/// The serf's state: a pier's kernel as of its most recent event.
+pub struct Serf {
+    pier: PathBuf,
+    kernel: Option<Kernel>,
+    evt_num: u64,
+}
+
+impl Serf {
+    /// Start a serf for a pier from its most recent snapshot: the pier's snapshot, or else the
+    /// snapshot its latest epoch starts from. A serf for a pier without a snapshot has no kernel
+    /// until it's sent the lifecycle events.
+    pub fn load(pier: &Path) -> Result<Self, Error> {
+        let mut snap = Snapshot::latest(pier)?;
+        if snap.is_none() {
+            for epoch in Epoch::list(pier)?.iter().rev() {
+                snap = epoch.snapshot()?;
+                if snap.is_some() {
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            pier: pier.to_path_buf(),
+            evt_num: snap.as_ref().map_or(0, |snap| snap.evt_num),
+            kernel: snap.map(|snap| snap.kernel),
+        })
+    }
+
+    /// Announce the serf's state.
+    pub fn ripe(&self) -> Plea {
+        Plea::Ripe {
+            pro: PROTOCOL,
+            eve: self.evt_num,
+            mug: self.mug(),
+        }
+    }
+
+    /// Get the mug of the kernel, or 0 if there's no kernel.
+    fn mug(&self) -> u32 {
+        self.kernel.as_ref().map_or(0, Kernel::mug)
+    }
+
+    /// Get the kernel, which the serf must have to do anything but replay lifecycle events.
+    fn kernel(&self) -> Result<&Kernel, Error> {
+        self.kernel
+            .as_ref()
+            .ok_or_else(|| Error::Ipc("serf has no kernel until it's played events".to_string()))
+    }
+
+    /// Handle a writ, producing the plea that answers it, or nothing if the serf should exit.
+    /// A writ that doesn't make sense in the serf's state is an error, which leaves the state
+    /// unchanged.
+    pub fn handle(&mut self, writ: Writ) -> Result<Option<Plea>, Error> {
+        match writ {
+            Writ::Live(Live::Save(eve)) => {
+                if eve != self.evt_num {
+                    return Err(Error::Ipc(format!(
+                        "can't save event {} when the kernel is at event {}",
+                        eve, self.evt_num
+                    )));
+                }
+                Snapshot {
+                    evt_num: self.evt_num,
+                    kernel: self.kernel()?.clone(),
+                }
+                .save(&self.pier)?;
+                Ok(Some(Plea::Live))
+            }
+            Writ::Live(Live::Pack) => {
+                self.kernel = self.kernel.take().map(Kernel::pack);
+                Ok(Some(Plea::Live))
+            }
+            Writ::Live(Live::Exit(_)) => Ok(None),
+            Writ::Live(Live::Mass) => {
+                let roots: Vec<(&str, &dyn Mark)> = match &self.kernel {
+                    Some(kernel) => vec![("kernel", kernel)],
+                    None => Vec::new(),
+                };
+                Ok(Some(Plea::Mass(Mass::measure(&roots))))
+            }
+            Writ::Peek { now, path } => {
+                let res = self.kernel()?.peek(now, path).unwrap_or(Some(None));
+                Ok(Some(Plea::Peek(res)))
+            }
+            Writ::Play { eve, jobs } => self.play(eve, jobs).map(Some),
+            Writ::Work(Job { date, ovum }) => match self.kernel()?.poke(date, ovum) {
+                Ok((fec, kernel)) => {
+                    self.kernel = Some(kernel);
+                    self.evt_num += 1;
+                    Ok(Some(Plea::Done {
+                        eve: self.evt_num,
+                        mug: self.mug(),
+                        fec,
+                    }))
+                }
+                Err(err) => Ok(Some(Plea::WorkBail(err.to_string()))),
+            },
+        }
+    }
+
+    /// Replay events starting at event `eve`, which must follow the serf's most recent event.
+    fn play(&mut self, eve: u64, jobs: Vec<Job>) -> Result<Plea, Error> {
+        if eve != self.evt_num + 1 {
+            return Err(Error::Ipc(format!(
+                "can't play event {} after event {}",
+                eve, self.evt_num
+            )));
+        }
+        let mut kernel = match self.kernel.take() {
+            Some(kernel) => kernel,
+            None => {
+                let count = jobs.len() as u64;
+                return match Kernel::lifecycle(jobs.into_iter().map(|job| job.ovum).collect()) {
+                    Ok(kernel) => {
+                        self.kernel = Some(kernel);
+                        self.evt_num = count;
+                        Ok(Plea::Played { mug: self.mug() })
+                    }
+                    Err(err) => Ok(Plea::PlayBail {
+                        eve: 0,
+                        mug: 0,
+                        why: err.to_string(),
+                    }),
+                };
+            }
+        };
+        let mut bail = None;
+        for Job { date, ovum } in jobs {
+            match kernel.poke(date, ovum) {
+                Ok((_, next)) => {
+                    kernel = next;
+                    self.evt_num += 1;
+                }
+                Err(err) => {
+                    bail = Some(err.to_string());
+                    break;
+                }
+            }
+        }
+        self.kernel = Some(kernel.relieve());
+        Ok(match bail {
+            Some(why) => Plea::PlayBail {
+                eve: self.evt_num,
+                mug: self.mug(),
+                why,
+            },
+            None => Plea::Played { mug: self.mug() },
+        })
+    }
+
+    /// Announce the serf and then answer writs read from `inp` with pleas written to `out`,
+    /// until the king asks the serf to exit or closes `inp`.
+    pub fn serve<R: Read, W: Write>(mut self, mut inp: R, mut out: W) -> Result<(), Error> {
+        newt::write(&mut out, &Noun::from(self.ripe()))?;
+        while let Some(noun) = newt::read(&mut inp)? {
+            match self.handle(Writ::try_from(noun)?)? {
+                Some(plea) => newt::write(&mut out, &Noun::from(plea))?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split `[%tag noun]` into its tag and noun.
+fn untag(noun: Noun) -> Result<(String, Noun), Error> {
+    match noun {
+        Noun::Cell(cell) => match text(&cell.head) {
+            Some(tag) => Ok((tag, *cell.tail)),
+            None => Err(Error::Ipc("tag isn't a term".to_string())),
+        },
+        Noun::Atom(_) => Err(Error::Ipc("expected a tagged cell".to_string())),
+    }
+}
+
+fn atom(noun: Noun) -> Option<Atom> {
+    match noun {
+        Noun::Atom(atom) => Some(atom),
+        Noun::Cell(_) => None,
+    }
+}
+
+fn num(noun: Noun) -> Option<u64> {
+    match atom(noun)? {
+        Atom::Direct(num) => Some(num),
+        Atom::Indirect(_) => None,
+    }
+}
+
+/// Make loom allocation counters, `[allocs bytes peak]`.
+fn from_stats(stats: Stats) -> Noun {
+    Noun::from_tuple(vec![
+        Noun::from(stats.allocs as u64),
+        Noun::from(stats.bytes as u64),
+        Noun::from(stats.peak as u64),
+    ])
+}
+
+/// Parse loom allocation counters, `[allocs bytes peak]`.
+fn stats(noun: Noun) -> Option<Stats> {
+    let mut fields = noun.into_tuple(3).ok()?.into_iter();
+    Some(Stats {
+        allocs: num(fields.next()?)? as usize,
+        bytes: num(fields.next()?)? as usize,
+        peak: num(fields.next()?)? as usize,
+    })
+}
+
+fn mug(noun: Noun) -> Option<u32> {
+    u32::try_from(num(noun)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pill::Pill, test_util::toy_pill, time};
+    use std::{env, fs, process};
+
+    #[test]
+    fn convert() {
+        let job = Job {
+            date: time::now(),
+            ovum: Noun::from((Noun::from(1), Noun::from(2))),
+        };
+        let writs = vec![
+            Writ::Live(Live::Save(7)),
+            Writ::Live(Live::Pack),
+            Writ::Live(Live::Exit(0)),
+            Writ::Live(Live::Mass),
+            Writ::Peek {
+                now: time::now(),
+                path: Noun::from(3),
+            },
+            Writ::Play {
+                eve: 1,
+                jobs: vec![job.clone(), job.clone()],
+            },
+            Writ::Work(job),
+        ];
+        for writ in writs {
+            assert_eq!(writ, Writ::try_from(Noun::from(writ.clone())).unwrap());
+        }
+        let pleas = vec![
+            Plea::Ripe {
+                pro: PROTOCOL,
+                eve: 4,
+                mug: 0xbeef,
+            },
+            Plea::Live,
+            Plea::Mass(Mass {
+                roots: vec![Root {
+                    name: "kernel".to_string(),
+                    allocs: 8,
+                    bytes: 1 << 20,
+                }],
+                other: Stats {
+                    allocs: 1,
+                    bytes: 64,
+                    peak: 1 << 21,
+                },
+                live: Stats {
+                    allocs: 9,
+                    bytes: (1 << 20) + 64,
+                    peak: 1 << 21,
+                },
+                cap: Some(1 << 31),
+            }),
+            Plea::Peek(None),
+            Plea::Peek(Some(None)),
+            Plea::Peek(Some(Some(Noun::from(0)))),
+            Plea::Played { mug: 0xbeef },
+            Plea::PlayBail {
+                eve: 3,
+                mug: 0xbeef,
+                why: "crash".to_string(),
+            },
+            Plea::Done {
+                eve: 5,
+                mug: 0xbeef,
+                fec: Noun::from(0),
+            },
+            Plea::WorkBail("crash".to_string()),
+        ];
+        for plea in pleas {
+            assert_eq!(plea, Plea::try_from(Noun::from(plea.clone())).unwrap());
+        }
+        assert!(matches!(
+            Writ::try_from(tagged("dance", Noun::from(0))),
+            Err(Error::Ipc(_))
+        ));
+    }
+
+    #[test]
+    fn serve() {
+        let pier = env::temp_dir().join(format!("vere-serf-{}", process::id()));
+        let (boot, _) = Pill::load(&toy_pill(&pier)).unwrap().events();
+        let job = |ovum| Job {
+            date: time::now(),
+            ovum,
+        };
+        let mut inp = Vec::new();
+        let writs = vec![
+            Writ::Play {
+                eve: 1,
+                jobs: boot.into_iter().map(job).collect(),
+            },
+            Writ::Work(job(Noun::from((Noun::from(3), Noun::from(3))))),
+            Writ::Live(Live::Save(3)),
+            Writ::Live(Live::Mass),
+            Writ::Live(Live::Save(2)),
+        ];
+        for writ in writs {
+            newt::write(&mut inp, &Noun::from(writ)).unwrap();
+        }
+        let mut out = Vec::new();
+        let res = Serf::load(&pier).unwrap().serve(&inp[..], &mut out);
+        assert!(matches!(res, Err(Error::Ipc(_))));
+
+        // The serf announced itself and answered each writ until the bad save.
+        let mut out = &out[..];
+        let mut recv = || Plea::try_from(newt::read(&mut out).unwrap().unwrap()).unwrap();
+        assert_eq!(
+            Plea::Ripe {
+                pro: PROTOCOL,
+                eve: 0,
+                mug: 0
+            },
+            recv()
+        );
+        assert!(matches!(recv(), Plea::Played { .. }));
+        let mug = match recv() {
+            Plea::Done { eve: 3, mug, .. } => mug,
+            plea => panic!("unexpected {:?}", plea),
+        };
+        assert_eq!(Plea::Live, recv());
+        match recv() {
+            Plea::Mass(mass) => assert_eq!("kernel", mass.roots[0].name),
+            plea => panic!("unexpected {:?}", plea),
+        }
+        assert_eq!(None, newt::read(&mut out).unwrap());
+
+        // A new serf starts from the saved snapshot.
+        assert_eq!(
+            Plea::Ripe {
+                pro: PROTOCOL,
+                eve: 3,
+                mug
+            },
+            Serf::load(&pier).unwrap().ripe()
+        );
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    error::{Context, Error},
+    kernel::Kernel,
+};
+use nock::{
+    cell::Cell,
+    hash::Mug,
+    noun::Noun,
+    serdes::{Cue, Jam},
+};
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Length of the header preceding the jammed kernel: the event number followed by the kernel's
+/// mug.
+const HEADER_LEN: usize = 12;
+
+/// A kernel as of a given event number.
+pub struct Snapshot {
+    pub evt_num: u64,
+    pub kernel: Kernel,
+}
+
+impl Snapshot {
+    /// Get the path of a pier's snapshot.
+    pub fn path(pier: &Path) -> PathBuf {
+        pier.join(".urb").join("chk").join("snapshot.jam")
+    }
+
+    /// Read the event number and kernel mug recorded by a pier's snapshot without loading its
+    /// kernel, if the pier has a snapshot.
+    pub fn header(pier: &Path) -> Result<Option<(u64, u32)>, Error> {
+        Self::read_header(&Self::path(pier))
+    }
+
+    /// Read the event number and kernel mug recorded by the snapshot at `path`, if it exists.
+    pub fn read_header(path: &Path) -> Result<Option<(u64, u32)>, Error> {
+        let mut header = [0; HEADER_LEN];
+        match File::open(path) {
+            Ok(mut file) => file
+                .read_exact(&mut header)
+                .map_err(|_| Error::BadSnapshot("truncated header".to_string()))
+                .at_path(path)?,
+            Err(err) if ErrorKind::NotFound == err.kind() => return Ok(None),
+            Err(err) => return Err(err).at_path(path),
+        }
+        let mut evt_num = [0; 8];
+        evt_num.copy_from_slice(&header[..8]);
+        let mut mug = [0; 4];
+        mug.copy_from_slice(&header[8..]);
+        Ok(Some((u64::from_le_bytes(evt_num), u32::from_le_bytes(mug))))
+    }
+
+    /// Load a pier's snapshot, checking the kernel against its recorded mug.
+    pub fn load(pier: &Path) -> Result<Self, Error> {
+        Self::read(&Self::path(pier))
+    }
+
+    /// Read the snapshot at `path`, checking the kernel against its recorded mug.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Self::decode(fs::read(path).at_path(path)?).at_path(path)
+    }
+
+    /// Decode a snapshot from its bytes, checking the kernel against its recorded mug.
+    fn decode(bytes: Vec<u8>) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::BadSnapshot("truncated header".to_string()));
+        }
+        let mut evt_num = [0; 8];
+        evt_num.copy_from_slice(&bytes[..8]);
+        let evt_num = u64::from_le_bytes(evt_num);
+        let mut mug = [0; 4];
+        mug.copy_from_slice(&bytes[8..HEADER_LEN]);
+        let mug = u32::from_le_bytes(mug);
+        let kernel = Cell::cue(&bytes[HEADER_LEN..])?;
+        drop(bytes);
+        if kernel.mug() != mug {
+            return Err(Error::SnapshotMismatch {
+                evt_num,
+                expected: mug,
+                found: kernel.mug(),
+            });
+        }
+        Ok(Self {
+            evt_num,
+            kernel: Kernel::try_from(Noun::Cell(kernel)).unwrap(),
+        })
+    }
+
+    /// Load a pier's snapshot if it has one, which is where replay starts.
+    pub fn latest(pier: &Path) -> Result<Option<Self>, Error> {
+        if Self::path(pier).exists() {
+            Self::load(pier).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Save a pier's snapshot, replacing any existing snapshot only once the new one is durable.
+    pub fn save(&self, pier: &Path) -> Result<(), Error> {
+        self.write(&Self::path(pier))
+    }
+
+    /// Write the snapshot to `path`, replacing any existing file only once the snapshot is
+    /// durable.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        self.write_file(path).at_path(path)
+    }
+
+    fn write_file(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        let kernel = self.kernel.as_ref();
+        let mut file = File::create(&tmp)?;
+        file.write_all(&self.evt_num.to_le_bytes())?;
+        file.write_all(&kernel.mug().to_le_bytes())?;
+        file.write_all(&kernel.jam())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn save_load() {
+        let pier = env::temp_dir().join(format!("vere-snapshot-{}", process::id()));
+        let core = Noun::from((Noun::from(42), Noun::from((Noun::from(0), Noun::from(1)))));
+        assert!(Snapshot::latest(&pier).unwrap().is_none());
+
+        // Load what was saved.
+        {
+            let snap = Snapshot {
+                evt_num: 17,
+                kernel: Kernel::try_from(core.clone()).unwrap(),
+            };
+            snap.save(&pier).unwrap();
+            let snap = Snapshot::load(&pier).unwrap();
+            assert_eq!(17, snap.evt_num);
+            assert_eq!(
+                Some((17, snap.kernel.mug())),
+                Snapshot::header(&pier).unwrap()
+            );
+            assert_eq!(core, Noun::from(snap.kernel));
+        }
+
+        // Reject a snapshot whose kernel doesn't match its mug.
+        {
+            let path = Snapshot::path(&pier);
+            let mut bytes = fs::read(&path).unwrap();
+            bytes[8] ^= 1;
+            fs::write(&path, bytes).unwrap();
+            let err = Snapshot::load(&pier).err().unwrap();
+            assert!(matches!(
+                err.root(),
+                Error::SnapshotMismatch { evt_num: 17, .. }
+            ));
+            assert!(err.to_string().starts_with(&path.display().to_string()));
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! Fixtures shared by the crate's unit tests and its integration tests, which build with the
+//! `test-util` feature: a toy kernel, a pill that boots it, and events to poke into a log.
+
+use crate::{
+    event_log::{Event, EvtLog},
+    kernel::Kernel,
+    pill::Pill,
+    time,
+};
+use nock::{atom::Atom, noun::Noun, serdes::Jam};
+use std::{
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// A toy Arvo whose state is the last ovum it was poked with, starting at `state`.
+///
+/// Its `+poke` echoes the ovum as its only effect and its `+peek` produces the state
+/// regardless of path.
+pub fn arvo(state: Noun) -> Noun {
+    let n = Noun::from;
+    let t = Noun::from_tuple;
+    // Produce a gate with a null sample whose context is the kernel: [[1 battery] [1 0] [0 1]]
+    let arm = |battery| {
+        t(vec![
+            t(vec![n(1), battery]),
+            t(vec![n(1), n(0)]),
+            t(vec![n(0), n(1)]),
+        ])
+    };
+    // [[[0 13] [1 0]] [0 14] [0 13]]
+    let poke = arm(t(vec![
+        t(vec![t(vec![n(0), n(13)]), t(vec![n(1), n(0)])]),
+        t(vec![n(0), n(14)]),
+        t(vec![n(0), n(13)]),
+    ]));
+    // [[1 0] [1 0] [0 15]]
+    let peek = arm(t(vec![
+        t(vec![n(1), n(0)]),
+        t(vec![n(1), n(0)]),
+        t(vec![n(0), n(15)]),
+    ]));
+    // Place the arms at axes 22 and 47 of the kernel.
+    let battery = t(vec![n(0), n(0), peek, n(0), poke]);
+    Noun::from((battery, state))
+}
+
+/// A pill whose lifecycle conses two zeros onto the kernel that follows its first event.
+pub fn pill(kernel: Noun) -> Pill {
+    // [[1 0] [1 0] [0 2]]
+    let formula = Noun::from_tuple(vec![
+        Noun::from((Noun::from(1), Noun::from(0))),
+        Noun::from((Noun::from(1), Noun::from(0))),
+        Noun::from((Noun::from(0), Noun::from(2))),
+    ]);
+    Pill {
+        name: Atom::from("toy"),
+        boot: vec![formula, kernel],
+        kernel: vec![Noun::from(7)],
+        userspace: vec![Noun::from(8)],
+    }
+}
+
+/// Write the pill of the toy Arvo to a pier, producing its path.
+pub fn toy_pill(pier: &Path) -> PathBuf {
+    let path = pier.join("toy.pill");
+    fs::create_dir_all(pier).unwrap();
+    fs::write(&path, Noun::from(pill(arvo(Noun::from(0)))).jam()).unwrap();
+    path
+}
+
+/// Poke events into a log, producing the resulting kernel.
+pub fn poke<L: EvtLog<Evt = Event>>(
+    log: &mut L,
+    mut kernel: Kernel,
+    nums: RangeInclusive<u64>,
+) -> Kernel {
+    for num in nums {
+        let date = time::now();
+        let ovum = Noun::from((Noun::from(num), Noun::from(num)));
+        kernel = kernel.poke(date.clone(), ovum.clone()).unwrap().1;
+        log.append(Event {
+            num,
+            mug: kernel.mug(),
+            date,
+            ovum,
+        })
+        .unwrap();
+    }
+    log.commit().unwrap();
+    kernel
+}
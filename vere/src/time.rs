@@ -0,0 +1,77 @@
+use nock::atom::Atom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The Unix epoch as the whole seconds of an Urbit date (`@da`).
+const UNIX_EPOCH_SECS: u64 = 0x8000000cce9e0d80;
+
+/// Get the current time as an Urbit date.
+pub fn now() -> Atom {
+    to_date(SystemTime::now())
+}
+
+/// Convert a system time to an Urbit date, whose high 64 bits are whole seconds and whose low
+/// 64 bits are fractions of a second.
+pub fn to_date(time: SystemTime) -> Atom {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let frac = (u128::from(since.subsec_nanos()) << 64) / 1_000_000_000;
+    Atom::from_limbs(vec![frac as u64, UNIX_EPOCH_SECS + since.as_secs()])
+}
+
+/// Convert an Urbit date to a system time, if it's on or after the Unix epoch.
+pub fn from_date(date: &Atom) -> Option<SystemTime> {
+    let limbs = date.limbs();
+    if limbs.len() > 2 {
+        return None;
+    }
+    let frac = limbs.first().copied().unwrap_or(0);
+    let secs = limbs
+        .get(1)
+        .copied()
+        .unwrap_or(0)
+        .checked_sub(UNIX_EPOCH_SECS)?;
+    let nanos = (u128::from(frac) * 1_000_000_000) >> 64;
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos as u32))
+}
+
+/// Check whether an Urbit date is before the Unix epoch.
+pub fn before_epoch(date: &Atom) -> bool {
+    let limbs = date.limbs();
+    limbs.len() <= 2 && limbs.get(1).copied().unwrap_or(0) < UNIX_EPOCH_SECS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date() {
+        // ~1970.1.1
+        {
+            let date = to_date(UNIX_EPOCH);
+            assert_eq!(&[0, UNIX_EPOCH_SECS], date.limbs());
+            assert_eq!(Some(UNIX_EPOCH), from_date(&date));
+        }
+
+        // ~2022.1.1..12.00.00..8000
+        {
+            let time = UNIX_EPOCH + Duration::new(1_641_038_400, 500_000_000);
+            let date = to_date(time);
+            assert_eq!(&[1 << 63, UNIX_EPOCH_SECS + 1_641_038_400], date.limbs());
+            assert_eq!(Some(time), from_date(&date));
+        }
+
+        // Before the Unix epoch.
+        {
+            assert_eq!(None, from_date(&Atom::from(1)));
+            assert!(before_epoch(&Atom::from(1)));
+            assert!(!before_epoch(&to_date(UNIX_EPOCH)));
+        }
+
+        // Too far in the future for a system time.
+        {
+            let date = Atom::from_limbs(vec![0, 0, 1]);
+            assert_eq!(None, from_date(&date));
+            assert!(!before_epoch(&date));
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::{
+    error::Error,
+    event_log::{epoch::Epoch, Event, EvtLog, Log},
+    pier,
+    snapshot::Snapshot,
+};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Number of events read from a log at a time.
+const BATCH: usize = 1000;
+
+/// The outcome of one check of a pier's event log or snapshots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    /// What was checked: `log`, `event`, `epoch`, `snapshot` or `replay`.
+    pub check: &'static str,
+    pub ok: bool,
+    /// File or directory the check was of.
+    pub path: Option<PathBuf>,
+    /// Event the check was of, or the range of events a log holds.
+    pub events: Option<(u64, u64)>,
+    pub detail: String,
+}
+
+impl Finding {
+    fn new(check: &'static str, ok: bool, path: Option<&Path>, detail: String) -> Self {
+        Self {
+            check,
+            ok,
+            path: path.map(Path::to_path_buf),
+            events: None,
+            detail,
+        }
+    }
+
+    fn events(mut self, first: u64, last: u64) -> Self {
+        self.events = Some((first, last));
+        self
+    }
+
+    /// Render the finding as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"check\":{},\"ok\":{}", json_str(self.check), self.ok);
+        if let Some(path) = &self.path {
+            write!(json, ",\"path\":{}", json_str(&path.to_string_lossy())).unwrap();
+        }
+        match self.events {
+            Some((first, last)) if first == last => {
+                write!(json, ",\"event\":{}", first).unwrap();
+            }
+            Some((first, last)) => write!(json, ",\"first\":{},\"last\":{}", first, last).unwrap(),
+            None => {}
+        }
+        if !self.detail.is_empty() {
+            write!(json, ",\"detail\":{}", json_str(&self.detail)).unwrap();
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Quote and escape a string as a JSON string.
+fn json_str(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Totals of a verification pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Number of events read.
+    pub events: u64,
+    /// Number of failed checks.
+    pub failures: u64,
+}
+
+impl Summary {
+    /// Render the summary as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"check\":\"summary\",\"ok\":{},\"events\":{},\"failures\":{}}}",
+            0 == self.failures,
+            self.events,
+            self.failures
+        )
+    }
+}
+
+/// Verify a pier without booting it, reporting each finding as it's made.
+///
+/// Opening each log checks its record framing. Every event is then read back, which checks that
+/// it decodes and is numbered contiguously within and across epochs. Each snapshot, along with
+/// `snap` if given, is checked against its own mug and against the mug recorded for the event
+/// it claims to be at. With `replay`, every event is also replayed and checked against its
+/// recorded mug.
+pub fn verify(
+    pier: &Path,
+    snap: Option<&Path>,
+    replay: bool,
+    report: &mut dyn FnMut(&Finding),
+) -> Result<Summary, Error> {
+    let mut summary = Summary::default();
+    let mut note = |finding: Finding| {
+        if !finding.ok {
+            summary.failures += 1;
+        }
+        report(&finding);
+    };
+
+    // Find the pier's logs, along with the event each epoch claims to start at.
+    let logs_path = Log::path(pier);
+    let epochs = match Epoch::list(pier) {
+        Ok(epochs) => epochs,
+        Err(err) => {
+            note(Finding::new(
+                "epoch",
+                false,
+                Some(&logs_path),
+                describe(err),
+            ));
+            return Ok(summary);
+        }
+    };
+    let dirs: Vec<(PathBuf, Option<u64>)> = if !epochs.is_empty() {
+        epochs
+            .iter()
+            .map(|epoch| (epoch.dir.clone(), Some(epoch.first)))
+            .collect()
+    } else if Log::is_flat(pier) {
+        vec![(logs_path, None)]
+    } else {
+        note(Finding::new(
+            "log",
+            false,
+            Some(&logs_path),
+            "no event log".to_string(),
+        ));
+        return Ok(summary);
+    };
+
+    // Read back every event of every log.
+    let mut logs = Vec::new();
+    let mut prev: Option<u64> = None;
+    for (dir, claimed) in dirs {
+        let log = match Log::new(&dir) {
+            Ok(log) => log,
+            Err(err) => {
+                note(Finding::new("log", false, Some(&dir), describe(err)));
+                prev = None;
+                continue;
+            }
+        };
+        let first = match log.first() {
+            0 => log.last() + 1,
+            first => first,
+        };
+        if let Some(claimed) = claimed.filter(|claimed| *claimed != first) {
+            note(Finding::new(
+                "epoch",
+                false,
+                Some(&dir),
+                format!(
+                    "epoch starts at event {} but its log at event {}",
+                    claimed, first
+                ),
+            ));
+        }
+        if let Some(prev) = prev.filter(|prev| *prev + 1 != first) {
+            note(
+                Finding::new(
+                    "epoch",
+                    false,
+                    Some(&dir),
+                    format!("expected event {} after event {}", prev + 1, prev),
+                )
+                .events(first, first),
+            );
+        }
+        let (count, failures) = scan(&log, &mut note);
+        summary.events += count;
+        let detail = match failures {
+            0 => String::new(),
+            failures => format!("{} unreadable events", failures),
+        };
+        note(Finding::new("log", 0 == failures, Some(&dir), detail).events(first, log.last()));
+        prev = Some(log.last());
+        logs.push(log);
+    }
+
+    // Check every snapshot.
+    let mut snaps: Vec<(PathBuf, Option<u32>)> = epochs
+        .iter()
+        .filter(|epoch| epoch.kernel.is_some())
+        .map(|epoch| (epoch.snapshot_path(), epoch.kernel))
+        .collect();
+    if Snapshot::path(pier).exists() {
+        snaps.push((Snapshot::path(pier), None));
+    }
+    snaps.extend(snap.map(|path| (path.to_path_buf(), None)));
+    for (path, claimed) in snaps {
+        note(check_snapshot(&path, claimed, &logs));
+    }
+
+    // Replay every event.
+    if replay {
+        drop(logs);
+        let finding = match pier::replay(pier, None, &mut |_| {}) {
+            Ok(snap) => {
+                Finding::new("replay", true, None, String::new()).events(snap.evt_num, snap.evt_num)
+            }
+            Err(Error::Diverged(div)) => Finding::new("replay", false, None, div.to_string())
+                .events(div.evt_num, div.evt_num),
+            Err(err) => Finding::new("replay", false, None, describe(err)),
+        };
+        note(finding);
+    }
+
+    Ok(summary)
+}
+
+/// Read back every event of a log, noting each event that can't be read. Produces the number
+/// of events read and the number that couldn't be.
+fn scan(log: &Log, note: &mut dyn FnMut(Finding)) -> (u64, u64) {
+    let path = log.path().to_path_buf();
+    let (mut count, mut failures) = (0, 0);
+    let mut num = log.first().max(1);
+    while 0 != log.first() && num <= log.last() {
+        let evts = match log.read(num, BATCH) {
+            Ok(evts) if evts.is_empty() => break,
+            Ok(evts) => evts,
+            Err(_) => {
+                // Find the bad events in the batch one at a time.
+                let end = log.last().min(num + BATCH as u64 - 1);
+                for num in num..=end {
+                    match log.read(num, 1).map(|mut evts| evts.pop()) {
+                        Ok(Some(evt)) if evt.num == num => count += 1,
+                        res => {
+                            failures += 1;
+                            let detail = match res {
+                                Err(err) => describe(err),
+                                Ok(_) => "missing".to_string(),
+                            };
+                            note(
+                                Finding::new("event", false, Some(&path), detail).events(num, num),
+                            );
+                        }
+                    }
+                }
+                num = end + 1;
+                continue;
+            }
+        };
+        for evt in &evts {
+            if evt.num != num {
+                failures += 1;
+                note(
+                    Finding::new(
+                        "event",
+                        false,
+                        Some(&path),
+                        format!("expected event {} but found event {}", num, evt.num),
+                    )
+                    .events(num, num),
+                );
+            }
+            num = evt.num + 1;
+            count += 1;
+        }
+    }
+    (count, failures)
+}
+
+/// Check a snapshot against its own mug, the mug claimed for it by its epoch and the mug
+/// recorded for the event it's at.
+fn check_snapshot(path: &Path, claimed: Option<u32>, logs: &[Log]) -> Finding {
+    let snap = match Snapshot::read(path) {
+        Ok(snap) => snap,
+        Err(err) => return Finding::new("snapshot", false, Some(path), describe(err)),
+    };
+    let mug = snap.kernel.mug();
+    let evt_num = snap.evt_num;
+    let finding =
+        |ok, detail| Finding::new("snapshot", ok, Some(path), detail).events(evt_num, evt_num);
+    if let Some(claimed) = claimed.filter(|claimed| *claimed != mug) {
+        return finding(
+            false,
+            format!("kernel mug {:#x} but its epoch claims {:#x}", mug, claimed),
+        );
+    }
+    let recorded = logs
+        .iter()
+        .filter_map(|log| log.read(evt_num, 1).ok()?.pop())
+        .find(|evt: &Event| evt.num == evt_num);
+    match recorded {
+        Some(evt) if 0 == evt.mug => finding(true, "no mug recorded for its event".to_string()),
+        Some(evt) if mug == evt.mug => finding(true, String::new()),
+        Some(evt) => finding(
+            false,
+            format!(
+                "kernel mug {:#x} but its event recorded {:#x}",
+                mug, evt.mug
+            ),
+        ),
+        // A chopped log or pruned epoch starts from the snapshot, whose event it no longer has.
+        None if logs.iter().any(|log| log.first() == evt_num + 1) => {
+            finding(true, "its event precedes the log".to_string())
+        }
+        None => finding(false, "its event isn't in the log".to_string()),
+    }
+}
+
+/// Describe an error for a finding.
+fn describe(err: Error) -> String {
+    match err {
+        Error::BadEventLog(msg) | Error::BadSnapshot(msg) => msg,
+        err => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event_log::file::FileLog,
+        kernel::Kernel,
+        test_util::{poke, toy_pill},
+    };
+    use std::{env, fs, process};
+
+    fn verify(pier: &Path, snap: Option<&Path>) -> (Summary, Vec<Finding>) {
+        let mut findings = Vec::new();
+        let summary = super::verify(pier, snap, true, &mut |f| findings.push(f.clone())).unwrap();
+        (summary, findings)
+    }
+
+    #[test]
+    fn json() {
+        let finding = Finding::new(
+            "event",
+            false,
+            Some(Path::new("a\"b")),
+            "bad\n\u{1}".to_string(),
+        )
+        .events(3, 3);
+        assert_eq!(
+            r#"{"check":"event","ok":false,"path":"a\"b","event":3,"detail":"bad\n\u0001"}"#,
+            finding.to_json()
+        );
+        assert_eq!(
+            r#"{"check":"summary","ok":true,"events":2,"failures":0}"#,
+            Summary {
+                events: 2,
+                failures: 0
+            }
+            .to_json()
+        );
+    }
+
+    #[test]
+    fn pier() {
+        let pier = env::temp_dir().join(format!("vere-verify-{}", process::id()));
+        assert_eq!(1, verify(&pier, None).0.failures);
+        let path = toy_pill(&pier);
+        let mut log = FileLog::new(&Log::path(&pier)).unwrap();
+        let (kernel, _) = Kernel::new(&path, &mut log).unwrap();
+        let third = poke(&mut log, kernel, 3..=3);
+        poke(&mut log, third.clone(), 4..=5);
+        drop(log);
+        Snapshot {
+            evt_num: 3,
+            kernel: third.clone(),
+        }
+        .save(&pier)
+        .unwrap();
+
+        // A sound pier passes every check.
+        {
+            let (summary, findings) = verify(&pier, None);
+            assert_eq!(
+                Summary {
+                    events: 5,
+                    failures: 0
+                },
+                summary
+            );
+            let checks: Vec<_> = findings.iter().map(|f| f.check).collect();
+            assert_eq!(vec!["log", "snapshot", "replay"], checks);
+            assert_eq!(Some((1, 5)), findings[0].events);
+        }
+
+        // A snapshot that claims the wrong event.
+        {
+            let bad = pier.join("bad.jam");
+            Snapshot {
+                evt_num: 4,
+                kernel: third.clone(),
+            }
+            .write(&bad)
+            .unwrap();
+            let (summary, findings) = verify(&pier, Some(&bad));
+            assert_eq!(1, summary.failures);
+            assert!(findings.iter().any(|f| !f.ok && "snapshot" == f.check));
+        }
+
+        // A snapshot of an event the log doesn't have.
+        {
+            let ahead = pier.join("ahead.jam");
+            Snapshot {
+                evt_num: 9,
+                kernel: third,
+            }
+            .write(&ahead)
+            .unwrap();
+            let (summary, findings) = verify(&pier, Some(&ahead));
+            assert_eq!(1, summary.failures);
+            assert!(findings
+                .iter()
+                .any(|f| !f.ok && f.detail == "its event isn't in the log"));
+        }
+
+        // A corrupt record.
+        {
+            let events = Log::path(&pier).join("events");
+            let mut bytes = fs::read(&events).unwrap();
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0x80;
+            fs::write(&events, bytes).unwrap();
+            let (_, findings) = verify(&pier, None);
+            assert!(!findings[0].ok);
+            assert!(findings[0].detail.contains("corrupt event 5"));
+        }
+
+        fs::remove_dir_all(&pier).unwrap();
+    }
+}
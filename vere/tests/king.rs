@@ -0,0 +1,49 @@
+//! Tests of the king driving a serf in its own process, which need the `serf` executable.
+
+use nock::{atom::Atom, noun::Noun};
+use std::{env, fs, path::Path, process};
+use vere::{
+    config::Config,
+    event_log::{EvtLog, Log},
+    king::King,
+    pier::{Identity, Pier},
+    test_util::toy_pill,
+};
+
+/// Create a fake ~zod from the toy pill.
+fn create(pier: &Path) {
+    let ident = Identity {
+        who: Atom::from(0u64),
+        fake: true,
+    };
+    Pier::create(pier, &toy_pill(pier), &ident, &Config::default()).unwrap();
+}
+
+#[test]
+fn restart() {
+    let pier = env::temp_dir().join(format!("vere-king-restart-{}", process::id()));
+    create(&pier);
+    let serf = Path::new(env!("CARGO_BIN_EXE_serf"));
+    let mut king = King::boot(&pier, serf, None, &mut |_| {}).unwrap();
+    let ovum = |num: u64| Noun::from((Noun::from(num), Noun::from(num)));
+    king.poke(ovum(5)).unwrap();
+    let (evt_num, mug) = (king.evt_num(), king.mug());
+
+    // The effects of a poke are only produced once its event is durable.
+    assert_eq!(evt_num, Log::open(&pier).unwrap().last());
+
+    // A poke to a serf that's been killed fails, and the serf is restarted and replayed to
+    // the same event and kernel.
+    let dead = king.serf_id();
+    assert_eq!(0, unsafe { libc::kill(dead as i32, libc::SIGKILL) });
+    assert!(king.poke(ovum(6)).is_err());
+    assert_ne!(dead, king.serf_id());
+    assert_eq!((evt_num, mug), (king.evt_num(), king.mug()));
+
+    // The new serf carries on from there.
+    assert_eq!(Ok(vec![ovum(6)]), king.poke(ovum(6)).unwrap().into_list());
+    assert_eq!(evt_num + 1, king.evt_num());
+    drop(king);
+
+    fs::remove_dir_all(&pier).unwrap();
+}